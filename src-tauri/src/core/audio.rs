@@ -203,6 +203,13 @@ mod test {
 
     #[test]
     fn test_thread_msg() {
+        //kept until the end of the test, the library database lives in it
+        let dir = tempfile::tempdir().unwrap();
+        crate::profiles::init(crate::profiles::LibraryLocation {
+            data_dir: dir.path().to_path_buf(),
+            profile: crate::profiles::DEFAULT_PROFILE.into(),
+        });
+
         let app = tauri::test::mock_app();
         app.manage(Mutex::new(MusicLibrary::new()));
 
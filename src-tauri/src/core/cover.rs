@@ -0,0 +1,228 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::song::{CoverSource, Image};
+
+//file names (without extension) checked for album art next to the music, earlier names win
+pub const DEFAULT_COVER_NAMES: &[&str] = &["cover", "folder", "front", "album", "albumart"];
+
+//image types we pick up as sidecar covers, earlier extensions win when a name exists more than once
+const COVER_EXTENSIONS: &[(&str, &str)] = &[
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("webp", "image/webp"),
+];
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverPreference {
+    //use the art in the tags and only fall back to an image in the folder
+    #[default]
+    Embedded,
+    //use an image in the folder and only fall back to the art in the tags
+    Sidecar,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CoverSettings {
+    pub names: Vec<String>,
+    pub prefer: CoverPreference,
+}
+
+impl Default for CoverSettings {
+    fn default() -> Self {
+        CoverSettings {
+            names: DEFAULT_COVER_NAMES.iter().map(|n| n.to_string()).collect(),
+            prefer: CoverPreference::default(),
+        }
+    }
+}
+
+//the cover picked for a file, sidecar images are only read once an album actually needs them
+//so a folder of twenty tracks doesnt load the same image twenty times
+#[derive(Debug, Clone)]
+pub enum CoverCandidate {
+    Embedded(Image),
+    Sidecar(PathBuf),
+}
+
+impl CoverCandidate {
+    pub fn choose(embedded: Option<Image>, dir: Option<&Path>, settings: &CoverSettings, sidecars: &SidecarCache) -> Option<Self> {
+        let sidecar = || dir.and_then(|d| sidecars.find(d, &settings.names)).map(CoverCandidate::Sidecar);
+
+        match settings.prefer {
+            CoverPreference::Embedded => embedded.map(CoverCandidate::Embedded).or_else(sidecar),
+            CoverPreference::Sidecar => sidecar().or(embedded.map(CoverCandidate::Embedded)),
+        }
+    }
+
+    //whether this should replace the cover an album already has, an album without one takes anything
+    //otherwise only the preferred kind replaces the other one, so every track doesnt reload the same image
+    pub fn outranks(&self, current: Option<&CoverSource>, prefer: CoverPreference) -> bool {
+        let fallback = |sidecar: bool| sidecar != (prefer == CoverPreference::Sidecar);
+
+        match current {
+            None => true,
+            Some(source) => !fallback(matches!(self, CoverCandidate::Sidecar(_))) && fallback(matches!(source, CoverSource::Sidecar(_))),
+        }
+    }
+
+    pub fn load(self) -> Option<(Image, CoverSource)> {
+        match self {
+            CoverCandidate::Embedded(image) => Some((image, CoverSource::Embedded)),
+            CoverCandidate::Sidecar(path) => {
+                let data = match fs::read(&path) {
+                    Ok(d) => d,
+                    Err(e) => {
+                        println!("failed to read cover {}: {e}", path.display());
+                        return None;
+                    }
+                };
+
+                let image = Image {
+                    data,
+                    extension: mime_for(&path)?.to_string(),
+                };
+
+                Some((image, CoverSource::Sidecar(path)))
+            }
+        }
+    }
+}
+
+//the sidecar cover of each folder a scan has looked in, so a folder is only listed once
+//instead of once per track. one per scan so images added in between are picked up
+#[derive(Default)]
+pub struct SidecarCache {
+    found: Mutex<HashMap<PathBuf, Option<PathBuf>>>,
+}
+
+impl SidecarCache {
+    pub fn find(&self, dir: &Path, names: &[String]) -> Option<PathBuf> {
+        if let Some(found) = self.found.lock().unwrap().get(dir) {
+            return found.clone();
+        }
+
+        //listed without the lock held so workers in other folders dont wait on this one
+        let found = find_sidecar_cover(dir, names);
+        self.found.lock().unwrap().insert(dir.to_path_buf(), found.clone());
+        found
+    }
+}
+
+fn mime_for(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+
+    COVER_EXTENSIONS
+        .iter()
+        .find(|(e, _)| *e == ext)
+        .map(|(_, mime)| *mime)
+}
+
+//looks for the highest priority cover image directly inside dir, names are matched case insensitively
+pub fn find_sidecar_cover(dir: &Path, names: &[String]) -> Option<PathBuf> {
+    let entries = fs::read_dir(dir).ok()?;
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let stem = path.file_stem()?.to_string_lossy().to_lowercase();
+            let ext = path.extension()?.to_string_lossy().to_lowercase();
+
+            let name_rank = names.iter().position(|n| n.to_lowercase() == stem)?;
+            let ext_rank = COVER_EXTENSIONS.iter().position(|(e, _)| *e == ext)?;
+
+            Some(((name_rank, ext_rank), path))
+        })
+        .min_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, path)| path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_cover_priority() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = CoverSettings::default().names;
+
+        assert_eq!(find_sidecar_cover(dir.path(), &names), None);
+
+        for file in ["back.jpg", "Folder.PNG", "notes.txt"] {
+            fs::write(dir.path().join(file), b"").unwrap();
+        }
+        assert_eq!(find_sidecar_cover(dir.path(), &names), Some(dir.path().join("Folder.PNG")));
+
+        fs::write(dir.path().join("cover.webp"), b"").unwrap();
+        fs::write(dir.path().join("cover.jpg"), b"").unwrap();
+        assert_eq!(find_sidecar_cover(dir.path(), &names), Some(dir.path().join("cover.jpg")));
+    }
+
+    #[test]
+    fn test_sidecar_cache_lists_each_folder_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = CoverSettings::default().names;
+        let cache = SidecarCache::default();
+
+        assert_eq!(cache.find(dir.path(), &names), None);
+
+        //still the answer from the first look, the folder isnt listed again
+        fs::write(dir.path().join("cover.jpg"), b"").unwrap();
+        assert_eq!(cache.find(dir.path(), &names), None);
+        assert_eq!(SidecarCache::default().find(dir.path(), &names), Some(dir.path().join("cover.jpg")));
+    }
+
+    #[test]
+    fn test_cover_preference() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("front.png"), b"png").unwrap();
+
+        let embedded = Image {
+            data: vec![1, 2, 3],
+            extension: "image/jpeg".into(),
+        };
+
+        let mut settings = CoverSettings::default();
+        let (image, source) = CoverCandidate::choose(Some(embedded.clone()), Some(dir.path()), &settings, &SidecarCache::default())
+            .and_then(CoverCandidate::load)
+            .unwrap();
+        assert_eq!((image, source), (embedded.clone(), CoverSource::Embedded));
+
+        settings.prefer = CoverPreference::Sidecar;
+        let (image, source) = CoverCandidate::choose(Some(embedded.clone()), Some(dir.path()), &settings, &SidecarCache::default())
+            .and_then(CoverCandidate::load)
+            .unwrap();
+        assert_eq!(image.extension, "image/png");
+        assert_eq!(source, CoverSource::Sidecar(dir.path().join("front.png")));
+
+        //falls back to whatever there is
+        let (_, source) = CoverCandidate::choose(Some(embedded), None, &settings, &SidecarCache::default())
+            .and_then(CoverCandidate::load)
+            .unwrap();
+        assert_eq!(source, CoverSource::Embedded);
+    }
+
+    #[test]
+    fn test_only_the_preferred_kind_outranks_a_cover() {
+        let embedded = CoverCandidate::Embedded(Image { data: vec![1], extension: "image/jpeg".into() });
+        let sidecar = CoverCandidate::Sidecar(PathBuf::from("/music/cover.jpg"));
+        let old_sidecar = CoverSource::Sidecar(PathBuf::from("/music/folder.jpg"));
+
+        assert!(sidecar.outranks(None, CoverPreference::Embedded));
+        assert!(embedded.outranks(Some(&old_sidecar), CoverPreference::Embedded));
+        assert!(!sidecar.outranks(Some(&CoverSource::Embedded), CoverPreference::Embedded));
+        assert!(!sidecar.outranks(Some(&old_sidecar), CoverPreference::Sidecar));
+        assert!(sidecar.outranks(Some(&CoverSource::Embedded), CoverPreference::Sidecar));
+    }
+}
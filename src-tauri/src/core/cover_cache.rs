@@ -0,0 +1,247 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use image::codecs::jpeg::JpegEncoder;
+
+use crate::core::palette::Palette;
+use crate::core::song::Image;
+
+//longest edge of the thumbnails made for every cover, the frontend asks for whichever fits what its drawing
+pub const THUMBNAIL_SIZES: [u32; 3] = [64, 256, 512];
+
+const THUMBNAIL_QUALITY: u8 = 85;
+
+//file extensions the originals are stored under, so they can be served with the right type later
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("jpg", "image/jpeg"),
+    ("png", "image/png"),
+    ("webp", "image/webp"),
+    ("gif", "image/gif"),
+    ("bmp", "image/bmp"),
+    ("tiff", "image/tiff"),
+    ("bin", "application/octet-stream"),
+];
+
+//covers stored on disk by the hash of their contents, an album in twenty folders or a
+//compilation with the same art in every track only ever takes up one original and its thumbnails
+//layout is <dir>/<first two hash chars>/<hash>.<ext> with <hash>_<size>.jpg next to it
+#[derive(Debug, Clone)]
+pub struct CoverCache {
+    dir: PathBuf,
+}
+
+impl CoverCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        CoverCache {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    //writes the cover and its thumbnails unless the same image is already cached, returns its hash
+    pub fn store(&self, image: &Image) -> io::Result<String> {
+        let hash = blake3::hash(&image.data).to_hex().to_string();
+
+        if self.original_path(&hash).is_some() {
+            return Ok(hash);
+        }
+
+        let shard = self.shard_dir(&hash);
+        fs::create_dir_all(&shard)?;
+
+        //thumbnails go first, the original existing is what marks a cover as fully cached
+        self.write_thumbnails(&hash, &image.data)?;
+
+        let original = shard.join(format!("{hash}.{}", extension_for(&image.extension)));
+        write_atomic(&original, &image.data)?;
+
+        Ok(hash)
+    }
+
+    //the smallest thumbnail at least size pixels across, or the original when there isnt one that big
+    //no size means the original
+    pub fn load(&self, hash: &str, size: Option<u32>) -> Option<Image> {
+        if !is_valid_hash(hash) {
+            return None;
+        }
+
+        if let Some(size) = size {
+            if let Some(thumb) = THUMBNAIL_SIZES.iter().find(|s| **s >= size) {
+                if let Ok(data) = fs::read(self.thumbnail_path(hash, *thumb)) {
+                    return Some(Image {
+                        data,
+                        extension: "image/jpeg".into(),
+                    });
+                }
+            }
+        }
+
+        let path = self.original_path(hash)?;
+        let data = match fs::read(&path) {
+            Ok(d) => d,
+            Err(e) => {
+                println!("failed to read cached cover {}: {e}", path.display());
+                return None;
+            }
+        };
+
+        Some(Image {
+            data,
+            extension: mime_for(&path).to_string(),
+        })
+    }
+
+    //worked out from the smallest copy we have, the palette doesnt need any more detail than that
+    pub fn palette(&self, hash: &str) -> Result<Palette, String> {
+        let image = self.load(hash, Some(THUMBNAIL_SIZES[0])).ok_or_else(|| format!("cover {hash} isnt in the cache"))?;
+        Palette::from_bytes(&image.data)
+    }
+
+    fn shard_dir(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2])
+    }
+
+    fn thumbnail_path(&self, hash: &str, size: u32) -> PathBuf {
+        self.shard_dir(hash).join(format!("{hash}_{size}.jpg"))
+    }
+
+    fn original_path(&self, hash: &str) -> Option<PathBuf> {
+        let shard = self.shard_dir(hash);
+
+        IMAGE_TYPES
+            .iter()
+            .map(|(ext, _)| shard.join(format!("{hash}.{ext}")))
+            .find(|p| p.is_file())
+    }
+
+    //covers that cant be decoded are still cached, they just only have the original
+    fn write_thumbnails(&self, hash: &str, data: &[u8]) -> io::Result<()> {
+        let decoded = match image::load_from_memory(data) {
+            Ok(i) => i,
+            Err(e) => {
+                println!("failed to decode cover {hash}, not making thumbnails: {e}");
+                return Ok(());
+            }
+        };
+
+        let longest = decoded.width().max(decoded.height());
+
+        for size in THUMBNAIL_SIZES {
+            //no point upscaling, asking for this size gets the original instead
+            if size >= longest {
+                break;
+            }
+
+            let mut thumb = Vec::new();
+            let encoded = decoded
+                .thumbnail(size, size)
+                .to_rgb8()
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut thumb, THUMBNAIL_QUALITY));
+
+            if let Err(e) = encoded {
+                println!("failed to make {size}px thumbnail for cover {hash}: {e}");
+                continue;
+            }
+
+            write_atomic(&self.thumbnail_path(hash, size), &thumb)?;
+        }
+
+        Ok(())
+    }
+}
+
+//hashes come back from the frontend, anything that isnt one could point outside the cache
+fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+//tags give anything from image/jpeg to just jpeg or jpg
+fn extension_for(mime: &str) -> &'static str {
+    let mime = mime.to_lowercase();
+    let name = mime.strip_prefix("image/").unwrap_or(&mime);
+    let name = if name == "jpeg" { "jpg" } else { name };
+
+    IMAGE_TYPES
+        .iter()
+        .find(|(ext, m)| *ext == name || *m == mime)
+        .map(|(ext, _)| *ext)
+        .unwrap_or("bin")
+}
+
+fn mime_for(path: &Path) -> &'static str {
+    let ext = path.extension().map(|e| e.to_string_lossy()).unwrap_or_default();
+
+    IMAGE_TYPES
+        .iter()
+        .find(|(e, _)| *e == ext)
+        .map(|(_, mime)| *mime)
+        .unwrap_or("application/octet-stream")
+}
+
+//a scan killed halfway through a write shouldnt leave a truncated cover that later scans trust
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageFormat};
+    use std::io::Cursor;
+
+    fn png(width: u32, height: u32) -> Image {
+        let mut data = Vec::new();
+        DynamicImage::new_rgb8(width, height)
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+
+        Image {
+            data,
+            extension: "image/png".into(),
+        }
+    }
+
+    #[test]
+    fn test_store_dedups_and_makes_thumbnails() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CoverCache::new(dir.path());
+        let cover = png(300, 150);
+
+        let hash = cache.store(&cover).unwrap();
+        assert_eq!(cache.store(&cover).unwrap(), hash);
+
+        let files = fs::read_dir(cache.shard_dir(&hash)).unwrap().count();
+        assert_eq!(files, 3, "original plus 64 and 256px thumbnails");
+
+        let thumb = cache.load(&hash, Some(100)).unwrap();
+        assert_eq!(thumb.extension, "image/jpeg");
+        let decoded = image::load_from_memory(&thumb.data).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (256, 128));
+
+        //bigger than any thumbnail made for it, so the original comes back
+        assert_eq!(cache.load(&hash, Some(512)).unwrap(), cover);
+        assert_eq!(cache.load(&hash, None).unwrap(), cover);
+    }
+
+    #[test]
+    fn test_undecodable_and_unknown_covers() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CoverCache::new(dir.path());
+
+        let broken = Image {
+            data: b"not really a jpeg".to_vec(),
+            extension: "jpeg".into(),
+        };
+        let hash = cache.store(&broken).unwrap();
+
+        let loaded = cache.load(&hash, Some(64)).unwrap();
+        assert_eq!(loaded.data, broken.data);
+        assert_eq!(loaded.extension, "image/jpeg");
+
+        assert_eq!(cache.load("../../etc/passwd", None), None);
+        assert_eq!(cache.load(&"0".repeat(64), None), None);
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::Mutex;
+
+use tauri::http::{header, Request, Response, StatusCode};
+use uuid::Uuid;
+
+use crate::state::MusicLibrary;
+
+//the frontend loads art with <img src="cover://localhost/<album-id>?size=256">,
+//on windows and android the webview sees the same thing as http://cover.localhost/<album-id>
+pub const COVER_SCHEME: &str = "cover";
+
+//revalidating is a hashmap lookup and a 304, so a cover that changed on a rescan shows up straight away
+const REVALIDATE: &str = "no-cache";
+
+//a url pinned to a cover hash with v= can never point at different bytes
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+struct CoverQuery {
+    size: Option<u32>,
+    //hash the frontend expects, from Album.cover
+    version: Option<String>,
+}
+
+fn parse_query(query: Option<&str>) -> CoverQuery {
+    let mut parsed = CoverQuery {
+        size: None,
+        version: None,
+    };
+
+    for pair in query.unwrap_or("").split('&') {
+        match pair.split_once('=') {
+            Some(("size", value)) => parsed.size = value.parse().ok(),
+            Some(("v", value)) => parsed.version = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    parsed
+}
+
+//answers a single cover:// request from the cover cache, the library is only locked for the album lookup
+pub fn cover_response(library: &Mutex<MusicLibrary>, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let id = request.uri().path().trim_matches('/');
+    let album_id = match Uuid::parse_str(id) {
+        Ok(u) => u,
+        Err(_) => return status_response(StatusCode::BAD_REQUEST),
+    };
+
+    let query = parse_query(request.uri().query());
+
+    let (hash, covers) = {
+        let library = library.lock().unwrap();
+        let hash = library.albums.get(&album_id).and_then(|a| a.cover.clone());
+        (hash, library.covers.clone())
+    };
+
+    let hash = match hash {
+        Some(h) => h,
+        None => return status_response(StatusCode::NOT_FOUND),
+    };
+
+    let etag = match query.size {
+        Some(size) => format!("\"{hash}-{size}\""),
+        None => format!("\"{hash}\""),
+    };
+
+    let cache_control = if query.version.as_deref() == Some(hash.as_str()) {
+        IMMUTABLE
+    } else {
+        REVALIDATE
+    };
+
+    let cached = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v == etag);
+
+    if cached {
+        return build(
+            Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(header::ETAG, &etag)
+                .header(header::CACHE_CONTROL, cache_control),
+            Vec::new(),
+        );
+    }
+
+    let image = match covers.load(&hash, query.size) {
+        Some(i) => i,
+        None => {
+            println!("cover {hash} for album {album_id} is missing from the cover cache");
+            return status_response(StatusCode::NOT_FOUND);
+        }
+    };
+
+    build(
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, &image.extension)
+            .header(header::ETAG, &etag)
+            .header(header::CACHE_CONTROL, cache_control),
+        image.data,
+    )
+}
+
+fn status_response(status: StatusCode) -> Response<Vec<u8>> {
+    build(Response::builder().status(status), Vec::new())
+}
+
+fn build(builder: tauri::http::response::Builder, body: Vec<u8>) -> Response<Vec<u8>> {
+    match builder.body(body) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("failed to build cover response: {e}");
+            let mut response = Response::new(Vec::new());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures;
+    use crate::core::cover_cache::CoverCache;
+    use crate::core::song::{Album, Image};
+    use crate::store::MemoryStore;
+    use std::sync::Arc;
+    use std::sync::mpsc;
+
+    fn library_with_cover(dir: &std::path::Path) -> (Mutex<MusicLibrary>, Uuid, String) {
+        let (sender, receiver) = mpsc::channel();
+        let player = crate::core::audio::PlayerController::new(sender, receiver);
+        let covers = CoverCache::new(dir);
+        let mut library = MusicLibrary::from_store(Arc::new(MemoryStore::default()), covers.clone(), player);
+
+        let hash = covers
+            .store(&Image {
+                data: b"art".to_vec(),
+                extension: "image/png".into(),
+            })
+            .unwrap();
+
+        let album = Album {
+            artists: Vec::new(),
+            cover: Some(hash.clone()),
+            ..fixtures::album("album", &fixtures::artist("artist"))
+        };
+        let id = album.id;
+        library.albums.insert(id, album);
+
+        (Mutex::new(library), id, hash)
+    }
+
+    fn get(library: &Mutex<MusicLibrary>, uri: &str, etag: Option<&str>) -> Response<Vec<u8>> {
+        let mut request = Request::builder().uri(uri);
+        if let Some(etag) = etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        cover_response(library, &request.body(Vec::new()).unwrap())
+    }
+
+    #[test]
+    fn test_cover_response() {
+        let dir = tempfile::tempdir().unwrap();
+        let (library, id, hash) = library_with_cover(dir.path());
+
+        let response = get(&library, &format!("cover://localhost/{id}?size=256"), None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), b"art");
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap().to_str().unwrap(), "image/png");
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap().to_str().unwrap(), REVALIDATE);
+
+        let etag = response.headers().get(header::ETAG).unwrap().to_str().unwrap().to_string();
+        let response = get(&library, &format!("http://cover.localhost/{id}?size=256"), Some(&etag));
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(response.body().is_empty());
+
+        let response = get(&library, &format!("cover://localhost/{id}?size=256&v={hash}"), None);
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap().to_str().unwrap(), IMMUTABLE);
+
+        assert_eq!(get(&library, "cover://localhost/not-an-id", None).status(), StatusCode::BAD_REQUEST);
+        assert_eq!(get(&library, &format!("cover://localhost/{}", Uuid::new_v4()), None).status(), StatusCode::NOT_FOUND);
+    }
+}
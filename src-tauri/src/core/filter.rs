@@ -0,0 +1,243 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::Read,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+//extensions we know how to read tags from, anything else is skipped by default
+pub const DEFAULT_ALLOWED_EXTENSIONS: &[&str] = &["mp3", "flac", "m4a", "m4b", "mp4"];
+
+//number of bytes read from the start of a file when sniffing its format
+const SNIFF_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Mp3,
+    Flac,
+    Mp4,
+    Ogg,
+    Wav,
+}
+
+impl AudioFormat {
+    //extensions that are expected for each detected format
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            AudioFormat::Mp3 => &["mp3"],
+            AudioFormat::Flac => &["flac"],
+            AudioFormat::Mp4 => &["m4a", "m4b", "mp4"],
+            AudioFormat::Ogg => &["ogg", "oga", "opus"],
+            AudioFormat::Wav => &["wav"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterResult {
+    Supported(AudioFormat),
+    Denied,
+    UnsupportedExtension,
+    UnrecognisedContent,
+    Unreadable(std::io::ErrorKind),
+}
+
+//allow/deny list of file extensions used by the scanner
+//deny always wins, so a user can block e.g. m4b audiobooks without editing the allow list
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(from = "FormatLists")]
+pub struct FormatFilter {
+    pub allow: HashSet<String>,
+    pub deny: HashSet<String>,
+}
+
+//the lists as the frontend sends them, they go through new so "MP3" or ".flac" still match
+#[derive(Deserialize)]
+struct FormatLists {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl From<FormatLists> for FormatFilter {
+    fn from(lists: FormatLists) -> Self {
+        FormatFilter::new(lists.allow, lists.deny)
+    }
+}
+
+impl Default for FormatFilter {
+    fn default() -> Self {
+        FormatFilter {
+            allow: DEFAULT_ALLOWED_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+            deny: HashSet::new(),
+        }
+    }
+}
+
+impl FormatFilter {
+    pub fn new<A, D>(allow: A, deny: D) -> Self
+    where
+        A: IntoIterator,
+        A::Item: AsRef<str>,
+        D: IntoIterator,
+        D::Item: AsRef<str>,
+    {
+        FormatFilter {
+            allow: allow.into_iter().map(|e| normalise_extension(e.as_ref())).collect(),
+            deny: deny.into_iter().map(|e| normalise_extension(e.as_ref())).collect(),
+        }
+    }
+
+    fn is_allowed(&self, ext: &str) -> bool {
+        self.allow.contains(ext) && !self.deny.contains(ext)
+    }
+
+    //checks the extension first so we dont open every jpg in the library,
+    //files without an extension are let through to the sniffer
+    pub fn check<P: AsRef<Path>>(&self, path: P) -> FilterResult {
+        let ext = path
+            .as_ref()
+            .extension()
+            .map(|e| normalise_extension(&e.to_string_lossy()));
+
+        if let Some(ext) = &ext {
+            if self.deny.contains(ext) {
+                return FilterResult::Denied;
+            }
+
+            if !self.allow.contains(ext) {
+                return FilterResult::UnsupportedExtension;
+            }
+        }
+
+        let format = match sniff_file(&path) {
+            Ok(Some(f)) => f,
+            Ok(None) => return FilterResult::UnrecognisedContent,
+            Err(e) => return FilterResult::Unreadable(e.kind()),
+        };
+
+        //the content has to be something we allow as well, a renamed png is still a png
+        if format.extensions().iter().any(|e| self.is_allowed(e)) {
+            FilterResult::Supported(format)
+        } else {
+            FilterResult::UnrecognisedContent
+        }
+    }
+}
+
+fn normalise_extension(ext: &str) -> String {
+    ext.trim_start_matches('.').to_lowercase()
+}
+
+pub fn sniff_file<P: AsRef<Path>>(path: P) -> std::io::Result<Option<AudioFormat>> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; SNIFF_LEN];
+
+    let mut read = 0;
+    while read < SNIFF_LEN {
+        match file.read(&mut header[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+
+    Ok(sniff_format(&header[..read]))
+}
+
+pub fn sniff_format(header: &[u8]) -> Option<AudioFormat> {
+    if header.starts_with(b"ID3") {
+        return Some(AudioFormat::Mp3);
+    }
+
+    if header.starts_with(b"fLaC") {
+        return Some(AudioFormat::Flac);
+    }
+
+    if header.starts_with(b"OggS") {
+        return Some(AudioFormat::Ogg);
+    }
+
+    if header.len() >= 12 && header.starts_with(b"RIFF") && &header[8..12] == b"WAVE" {
+        return Some(AudioFormat::Wav);
+    }
+
+    if header.len() >= 8 && &header[4..8] == b"ftyp" {
+        return Some(AudioFormat::Mp4);
+    }
+
+    //mp3 without an id3 header starts straight with an mpeg frame sync
+    if header.len() >= 2 && header[0] == 0xFF && (header[1] & 0xE0) == 0xE0 {
+        return Some(AudioFormat::Mp3);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_sniff_format_detects_magic_bytes() {
+        assert_eq!(sniff_format(b"ID3\x04\x00\x00\x00\x00\x00\x00"), Some(AudioFormat::Mp3));
+        assert_eq!(sniff_format(&[0xFF, 0xFB, 0x90, 0x64]), Some(AudioFormat::Mp3));
+        assert_eq!(sniff_format(b"fLaC\x00\x00\x00\x22"), Some(AudioFormat::Flac));
+        assert_eq!(sniff_format(b"\x00\x00\x00\x20ftypM4A "), Some(AudioFormat::Mp4));
+        assert_eq!(sniff_format(b"RIFF\x24\x08\x00\x00WAVE"), Some(AudioFormat::Wav));
+        assert_eq!(sniff_format(b"\x89PNG\r\n\x1a\n"), None);
+        assert_eq!(sniff_format(b""), None);
+    }
+
+    #[test]
+    fn test_filter_skips_non_audio_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let filter = FormatFilter::default();
+
+        let cover = dir.path().join("folder.jpg");
+        fs_write(&cover, b"\xFF\xD8\xFF\xE0\x00\x10JFIF");
+        assert_eq!(filter.check(&cover), FilterResult::UnsupportedExtension);
+
+        let store = dir.path().join(".DS_Store");
+        fs_write(&store, b"\x00\x00\x00\x01Bud1");
+        assert_eq!(filter.check(&store), FilterResult::UnrecognisedContent);
+
+        let song = dir.path().join("song.flac");
+        fs_write(&song, b"fLaC\x00\x00\x00\x22");
+        assert_eq!(filter.check(&song), FilterResult::Supported(AudioFormat::Flac));
+
+        let renamed = dir.path().join("not_a_song.mp3");
+        fs_write(&renamed, b"\x89PNG\r\n\x1a\n");
+        assert_eq!(filter.check(&renamed), FilterResult::UnrecognisedContent);
+    }
+
+    #[test]
+    fn test_filter_deny_wins_over_allow() {
+        let dir = tempfile::tempdir().unwrap();
+        let filter = FormatFilter::new(vec!["mp3", "M4B"], vec![".m4b"]);
+
+        let book = dir.path().join("book.m4b");
+        fs_write(&book, b"\x00\x00\x00\x20ftypM4B ");
+        assert_eq!(filter.check(&book), FilterResult::Denied);
+    }
+
+    #[test]
+    fn test_filter_normalises_deserialised_extensions() {
+        let dir = tempfile::tempdir().unwrap();
+        let filter: FormatFilter = serde_json::from_str(r#"{"allow": ["MP3", ".flac"], "deny": [".M4B"]}"#).unwrap();
+        assert_eq!(filter, FormatFilter::new(["mp3", "flac"], ["m4b"]));
+
+        let song = dir.path().join("song.flac");
+        fs_write(&song, b"fLaC\x00\x00\x00\x22");
+        assert_eq!(filter.check(&song), FilterResult::Supported(AudioFormat::Flac));
+
+        let round_trip: FormatFilter = serde_json::from_str(&serde_json::to_string(&filter).unwrap()).unwrap();
+        assert_eq!(round_trip, filter);
+    }
+
+    fn fs_write(path: &Path, data: &[u8]) {
+        let mut f = File::create(path).unwrap();
+        f.write_all(data).unwrap();
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use uuid::Uuid;
+
+use crate::core::song::{Album, Artist, Song};
+
+//library types with every optional field empty, tests set what they care about with struct update syntax
+
+pub fn artist(name: &str) -> Artist {
+    Artist {
+        id: Uuid::new_v4(),
+        name: name.into(),
+        sort_name: None,
+    }
+}
+
+pub fn album(title: &str, artist: &Artist) -> Album {
+    Album {
+        id: Uuid::new_v4(),
+        artists: vec![(Some(artist.id), artist.name.clone())],
+        cover: None,
+        cover_source: None,
+        palette: None,
+        title: title.into(),
+        sort_name: None,
+        year: None,
+        original_year: None,
+        genres: Vec::new(),
+        track_total: None,
+        disc_total: None,
+        label: None,
+        songs: Vec::new(),
+    }
+}
+
+//titled after its path so songs of one test are told apart without setting a title
+pub fn song(folder_id: i64, path: &str, artist: &Artist, album: &Album) -> Song {
+    Song {
+        id: Uuid::new_v4(),
+        title: path.into(),
+        artist: artist.id,
+        album: album.id,
+        features: None,
+        track_num: 1,
+        disc_num: 1,
+        cover: None,
+        path: PathBuf::from(path),
+        duration: 1.0,
+        folder_id,
+        file_size: 1,
+        modified: 1,
+        fingerprint: None,
+        year: None,
+        genres: Vec::new(),
+        composer: None,
+        conductor: None,
+        bpm: None,
+        comment: None,
+        original_year: None,
+        track_total: None,
+        disc_total: None,
+        label: None,
+    }
+}
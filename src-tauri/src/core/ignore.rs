@@ -0,0 +1,199 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+//name of the per directory file listing extra patterns to skip, it applies to its directory and everything below
+pub const IGNORE_FILE: &str = ".blehignore";
+
+//nas, os and trash folders that never contain music worth importing
+pub const DEFAULT_IGNORE_PATTERNS: &[&str] = &[
+    ".Trash",
+    ".Trash-*",
+    "$RECYCLE.BIN",
+    "System Volume Information",
+    "lost+found",
+    "@eaDir",
+    "#recycle",
+    "#snapshot",
+    ".AppleDouble",
+    "._*",
+];
+
+//a single line of an ignore list, a subset of gitignore:
+//`*` and `?` match inside a name, `**` matches any number of directories,
+//a trailing `/` only matches directories and a pattern containing `/` is relative to the list's directory
+#[derive(Debug, Clone, PartialEq)]
+struct Pattern {
+    segments: Vec<String>,
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Pattern> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Pattern {
+            segments: line.split('/').map(|s| s.to_string()).collect(),
+            anchored,
+            dir_only,
+        })
+    }
+
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let parts: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+
+        if self.anchored {
+            segments_match(&self.segments, &parts)
+        } else {
+            parts.last().is_some_and(|name| glob_match(&self.segments[0], name))
+        }
+    }
+}
+
+//ignore patterns for one directory, chained to the ones of the directories above it
+#[derive(Debug)]
+pub struct IgnoreRules {
+    base: PathBuf,
+    patterns: Vec<Pattern>,
+    parent: Option<Arc<IgnoreRules>>,
+}
+
+impl IgnoreRules {
+    //the patterns from the scan settings, relative to the library folder
+    pub fn new<S: AsRef<str>>(base: &Path, patterns: &[S]) -> Arc<Self> {
+        Arc::new(IgnoreRules {
+            base: base.to_path_buf(),
+            patterns: patterns.iter().filter_map(|p| Pattern::parse(p.as_ref())).collect(),
+            parent: None,
+        })
+    }
+
+    //rules for the entries of dir, adding its ignore file if it has one
+    pub fn child(self: &Arc<Self>, dir: &Path) -> Arc<Self> {
+        let contents = match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(c) => c,
+            Err(_) => return self.clone(),
+        };
+
+        Arc::new(IgnoreRules {
+            base: dir.to_path_buf(),
+            patterns: contents.lines().filter_map(Pattern::parse).collect(),
+            parent: Some(self.clone()),
+        })
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut rules = Some(self);
+
+        while let Some(r) = rules {
+            if let Ok(relative) = path.strip_prefix(&r.base) {
+                if r.patterns.iter().any(|p| p.matches(relative, is_dir)) {
+                    return true;
+                }
+            }
+            rules = r.parent.as_deref();
+        }
+
+        false
+    }
+}
+
+fn segments_match(pattern: &[String], parts: &[String]) -> bool {
+    match pattern.split_first() {
+        None => parts.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=parts.len()).any(|skip| segments_match(rest, &parts[skip..]))
+        }
+        Some((first, rest)) => match parts.split_first() {
+            Some((name, remaining)) => glob_match(first, name) && segments_match(rest, remaining),
+            None => false,
+        },
+    }
+}
+
+//`*` matches any run of characters and `?` a single one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            last_star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = last_star {
+            //let the last star swallow one more character and try again
+            p = star_p + 1;
+            t = star_t + 1;
+            last_star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(".Trash-*", ".Trash-1000"));
+        assert!(glob_match("*.cue", "album.cue"));
+        assert!(glob_match("disc?", "disc2"));
+        assert!(!glob_match("disc?", "disc10"));
+        assert!(!glob_match("*.cue", "album.flac"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn test_ignore_file_applies_below_its_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let albums = root.join("albums");
+        fs::create_dir_all(albums.join("live/bootlegs")).unwrap();
+        fs::write(albums.join(IGNORE_FILE), "# skip these\nlive/bootlegs/\n*.wav\n**/demo*\n").unwrap();
+
+        let rules = IgnoreRules::new(root, DEFAULT_IGNORE_PATTERNS).child(root).child(&albums);
+
+        assert!(rules.is_ignored(&albums.join("live/bootlegs"), true));
+        assert!(!rules.is_ignored(&albums.join("live/bootlegs"), false));
+        assert!(!rules.is_ignored(&albums.join("live"), true));
+        assert!(rules.is_ignored(&albums.join("live/track.wav"), false));
+        assert!(rules.is_ignored(&albums.join("live/x/demo 1.mp3"), false));
+        assert!(rules.is_ignored(&albums.join("@eaDir"), true));
+
+        //patterns from albums/.blehignore dont leak into its siblings
+        let root_rules = IgnoreRules::new(root, DEFAULT_IGNORE_PATTERNS).child(root);
+        assert!(!root_rules.is_ignored(&root.join("track.wav"), false));
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use uuid::Uuid;
+
+use crate::core::scan::{scan_dir, ScanReport};
+use crate::core::watcher::WatcherState;
+use crate::AppState;
+
+pub type ScanJobsState = Mutex<ScanJobs>;
+
+//scan-progress events are throttled so a fast scan doesnt flood the frontend
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+//shared between the command that started a scan and the thread running it
+#[derive(Clone)]
+pub struct ScanJob {
+    pub id: Uuid,
+    pub folder: PathBuf,
+    cancel: Arc<AtomicBool>,
+}
+
+impl ScanJob {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ScanJobInfo {
+    pub id: Uuid,
+    pub folder: PathBuf,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ScanProgress {
+    pub job_id: Uuid,
+    pub current_path: PathBuf,
+    #[serde(flatten)]
+    pub report: ScanReport,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ScanFinished {
+    pub job_id: Uuid,
+    pub folder: PathBuf,
+    pub report: Option<ScanReport>,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct ScanJobs {
+    running: HashMap<Uuid, ScanJob>,
+}
+
+impl ScanJobs {
+    //registers a new scan, refusing to start one that overlaps a folder thats already being scanned
+    pub fn start<P: AsRef<Path>>(&mut self, folder: P) -> Result<ScanJob, String> {
+        let folder = folder.as_ref();
+
+        if let Some(job) = self
+            .running
+            .values()
+            .find(|job| job.folder.starts_with(folder) || folder.starts_with(&job.folder))
+        {
+            return Err(format!("{} is already being scanned", job.folder.display()));
+        }
+
+        let job = ScanJob {
+            id: Uuid::new_v4(),
+            folder: folder.to_path_buf(),
+            cancel: Arc::new(AtomicBool::new(false)),
+        };
+
+        self.running.insert(job.id, job.clone());
+        Ok(job)
+    }
+
+    pub fn cancel(&self, id: Uuid) -> bool {
+        match self.running.get(&id) {
+            Some(job) => {
+                job.cancel.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn finish(&mut self, id: Uuid) {
+        self.running.remove(&id);
+    }
+
+    pub fn running(&self) -> Vec<ScanJobInfo> {
+        self.running
+            .values()
+            .map(|job| ScanJobInfo {
+                id: job.id,
+                folder: job.folder.clone(),
+            })
+            .collect()
+    }
+}
+
+//runs on its own thread, emits scan-progress while walking and scan-finished once the job is done
+pub fn run_scan(app: AppHandle, job: ScanJob) {
+    let state = app.state::<AppState>();
+    let mut last_emit: Option<Instant> = None;
+
+    let result = scan_dir(&job.folder, state.clone(), &job, |report, current_path| {
+        if last_emit.is_some_and(|t| t.elapsed() < PROGRESS_INTERVAL) {
+            return;
+        }
+        last_emit = Some(Instant::now());
+
+        let progress = ScanProgress {
+            job_id: job.id,
+            current_path: current_path.to_path_buf(),
+            report: report.summary(),
+        };
+
+        if let Err(e) = app.emit("scan-progress", &progress) {
+            println!("failed to emit scan progress: {e}");
+        }
+    });
+
+    if result.is_ok() {
+        let folders = state.lock().unwrap().folders.clone();
+        app.state::<WatcherState>().lock().unwrap().sync(&folders);
+    }
+
+    app.state::<ScanJobsState>().lock().unwrap().finish(job.id);
+
+    let finished = match result {
+        Ok(report) => ScanFinished {
+            job_id: job.id,
+            folder: job.folder.clone(),
+            report: Some(report),
+            error: None,
+        },
+        Err(e) => {
+            println!("scan of {} failed: {e}", job.folder.display());
+            ScanFinished {
+                job_id: job.id,
+                folder: job.folder.clone(),
+                report: None,
+                error: Some(e),
+            }
+        }
+    };
+
+    if let Err(e) = app.emit("scan-finished", &finished) {
+        println!("failed to emit scan finished: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlapping_scans_are_rejected() {
+        let mut jobs = ScanJobs::default();
+
+        let job = jobs.start("/music").unwrap();
+        assert!(jobs.start("/music").is_err());
+        assert!(jobs.start("/music/jazz").is_err());
+        assert!(jobs.start("/").is_err());
+        assert!(jobs.start("/podcasts").is_ok());
+        assert!(jobs.start("/musicals").is_ok());
+
+        jobs.finish(job.id);
+        assert!(jobs.start("/music").is_ok());
+    }
+
+    #[test]
+    fn test_cancel_sets_flag() {
+        let mut jobs = ScanJobs::default();
+        let job = jobs.start("/music").unwrap();
+
+        assert!(!job.is_cancelled());
+        assert!(jobs.cancel(job.id));
+        assert!(job.is_cancelled());
+        assert!(!jobs.cancel(Uuid::new_v4()));
+    }
+}
@@ -1,4 +1,19 @@
 pub mod scan;
 pub mod song;
+#[cfg(test)]
+pub mod fixtures;
 pub mod audio;
-pub mod controller;
\ No newline at end of file
+pub mod controller;
+pub mod filter;
+pub mod probe;
+pub mod watcher;
+pub mod jobs;
+pub mod walk;
+pub mod ignore;
+pub mod cover;
+pub mod cover_cache;
+pub mod cover_protocol;
+pub mod palette;pub mod query;
+pub mod search;
+pub mod sort_key;
+pub mod tags;
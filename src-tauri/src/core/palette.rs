@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+//covers are shrunk to this before counting colours, plenty to find the main ones
+const SAMPLE_SIZE: u32 = 64;
+
+//a colour counts as vibrant above this saturation and as muted below it
+const VIBRANT_SATURATION: f32 = 0.35;
+
+//colours picked for the theme, all as #rrggbb so the frontend can use them as css directly
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Palette {
+    //the most common colour in the cover
+    pub dominant: String,
+    //the strongest saturated colour, falls back to dominant for covers without one
+    pub vibrant: String,
+    //the most common washed out colour, falls back to dominant as well
+    pub muted: String,
+    //black or white, whichever reads better on top of dominant
+    pub text: String,
+}
+
+struct Swatch {
+    rgb: [u8; 3],
+    population: u32,
+    saturation: f32,
+    lightness: f32,
+}
+
+impl Palette {
+    pub fn from_bytes(data: &[u8]) -> Result<Palette, String> {
+        let image = image::load_from_memory(data).map_err(|e| format!("failed to decode cover: {e}"))?;
+        Palette::from_image(&image).ok_or_else(|| "cover has no pixels".to_string())
+    }
+
+    pub fn from_image(image: &DynamicImage) -> Option<Palette> {
+        let swatches = swatches(image);
+        let dominant = swatches.first()?;
+
+        let in_middle = |s: &&Swatch| (0.2..=0.8).contains(&s.lightness);
+
+        let vibrant = swatches
+            .iter()
+            .filter(in_middle)
+            .filter(|s| s.saturation >= VIBRANT_SATURATION)
+            .max_by(|a, b| vibrancy(a).total_cmp(&vibrancy(b)))
+            .unwrap_or(dominant);
+
+        let muted = swatches
+            .iter()
+            .filter(in_middle)
+            .find(|s| s.saturation < VIBRANT_SATURATION)
+            .unwrap_or(dominant);
+
+        let text = if contrast(dominant.rgb, [255; 3]) >= contrast(dominant.rgb, [0; 3]) {
+            [255; 3]
+        } else {
+            [0; 3]
+        };
+
+        Some(Palette {
+            dominant: hex(dominant.rgb),
+            vibrant: hex(vibrant.rgb),
+            muted: hex(muted.rgb),
+            text: hex(text),
+        })
+    }
+}
+
+//groups similar pixels into buckets of 16 levels per channel, most common first
+fn swatches(image: &DynamicImage) -> Vec<Swatch> {
+    let sample = if image.width().max(image.height()) > SAMPLE_SIZE {
+        image.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgb8()
+    } else {
+        image.to_rgb8()
+    };
+    let mut buckets: HashMap<[u8; 3], (u32, [u32; 3])> = HashMap::new();
+
+    for pixel in sample.pixels() {
+        let [r, g, b] = pixel.0;
+        let (count, sum) = buckets.entry([r >> 4, g >> 4, b >> 4]).or_default();
+        *count += 1;
+        sum[0] += r as u32;
+        sum[1] += g as u32;
+        sum[2] += b as u32;
+    }
+
+    let mut swatches: Vec<Swatch> = buckets
+        .into_values()
+        .map(|(count, sum)| {
+            let rgb = sum.map(|c| (c / count) as u8);
+            let (saturation, lightness) = saturation_lightness(rgb);
+
+            Swatch {
+                rgb,
+                population: count,
+                saturation,
+                lightness,
+            }
+        })
+        .collect();
+
+    //ties broken on the colour itself so the same cover always gives the same palette
+    swatches.sort_by(|a, b| b.population.cmp(&a.population).then(a.rgb.cmp(&b.rgb)));
+    swatches
+}
+
+//saturation counts for more than size, a small bright logo beats a large dull background
+fn vibrancy(swatch: &Swatch) -> f32 {
+    swatch.saturation * (swatch.population as f32).sqrt()
+}
+
+//hsl saturation and lightness, both 0 to 1
+fn saturation_lightness(rgb: [u8; 3]) -> (f32, f32) {
+    let [r, g, b] = rgb.map(|c| c as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+
+    if max == min {
+        return (0.0, lightness);
+    }
+
+    let delta = max - min;
+    let saturation = if lightness > 0.5 {
+        delta / (2.0 - max - min)
+    } else {
+        delta / (max + min)
+    };
+
+    (saturation, lightness)
+}
+
+//wcag contrast ratio between two colours
+fn contrast(a: [u8; 3], b: [u8; 3]) -> f32 {
+    let (la, lb) = (luminance(a), luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+fn luminance(rgb: [u8; 3]) -> f32 {
+    let [r, g, b] = rgb.map(|c| {
+        let c = c as f32 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_palette_from_cover() {
+        //mostly dull grey-blue with a small bright red square in the corner
+        let cover = RgbImage::from_fn(64, 64, |x, y| {
+            if x < 16 && y < 16 {
+                Rgb([220, 30, 30])
+            } else {
+                Rgb([90, 100, 110])
+            }
+        });
+
+        let palette = Palette::from_image(&DynamicImage::from(cover)).unwrap();
+
+        assert_eq!(palette.dominant, "#5a646e");
+        assert_eq!(palette.muted, "#5a646e");
+        assert_eq!(palette.vibrant, "#dc1e1e");
+        assert_eq!(palette.text, "#ffffff");
+
+        let light = RgbImage::from_fn(10, 10, |_, _| Rgb([240, 240, 230]));
+        let palette = Palette::from_image(&DynamicImage::from(light)).unwrap();
+        assert_eq!(palette.vibrant, palette.dominant);
+        assert_eq!(palette.text, "#000000");
+    }
+}
@@ -0,0 +1,186 @@
+use std::{fs::File, io, path::Path};
+
+use symphonia::core::{
+    codecs::CODEC_TYPE_NULL,
+    errors::Error as SymphoniaError,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+    units::TimeBase,
+};
+
+use crate::core::tags::FileTags;
+
+pub struct AudioInfo {
+    pub duration: f64,
+    //hash of the encoded audio packets and the length, tags arent part of the packets
+    //so retagging or moving a file keeps the same fingerprint
+    pub fingerprint: String,
+    pub tags: FileTags,
+}
+
+//reads every packet of the first audio track to work out its exact length and fingerprint
+//the end of the last packet is used as the length, the frame count in the header is only a fallback
+//since for an mp3 without a xing/info frame symphonia estimates it from the bitrate of the first frames
+pub fn probe_audio<P: AsRef<Path>>(path: P) -> Result<AudioInfo, SymphoniaError> {
+    let file = File::open(path.as_ref())?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.as_ref().extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    //tags can be in front of the stream, like id3 on mp3, or inside the container
+    let mut tags = FileTags::default();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        tags.read(revision.tags());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.read(revision.tags());
+    }
+
+    let mut format = probed.format;
+
+    let track = match format.tracks().iter().find(|t| t.codec_params.codec != CODEC_TYPE_NULL) {
+        Some(t) => t,
+        None => return Err(SymphoniaError::Unsupported("no audio track found")),
+    };
+
+    let track_id = track.id;
+    let params = &track.codec_params;
+
+    let time_base = match params.time_base {
+        Some(tb) => tb,
+        None => match params.sample_rate {
+            Some(rate) => TimeBase::new(1, rate),
+            None => return Err(SymphoniaError::Unsupported("track has no time base")),
+        },
+    };
+
+    let n_frames = params.n_frames;
+
+    let mut hasher = blake3::Hasher::new();
+    let mut end = 0;
+    loop {
+        match format.next_packet() {
+            Ok(packet) => {
+                if packet.track_id() == track_id {
+                    end = end.max(packet.ts() + packet.dur());
+                    hasher.update(&packet.data);
+                }
+            }
+            Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    let frames = if end > 0 { end } else { n_frames.unwrap_or(0) };
+    hasher.update(&frames.to_le_bytes());
+
+    Ok(AudioInfo {
+        duration: to_seconds(time_base, frames),
+        fingerprint: hasher.finalize().to_hex().to_string(),
+        tags,
+    })
+}
+
+fn to_seconds(time_base: TimeBase, ts: u64) -> f64 {
+    let time = time_base.calc_time(ts);
+    time.seconds as f64 + time.frac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    //writes a mono 16 bit pcm wav file where every byte of audio is set to fill
+    fn write_wav(path: &Path, sample_rate: u32, samples: u32, fill: u8) {
+        let data_len = samples * 2;
+        let mut f = File::create(path).unwrap();
+
+        f.write_all(b"RIFF").unwrap();
+        f.write_all(&(36 + data_len).to_le_bytes()).unwrap();
+        f.write_all(b"WAVEfmt ").unwrap();
+        f.write_all(&16u32.to_le_bytes()).unwrap();
+        f.write_all(&1u16.to_le_bytes()).unwrap();
+        f.write_all(&1u16.to_le_bytes()).unwrap();
+        f.write_all(&sample_rate.to_le_bytes()).unwrap();
+        f.write_all(&(sample_rate * 2).to_le_bytes()).unwrap();
+        f.write_all(&2u16.to_le_bytes()).unwrap();
+        f.write_all(&16u16.to_le_bytes()).unwrap();
+        f.write_all(b"data").unwrap();
+        f.write_all(&data_len.to_le_bytes()).unwrap();
+        f.write_all(&vec![fill; data_len as usize]).unwrap();
+    }
+
+    #[test]
+    fn test_probe_audio_reads_frame_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("silence.wav");
+        write_wav(&path, 8000, 12000, 0);
+
+        let duration = probe_audio(&path).unwrap().duration;
+        assert!((duration - 1.5).abs() < 1e-6, "expected 1.5s, got {duration}");
+    }
+
+    //writes a mono 44.1khz mpeg-1 layer iii stream with no xing or vbri frame, the bitrate drops
+    //after the first frames so an estimate from the start of the file is far off
+    fn write_vbr_mp3(path: &Path, loud_frames: usize, quiet_frames: usize) {
+        let mut f = File::create(path).unwrap();
+
+        for (bitrate_index, frame_len, count) in [(14u8, 1044, loud_frames), (1u8, 104, quiet_frames)] {
+            let mut frame = vec![0u8; frame_len];
+            frame[..4].copy_from_slice(&[0xFF, 0xFB, bitrate_index << 4, 0xC0]);
+            for _ in 0..count {
+                f.write_all(&frame).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_probe_audio_walks_vbr_mp3_without_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vbr.mp3");
+        write_vbr_mp3(&path, 20, 200);
+
+        //every mp3 frame holds 1152 samples
+        let expected = 220.0 * 1152.0 / 44100.0;
+        let duration = probe_audio(&path).unwrap().duration;
+        assert!((duration - expected).abs() < 1e-6, "expected {expected}s, got {duration}");
+    }
+
+    #[test]
+    fn test_probe_audio_rejects_non_audio() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, b"definitely not audio").unwrap();
+
+        assert!(probe_audio(&path).is_err());
+    }
+
+    #[test]
+    fn test_fingerprint_follows_audio_not_path() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let original = dir.path().join("a.wav");
+        let moved = dir.path().join("b.wav");
+        let different = dir.path().join("c.wav");
+        write_wav(&original, 8000, 8000, 0);
+        write_wav(&moved, 8000, 8000, 0);
+        write_wav(&different, 8000, 8000, 1);
+
+        let original = probe_audio(&original).unwrap().fingerprint;
+        assert_eq!(original, probe_audio(&moved).unwrap().fingerprint);
+        assert_ne!(original, probe_audio(&different).unwrap().fingerprint);
+    }
+}
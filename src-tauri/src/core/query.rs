@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::SongToSend;
+
+//the most songs a single page can hold, the list only ever asks for what fits on screen plus a bit
+pub const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SongSortKey {
+    #[default]
+    Title,
+    //artist, then their albums in track order
+    Artist,
+    //album, then track order
+    Album,
+    Duration,
+    //year, then albums in track order
+    Year,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SongSort {
+    pub key: SongSortKey,
+    pub descending: bool,
+}
+
+//every field that is set has to match
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SongFilter {
+    //the main artist or one of the features
+    pub artist: Option<Uuid>,
+    pub album: Option<Uuid>,
+    pub folder: Option<i64>,
+    //part of the title, artist or album, ignoring case
+    pub text: Option<String>,
+    //the whole genre, composer or conductor name, ignoring case
+    pub genre: Option<String>,
+    pub year: Option<i32>,
+    pub composer: Option<String>,
+    pub conductor: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SongQuery {
+    pub offset: usize,
+    pub limit: usize,
+    pub sort: SongSort,
+    pub filter: SongFilter,
+}
+
+//a genre to browse by and how many songs are tagged with it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GenreCount {
+    pub name: String,
+    pub songs: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SongPage {
+    //songs matching the filter across every page, so the list can size its scrollbar
+    pub total: usize,
+    pub offset: usize,
+    pub songs: Vec<SongToSend>,
+}
+
+impl SongQuery {
+    pub fn new(offset: usize, limit: usize, sort: SongSort, filter: SongFilter) -> Self {
+        SongQuery {
+            offset,
+            limit: limit.min(MAX_PAGE_SIZE),
+            sort,
+            filter,
+        }
+    }
+}
+
+//blank text matches everything, same as not setting it
+fn non_blank(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|t| !t.is_empty())
+}
+
+impl SongFilter {
+    pub fn text(&self) -> Option<&str> {
+        non_blank(&self.text)
+    }
+
+    pub fn genre(&self) -> Option<&str> {
+        non_blank(&self.genre)
+    }
+
+    pub fn composer(&self) -> Option<&str> {
+        non_blank(&self.composer)
+    }
+
+    pub fn conductor(&self) -> Option<&str> {
+        non_blank(&self.conductor)
+    }
+}
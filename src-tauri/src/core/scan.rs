@@ -1,53 +1,251 @@
 use std::{
     cell::RefCell,
-    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    io,
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
     rc::{Rc, Weak},
-    sync::{Arc, RwLock},
+    sync::{mpsc, Arc, Mutex, RwLock},
+    thread,
+    time::UNIX_EPOCH,
 };
 
-use rusqlite::{params, Connection, Transaction};
+use symphonia::core::errors::Error as SymphoniaError;
+use serde::{Deserialize, Serialize};
 use tauri::State;
 use audiotags::{Picture, Tag};
-use metadata::media_file::MediaFileMetadata;
 use uuid::Uuid;
 
-use crate::db_dir;
-use crate::state::{get_all_albums, get_all_artists, insert_folder_and_get_id};
+use crate::state::{LibraryChanges, MusicLibrary};
+use crate::store::StoreTransaction;
 use crate::AppState;
-use crate::state::{init_db, insert_song_to_db};
-use crate::core::song::{Album, Artist, ArtistType, Image, Song};
+use crate::core::song::{Album, Artist, ArtistType, CoverSource, Song};
+use crate::core::filter::{FilterResult, FormatFilter};
+use crate::core::probe::probe_audio;
+use crate::core::tags::FileTags;
+use crate::core::jobs::ScanJob;
+use crate::core::walk::{is_excluded, walk_parallel, worker_count};
+use crate::core::ignore::DEFAULT_IGNORE_PATTERNS;
+use crate::core::cover::{CoverCandidate, CoverPreference, CoverSettings, SidecarCache};
+use crate::core::cover_cache::CoverCache;
+use crate::core::palette::Palette;
+
+const DEFAULT_MAX_DEPTH: usize = 32;
+//files queued per extraction worker ahead of the writer
+const IN_FLIGHT_PER_WORKER: usize = 4;
+//files written per transaction, the writer is handed over between batches so
+//settings changes, folder removals and watcher batches dont wait for the whole scan
+pub const SCAN_BATCH_SIZE: usize = 200;
+
+
+//why a file couldnt be imported, kept in the scan report so the settings page can list problem files
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum ScanError {
+    Unreadable(String),
+    PermissionDenied,
+    UnsupportedFormat(String),
+    CorruptTag(String),
+    CorruptAudio(String),
+    ZeroDuration,
+    DbInsert(String),
+}
+
+impl ScanError {
+    pub fn from_io(e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => ScanError::PermissionDenied,
+            _ => ScanError::Unreadable(e.to_string()),
+        }
+    }
+
+    fn from_tag(e: audiotags::Error) -> Self {
+        match e {
+            audiotags::Error::UnknownFileExtension(ext) => ScanError::UnsupportedFormat(ext),
+            audiotags::Error::UnsupportedFormat(format) => ScanError::UnsupportedFormat(format),
+            e => ScanError::CorruptTag(e.to_string()),
+        }
+    }
 
+    fn from_audio(e: SymphoniaError) -> Self {
+        match e {
+            SymphoniaError::IoError(e) => ScanError::from_io(&e),
+            SymphoniaError::Unsupported(what) => ScanError::UnsupportedFormat(what.to_string()),
+            e => ScanError::CorruptAudio(e.to_string()),
+        }
+    }
+}
 
-#[derive(Debug)]
-pub enum CError {
-    InvalidTag(PathBuf),
-    InvalidPath,
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanError::Unreadable(e) => write!(f, "file could not be read: {e}"),
+            ScanError::PermissionDenied => write!(f, "permission denied"),
+            ScanError::UnsupportedFormat(e) => write!(f, "unsupported format: {e}"),
+            ScanError::CorruptTag(e) => write!(f, "corrupt tag: {e}"),
+            ScanError::CorruptAudio(e) => write!(f, "corrupt audio: {e}"),
+            ScanError::ZeroDuration => write!(f, "audio has no length"),
+            ScanError::DbInsert(e) => write!(f, "failed to save to the library: {e}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileError {
+    pub path: PathBuf,
+    pub error: ScanError,
+}
+
+//user configurable options that control what the scanner picks up
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ScanSettings {
+    pub formats: FormatFilter,
+    pub follow_symlinks: bool,
+    //folders nested deeper than this below a library folder arent walked
+    pub max_depth: usize,
+    //skip files and folders whose name starts with a dot
+    pub skip_hidden: bool,
+    //glob patterns skipped in every library folder, on top of any .blehignore files
+    pub ignore: Vec<String>,
+    pub covers: CoverSettings,
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        ScanSettings {
+            formats: FormatFilter::default(),
+            follow_symlinks: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            skip_hidden: true,
+            ignore: DEFAULT_IGNORE_PATTERNS.iter().map(|p| p.to_string()).collect(),
+            covers: CoverSettings::default(),
+        }
+    }
+}
+
+//summary of a scan, skipped files are only counted so non audio files dont flood the logs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanReport {
+    pub dirs_visited: usize,
+    pub files_added: usize,
+    pub files_updated: usize,
+    pub files_moved: usize,
+    pub files_unchanged: usize,
+    pub files_removed: usize,
+    pub files_skipped: usize,
+    pub files_failed: usize,
+    pub cancelled: bool,
+    //every file counted in files_failed along with why
+    pub errors: Vec<FileError>,
+}
+
+impl ScanReport {
+    pub fn record(&mut self, path: &Path, outcome: &FileOutcome) {
+        match outcome {
+            FileOutcome::Added(_) => self.files_added += 1,
+            FileOutcome::Updated(_) => self.files_updated += 1,
+            FileOutcome::Moved(_) => self.files_moved += 1,
+            FileOutcome::Unchanged => self.files_unchanged += 1,
+            FileOutcome::Skipped => self.files_skipped += 1,
+            FileOutcome::Failed(error) => {
+                self.files_failed += 1;
+                self.errors.push(FileError {
+                    path: path.to_path_buf(),
+                    error: error.clone(),
+                });
+            }
+        }
+    }
+
+    //the counters without the error list, for progress events that fire many times a second
+    pub fn summary(&self) -> ScanReport {
+        ScanReport {
+            errors: Vec::new(),
+            ..*self
+        }
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.files_added + self.files_updated + self.files_moved + self.files_removed > 0
+    }
+}
+
+pub enum FileOutcome {
+    Added(Song),
+    Updated(Song),
+    //a new path whose audio matches a song whose file has disappeared, the song keeps its old id
+    Moved(Song),
+    Unchanged,
+    Skipped,
+    Failed(ScanError),
+}
+
+//size and modification time of a file, used to tell if it changed since the last scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStats {
+    pub size: u64,
+    pub modified: i64,
+}
+
+impl FileStats {
+    pub fn read<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let meta = fs::metadata(path)?;
+
+        //nanoseconds since the epoch, files older than 1970 just get 0
+        let modified = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as i64)
+            .unwrap_or(0);
+
+        Ok(FileStats {
+            size: meta.len(),
+            modified,
+        })
+    }
 }
 
 fn find_or_create_album(
     albums: &mut HashMap<Uuid, Album>,
     title: &str,
     parsed_artists: &[String],
-    cover: Option<Image>,
+    cover: Option<CoverCandidate>,
+    prefer: CoverPreference,
+    covers: &CoverCache,
     id: &Uuid,
     known_uuid: &Uuid,
     artists: &HashMap<Uuid, Artist>
 
 ) -> Uuid {
 
-    for album in albums.values() {
-        if album.title == title
-            && album.artists.len() == parsed_artists.len()
-            && album
-                .artists
-                .iter()
-                .zip(parsed_artists.iter())
-                .all(|(a, b)| a.1 == *b)
-        {
-            return album.id;
+    //the library can already hold duplicates, always pick the lowest id so the match doesnt depend on hashmap order
+    let existing = albums
+        .values()
+        .filter(|album| {
+            album.title == title
+                && album.artists.len() == parsed_artists.len()
+                && album
+                    .artists
+                    .iter()
+                    .zip(parsed_artists.iter())
+                    .all(|(a, b)| a.1 == *b)
+        })
+        .map(|album| album.id)
+        .min();
+
+    if let Some(id) = existing {
+        //art added since the album was created, or of the preferred kind, replaces what it has
+        let album = albums.get_mut(&id).unwrap();
+        if cover.as_ref().is_some_and(|c| c.outranks(album.cover_source.as_ref(), prefer)) {
+            if let (Some(hash), source, palette) = load_cover(cover, covers, title) {
+                album.cover = Some(hash);
+                album.cover_source = source;
+                album.palette = palette;
+            }
         }
+        return id;
     }
 
     //for the album artists, for each artist i create two new artists,
@@ -66,12 +264,24 @@ fn find_or_create_album(
     }
     
 
+    //only now that a new album is needed is a sidecar image read from disk
+    let (cover, cover_source, palette) = load_cover(cover, covers, title);
+
     let album = Album {
         id: Uuid::new_v4(),
         title: title.to_string(),
+        sort_name: None,
+        year: None,
+        original_year: None,
+        genres: Vec::new(),
+        track_total: None,
+        disc_total: None,
+        label: None,
         artists: album_artists,
         songs: vec![*id],
         cover,
+        cover_source,
+        palette,
     };
 
     let id = album.id;
@@ -79,20 +289,50 @@ fn find_or_create_album(
     id
 }
 
+//reads the candidate and stores it in the cover cache, returns its hash, where it came from and its palette
+fn load_cover(cover: Option<CoverCandidate>, covers: &CoverCache, title: &str) -> (Option<String>, Option<CoverSource>, Option<Palette>) {
+    let (cover, cover_source) = match cover.and_then(CoverCandidate::load) {
+        Some((image, source)) => match covers.store(&image) {
+            Ok(hash) => (Some(hash), Some(source)),
+            Err(e) => {
+                println!("failed to cache cover for {title}: {e}");
+                (None, None)
+            }
+        },
+        None => (None, None),
+    };
+
+    let palette = match cover.as_deref().map(|hash| covers.palette(hash)) {
+        Some(Ok(p)) => Some(p),
+        Some(Err(e)) => {
+            println!("failed to work out palette for {title}: {e}");
+            None
+        }
+        None => None,
+    };
+
+    (cover, cover_source, palette)
+}
+
 //for artists, we check to see if we already have an artist with the same name for now
 //in future, we may need to consider artists that have the same name (unlikely but happens, e.g there are two artists called Russ)
 fn find_or_create_artist(artists: &mut HashMap<Uuid, Artist>, known_artists: &mut HashMap<Uuid, ArtistType>, name: &str) -> Uuid {
-    for artist in artists.values() {
-        if artist.name == name {
-            return artist.id;
-        }
+    let existing = artists
+        .values()
+        .filter(|artist| artist.name == name)
+        .map(|artist| artist.id)
+        .min();
+
+    if let Some(id) = existing {
+        return id;
     }
 
     let id = Uuid::new_v4();
 
     let new_artist = Artist {
         id,
-        name: name.into()
+        name: name.into(),
+        sort_name: None,
     };
 
     
@@ -102,30 +342,38 @@ fn find_or_create_artist(artists: &mut HashMap<Uuid, Artist>, known_artists: &mu
     id
 }
 
-pub fn parse_file<P: AsRef<Path>>(
-    path: P,
-    albums: &mut HashMap<Uuid, Album>,
-    artists: &mut HashMap<Uuid, Artist>,
-    known_artists: &mut HashMap<Uuid, ArtistType>,
-    folder_id: i64
-) -> Result<Song, CError> {
-    let tag = match Tag::new().read_from_path(&path) {
-        Ok(t) => t,
-        Err(e) => {
-            println!(
-                "parse_file() tag error for {}: {e}",
-                path.as_ref().display()
-            );
-            return Err(CError::InvalidTag(path.as_ref().to_path_buf()));
-        }
-    };
+//everything read from a single file, gathered without touching the shared artist/album maps
+//so it can be built on any worker thread
+pub struct ParsedFile {
+    pub path: PathBuf,
+    pub stats: FileStats,
+    pub title: String,
+    pub artist: String,
+    pub album_title: String,
+    pub album_artists: Vec<String>,
+    pub features: Vec<String>,
+    pub track_num: u16,
+    pub disc_num: u16,
+    pub cover: Option<CoverCandidate>,
+    pub cover_preference: CoverPreference,
+    pub duration: f64,
+    pub fingerprint: String,
+    pub tags: FileTags,
+}
 
+pub fn read_file<P: AsRef<Path>>(path: P, covers: &CoverSettings, sidecars: &SidecarCache) -> Result<ParsedFile, ScanError> {
+    let stats = FileStats::read(&path).map_err(|e| ScanError::from_io(&e))?;
+    let tag = Tag::new().read_from_path(&path).map_err(ScanError::from_tag)?;
 
-    let title = tag.title().unwrap_or("unknown song");
-    let artist = tag.artist().unwrap_or(title);
+    //read the length first so a file we cant decode doesnt leave orphaned artists and albums behind
+    let audio = probe_audio(&path).map_err(ScanError::from_audio)?;
 
-    let artist_uuid = find_or_create_artist(artists, known_artists,artist);
+    if audio.duration <= 0.0 {
+        return Err(ScanError::ZeroDuration);
+    }
 
+    let title = tag.title().unwrap_or("unknown song");
+    let artist = tag.artist().unwrap_or(title);
     let album_title = tag.album_title().unwrap_or("unknown album");
 
     let album_artists = match tag.album_artists() {
@@ -133,91 +381,331 @@ pub fn parse_file<P: AsRef<Path>>(
         None => vec![artist.to_string()],
     };
 
-    let cover: Option<Image> = tag.album_cover().map(|img| img.into());
+    let features = match tag.artists() {
+        Some(artists_list) => artists_list
+            .into_iter()
+            .filter(|a| *a != artist)
+            .map(|a| a.to_string())
+            .collect(),
+        None => Vec::new(),
+    };
 
-    let song_id = Uuid::new_v4();
+    Ok(ParsedFile {
+        path: path.as_ref().to_path_buf(),
+        stats,
+        title: title.to_string(),
+        artist: artist.to_string(),
+        album_title: album_title.to_string(),
+        album_artists,
+        features,
+        track_num: tag.track_number().unwrap_or(1),
+        disc_num: tag.disc_number().unwrap_or(1),
+        cover: CoverCandidate::choose(tag.album_cover().map(|img| img.into()), path.as_ref().parent(), covers, sidecars),
+        cover_preference: covers.prefer,
+        duration: audio.duration,
+        fingerprint: audio.fingerprint,
+        tags: audio.tags,
+    })
+}
 
-    let album = find_or_create_album(albums, album_title, &album_artists, cover, &song_id, &artist_uuid, &artists);
+//id is passed in so a rescan can keep the id of a song that already exists
+fn build_song(
+    parsed: ParsedFile,
+    id: Uuid,
+    albums: &mut HashMap<Uuid, Album>,
+    artists: &mut HashMap<Uuid, Artist>,
+    known_artists: &mut HashMap<Uuid, ArtistType>,
+    covers: &CoverCache,
+    folder_id: i64
+) -> Song {
+    let artist_uuid = find_or_create_artist(artists, known_artists, &parsed.artist);
 
-    let features = if let Some(mut artists_list) = tag.artists() {
-        artists_list.retain(|a| *a !=artist);
+    let album = find_or_create_album(albums, &parsed.album_title, &parsed.album_artists, parsed.cover, parsed.cover_preference, covers, &id, &artist_uuid, artists);
 
-        if artists_list.is_empty() {
-            None
-        } else {
-            Some(artists_list.iter().map(|a| (None, a.to_string())).collect::<Vec<(Option<Uuid>, String)>>())
-        }
-    } else {
-        None
-    };
+    let tags = parsed.tags;
 
-    let track_num = tag.track_number().unwrap_or(1);
-    let disc_num = tag.disc_number().unwrap_or(1);
+    //the album artist sort tag goes to the artist the album is credited to, which isnt always the track artist.
+    //album artists that never turn up as a track artist have no artist of their own to sort
+    let album_artist = parsed.album_artists.first().and_then(|name| {
+        albums.get(&album)?.artists.iter().find(|(id, n)| id.is_some() && n == name)?.0
+    });
 
-    let duration = match MediaFileMetadata::new(&path) {
-        Ok(m) => {
-            if let Some(d) = m.duration {
-                if let Ok(d_float) = parse_duration_to_seconds(&d){
-                    d_float
-                }
-                else{
-                    0.0
-                }
-            }
-            else{
-                0.0
-            }
-        },
+    //a file without the tags leaves whatever another file set, the track artist sort tag wins over the album one
+    if let (Some(sort_name), Some(artist)) = (tags.sort.album_artist, album_artist.and_then(|id| artists.get_mut(&id))) {
+        artist.sort_name = Some(sort_name);
+    }
+    if let (Some(sort_name), Some(artist)) = (tags.sort.artist, artists.get_mut(&artist_uuid)) {
+        artist.sort_name = Some(sort_name);
+    }
+    //the year, genres and the rest are kept on the song, the store works the album ones out from all of its songs
+    if let (Some(sort_name), Some(album)) = (tags.sort.album, albums.get_mut(&album)) {
+        album.sort_name = Some(sort_name);
+    }
 
-        Err(e) => {
-            println!("failed to get duration of {title} by {artist}, defaulting to 0: {e}");
-            0.0
-        }
+    let features = if parsed.features.is_empty() {
+        None
+    } else {
+        Some(parsed.features.into_iter().map(|a| (None, a)).collect::<Vec<(Option<Uuid>, String)>>())
     };
 
-    let song = Song {
-        id: Uuid::new_v4(),
-        title: title.to_string(),
+    Song {
+        id,
+        title: parsed.title,
         artist: artist_uuid,
         album,
         features,
-        track_num,
-        disc_num,
+        track_num: parsed.track_num,
+        disc_num: parsed.disc_num,
         cover: None,
-        path: path.as_ref().to_path_buf(),
-        duration,
+        path: parsed.path,
+        duration: parsed.duration,
         folder_id,
+        file_size: parsed.stats.size,
+        modified: parsed.stats.modified,
+        fingerprint: Some(parsed.fingerprint),
+        year: tags.year,
+        genres: tags.genres,
+        composer: tags.composer,
+        conductor: tags.conductor,
+        bpm: tags.bpm,
+        comment: tags.comment,
+        original_year: tags.original_year,
+        track_total: tags.track_total,
+        disc_total: tags.disc_total,
+        label: tags.label,
+    }
+}
+
+//result of the part of scanning a file that doesnt need the database or the shared maps
+pub enum Extracted {
+    Parsed(ParsedFile),
+    Unchanged,
+    Skipped,
+    Failed(ScanError),
+}
+
+//checks a single file against the filter and what we already have stored for it,
+//only parsing tags when the file is new or its size/mtime changed. safe to call from any thread
+pub fn extract_file(path: &Path, existing: Option<(Uuid, FileStats)>, settings: &ScanSettings, sidecars: &SidecarCache) -> Extracted {
+    match settings.formats.check(path) {
+        FilterResult::Supported(_) => {}
+        FilterResult::Unreadable(kind) => return Extracted::Failed(ScanError::from_io(&io::Error::from(kind))),
+        _ => return Extracted::Skipped,
+    }
+
+    if let Some((_, stats)) = existing {
+        if FileStats::read(path).map(|s| s == stats).unwrap_or(false) {
+            return Extracted::Unchanged;
+        }
+    }
+
+    match read_file(path, &settings.covers, sidecars) {
+        Ok(parsed) => Extracted::Parsed(parsed),
+        Err(e) => {
+            println!("metadata extraction failed for {}: {e}", path.display());
+            Extracted::Failed(e)
+        }
+    }
+}
+
+//turns an extracted file into a song, matching it against the known artists and albums
+//this has to run on the thread that owns the transaction, in the same order every time,
+//so the same library always ends up with the same artists and albums
+pub fn resolve_file(
+    tx: &dyn StoreTransaction,
+    extracted: Extracted,
+    existing: Option<(Uuid, FileStats)>,
+    albums: &mut HashMap<Uuid, Album>,
+    artists: &mut HashMap<Uuid, Artist>,
+    known_artists: &mut HashMap<Uuid, ArtistType>,
+    covers: &CoverCache,
+    folder_id: i64
+) -> FileOutcome {
+    let parsed = match extracted {
+        Extracted::Parsed(p) => p,
+        Extracted::Unchanged => return FileOutcome::Unchanged,
+        Extracted::Skipped => return FileOutcome::Skipped,
+        Extracted::Failed(e) => return FileOutcome::Failed(e),
     };
 
-    Ok(song)
+    if let Some((id, _)) = existing {
+        return FileOutcome::Updated(build_song(parsed, id, albums, artists, known_artists, covers, folder_id));
+    }
+
+    match find_moved_song(tx, &parsed.fingerprint, &parsed.path) {
+        Some(old_id) => FileOutcome::Moved(build_song(parsed, old_id, albums, artists, known_artists, covers, folder_id)),
+        None => FileOutcome::Added(build_song(parsed, Uuid::new_v4(), albums, artists, known_artists, covers, folder_id)),
+    }
 }
 
+pub fn scan_file(
+    tx: &dyn StoreTransaction,
+    path: &Path,
+    existing: Option<(Uuid, FileStats)>,
+    settings: &ScanSettings,
+    sidecars: &SidecarCache,
+    albums: &mut HashMap<Uuid, Album>,
+    artists: &mut HashMap<Uuid, Artist>,
+    known_artists: &mut HashMap<Uuid, ArtistType>,
+    covers: &CoverCache,
+    folder_id: i64
+) -> FileOutcome {
+    let extracted = extract_file(path, existing, settings, sidecars);
+    resolve_file(tx, extracted, existing, albums, artists, known_artists, covers, folder_id)
+}
 
+//commits what a scan wrote since its last batch and merges it into the library. the library stays locked
+//from the commit until the merge so no other write can be merged in between
+fn commit_batch(
+    mut tx: Box<dyn StoreTransaction + '_>,
+    library: &Mutex<MusicLibrary>,
+    mut changes: LibraryChanges,
+    folder_id: i64,
+    merged_before: bool
+) -> Result<(), String> {
+    changes.album_tags = tx
+        .update_album_tags()
+        .map_err(|e| format!("failed to update album tags: {e}"))?;
+
+    let mut library = library.lock().unwrap();
+
+    //removed between batches, dropping the transaction throws away what was written for it since
+    if merged_before && !library.folders.contains_key(&folder_id) {
+        return Err(format!("folder {folder_id} was removed during the scan"));
+    }
 
+    if let Err(e) = tx.commit() {
+        return Err(format!("failed to commit scan transaction: {e}"));
+    }
 
-pub fn scan_dir<P: AsRef<Path>>(dir: P, state: State<AppState>) {
-    let mut songs = HashMap::new();
-    let mut known_artists: HashMap<Uuid, ArtistType> = HashMap::new();
+    library.merge_changes(changes);
+    Ok(())
+}
 
-    //start sqlite transaction here
-    let db_path = db_dir();
-    
-    let mut scann_conn = match Connection::open(&db_path) {
-        Ok(conn) => {
-            if let Err(e) = init_db(&conn) {
-                println!("failed to validate/create database schema {e}");
-                return;
-            }
-            else{
-                conn
-            }
+//writes a new or changed song, if the insert fails the file is reported as failed instead
+fn save_outcome(
+    tx: &mut dyn StoreTransaction,
+    outcome: FileOutcome,
+    artists: &HashMap<Uuid, Artist>,
+    albums: &HashMap<Uuid, Album>
+) -> FileOutcome {
+    let song = match &outcome {
+        FileOutcome::Added(song) | FileOutcome::Updated(song) | FileOutcome::Moved(song) => song,
+        _ => return outcome,
+    };
+
+    match tx.save_song(song, artists, albums) {
+        Ok(_) => outcome,
+        Err(e) => {
+            println!("Failed to insert song to DB: {}", e);
+            FileOutcome::Failed(ScanError::DbInsert(e.to_string()))
         }
+    }
+}
+
+//a song with the same fingerprint whose file no longer exists was moved or renamed to this path
+//if the old file is still there its a duplicate instead and gets its own entry
+fn find_moved_song(tx: &dyn StoreTransaction, fingerprint: &str, song_path: &Path) -> Option<Uuid> {
+    let candidates = match tx.song_paths_by_fingerprint(fingerprint) {
+        Ok(c) => c,
+        Err(e) => {
+            println!("failed to look up fingerprint for {}: {e}", song_path.display());
+            return None;
+        }
+    };
+
+    candidates
+        .into_iter()
+        .find(|(_, path)| path != song_path && !path.exists())
+        .map(|(id, _)| id)
+}
+
+//how a folder being added relates to the folders already in the library
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FolderOverlap {
+    //the folder is already part of this library folder, scanning it only rescans that part
+    Inside { id: i64, path: PathBuf },
+    //these library folders are inside the new one and would be merged into it
+    Contains { folders: Vec<(i64, PathBuf)> },
+}
+
+//the deepest library folder containing path owns it
+pub fn owning_folder<'a>(folders: &'a HashMap<i64, PathBuf>, path: &Path) -> Option<(i64, &'a PathBuf)> {
+    folders
+        .iter()
+        .filter(|(_, folder)| path.starts_with(folder))
+        .max_by_key(|(_, folder)| folder.components().count())
+        .map(|(id, folder)| (*id, folder))
+}
+
+//adding a folder thats already in the library is just a rescan, so thats not an overlap
+pub fn find_overlap(folders: &HashMap<i64, PathBuf>, path: &Path) -> Option<FolderOverlap> {
+    if folders.values().any(|f| f == path) {
+        return None;
+    }
+
+    if let Some((id, folder)) = owning_folder(folders, path) {
+        return Some(FolderOverlap::Inside {
+            id,
+            path: folder.clone(),
+        });
+    }
+
+    let mut nested: Vec<(i64, PathBuf)> = folders
+        .iter()
+        .filter(|(_, f)| f.starts_with(path))
+        .map(|(id, f)| (*id, f.clone()))
+        .collect();
+
+    if nested.is_empty() {
+        return None;
+    }
+
+    nested.sort();
+    Some(FolderOverlap::Contains { folders: nested })
+}
+
+//walks a folder and imports everything in it, on_progress is called while walking and after every file
+//when the job is cancelled the work done so far is committed but missing files arent removed,
+//since we cant tell which ones we just didnt get to
+pub fn scan_dir<P, F>(dir: P, state: State<AppState>, job: &ScanJob, on_progress: F) -> Result<ScanReport, String>
+where
+    P: AsRef<Path>,
+    F: FnMut(&ScanReport, &Path),
+{
+    scan_folder(dir.as_ref(), &state, || job.is_cancelled(), on_progress)
+}
+
+//does the work of scan_dir against any library, the in-memory library is only touched
+//after a batch is committed and only for the part that was scanned
+pub fn scan_folder<C, F>(
+    dir: &Path,
+    library: &Mutex<MusicLibrary>,
+    is_cancelled: C,
+    mut on_progress: F
+) -> Result<ScanReport, String>
+where
+    C: Fn() -> bool + Sync,
+    F: FnMut(&ScanReport, &Path),
+{
+    let mut known_artists: HashMap<Uuid, ArtistType> = HashMap::new();
+    let mut report = ScanReport::default();
+
+    let (settings, covers, store) = {
+        let library = library.lock().unwrap();
+        (library.scan_settings.clone(), library.covers.clone(), library.store.clone())
+    };
+
+    //a watcher batch waits for the batch being written instead of racing it
+    let mut tx = match store.begin() {
+        Ok(tx) => tx,
         Err(e) => {
-            println!("failed to open sqlite db connection for scanning: {e}");
-            return;
+            return Err(format!("failed to start sqlite db transaction: {}", e));
         }
     };
-    let mut albums = match get_all_albums(&scann_conn){
+
+    let mut albums = match tx.albums() {
         Ok(a) => a,
         Err(e) => {
             println!("failed to read albums from db, continuing with limited information {e}");
@@ -226,7 +714,7 @@ pub fn scan_dir<P: AsRef<Path>>(dir: P, state: State<AppState>) {
     };
 
     //here i need to get the artists
-    let mut artists = match get_all_artists(&scann_conn) {
+    let mut artists = match tx.artists() {
         Ok(a) => a,
         Err(e) => {
             println!("failed to get all artists from db, continuing with limited information {e}");
@@ -234,340 +722,798 @@ pub fn scan_dir<P: AsRef<Path>>(dir: P, state: State<AppState>) {
         }
     };
 
+    let mut folders = library.lock().unwrap().folders.clone();
 
-    let mut dir_queue = VecDeque::new();
-    dir_queue.push_back(dir.as_ref().to_path_buf());
+    //a folder inside an existing library folder is scanned as part of it,
+    //a new folder that contains existing ones takes over their songs
+    let (folder_id, library_root, absorbed) = match owning_folder(&folders, dir) {
+        Some((id, root)) => (id, root.clone(), Vec::new()),
+        None => {
+            let id = match tx.insert_folder(dir) {
+                Ok(f) => f,
+                Err(e) => {
+                    return Err(format!("failed to load folder id from sqlite db {e}"));
+                }
+            };
 
-    //start a transaction for batch inserts
-    let tx = match scann_conn.transaction() {
-        Ok(tx) => tx,
-        Err(e) => {
-            println!("failed to start sqlite db transaction: {}", e);
-            return;
+            let absorbed = match tx.absorb_nested_folders(id, dir) {
+                Ok(a) => a,
+                Err(e) => {
+                    return Err(format!("failed to merge nested folders into {}: {e}", dir.display()));
+                }
+            };
+
+            (id, dir.to_path_buf(), absorbed)
         }
     };
 
-    let folder_id = match insert_folder_and_get_id(&tx, &dir, state.clone()){
-        Ok(f) => f,
+    folders.retain(|id, _| !absorbed.contains(id));
+    folders.insert(folder_id, library_root.clone());
+
+    //songs already in this part of the folder, anything we dont see again during the walk gets removed
+    let existing: HashMap<PathBuf, (Uuid, FileStats)> = match tx.song_stats_in_folder(folder_id, dir) {
+        Ok(s) => s.into_iter().map(|(id, path, stats)| (path, (id, stats))).collect(),
         Err(e) => {
-            println!("failed to load folder id from sqlite db {e}");
-            return;
+            return Err(format!("failed to load existing songs for folder {folder_id}: {e}"));
         }
     };
 
-    while let Some(current_dir) = dir_queue.pop_front() {
-        let entries = match fs::read_dir(&current_dir) {
-            Ok(entries) => entries,
-            Err(err) => {
-                println!(
-                    "failed to read directory {}: {}",
-                    current_dir.display(),
-                    err
-                );
-                continue;
-            }
+    //written since the last batch was committed
+    let mut songs: Vec<Song> = Vec::new();
+    let mut batches = 0;
+
+    let mut seen: HashSet<Uuid> = HashSet::new();
+
+    let workers = worker_count();
+
+    let mut walk = walk_parallel(&library_root, dir, &settings, workers, &is_cancelled, |dirs_visited| {
+        report.dirs_visited = dirs_visited;
+        on_progress(&report, dir);
+    });
+    report.dirs_visited = walk.dirs_visited;
+
+    //files under another library folder nested in this one belong to that folder
+    walk.files.retain(|file| owning_folder(&folders, file).is_some_and(|(id, _)| id == folder_id));
+    let files = &walk.files;
+
+    //workers only read the files, results come back here and are written in walk order
+    //so artists and albums are matched the same way no matter which worker finishes first
+    let (job_tx, job_rx) = mpsc::channel::<(usize, Option<(Uuid, FileStats)>)>();
+    let job_rx = Mutex::new(job_rx);
+    let (result_tx, result_rx) = mpsc::channel::<(usize, Extracted)>();
+    let sidecars = SidecarCache::default();
+
+    //each batch is merged into the library as it is committed, the folder goes in with the first one
+    let batch_changes = |songs: &[Song], albums: &HashMap<Uuid, Album>, artists: &HashMap<Uuid, Artist>| {
+        let mut changes = LibraryChanges::with_songs(songs, albums, artists);
+        changes.folders.insert(folder_id, library_root.clone());
+        changes.removed_folders = absorbed.clone();
+        changes
+    };
+
+    let mut tx = thread::scope(|scope| {
+        for _ in 0..workers {
+            let result_tx = result_tx.clone();
+            let job_rx = &job_rx;
+            let settings = &settings;
+            let sidecars = &sidecars;
+
+            scope.spawn(move || loop {
+                let next = job_rx.lock().unwrap().recv();
+                let (index, existing_entry) = match next {
+                    Ok(n) => n,
+                    Err(_) => break,
+                };
+
+                let extracted = extract_file(&files[index], existing_entry, settings, sidecars);
+                if result_tx.send((index, extracted)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_tx);
+
+        //only keep a few files per worker queued so a cancel doesnt have to wait for the whole library
+        let send_job = |index: usize| {
+            let existing_entry = existing.get(&files[index]).copied();
+            let _ = job_tx.send((index, existing_entry));
         };
 
-        for entry_result in entries {
-            let entry = match entry_result {
-                Ok(e) => e,
-                Err(e) => {
-                    println!("failed to read directory entry: {}", e);
-                    continue;
+        let mut next_job = 0;
+        while next_job < files.len().min(workers * IN_FLIGHT_PER_WORKER) {
+            send_job(next_job);
+            next_job += 1;
+        }
+
+        let mut pending: BTreeMap<usize, Extracted> = BTreeMap::new();
+        let mut next_write = 0;
+
+        while next_write < files.len() {
+            if is_cancelled() {
+                report.cancelled = true;
+                break;
+            }
+
+            match result_rx.recv() {
+                Ok((index, extracted)) => {
+                    pending.insert(index, extracted);
                 }
-            };
+                Err(_) => break,
+            }
 
-            let path = entry.path();
+            while let Some(extracted) = pending.remove(&next_write) {
+                let path = &files[next_write];
+                let existing_entry = existing.get(path).copied();
+                let outcome = resolve_file(&*tx, extracted, existing_entry, &mut albums, &mut artists, &mut known_artists, &covers, folder_id);
+                let outcome = save_outcome(&mut *tx, outcome, &artists, &albums);
 
-            if path.is_file() {
-                match parse_file(&path, &mut albums, &mut artists, &mut known_artists, folder_id) {
-                    Ok(song) => {
-                        if let Err(e) = insert_song_to_db(&tx, &song, &artists, &albums) {
-                            println!("Failed to insert song to DB: {}", e);
-                        }
-                        songs.insert(song.id, song);
-                    }
-                    Err(e) => {
-                        println!("metadata extraction failed for {}: {:?}", path.display(), e);
+                //files that are now filtered out are treated as removed
+                if !matches!(outcome, FileOutcome::Skipped) {
+                    if let Some((id, _)) = existing_entry {
+                        seen.insert(id);
                     }
                 }
-            } else if path.is_dir() {
-                dir_queue.push_back(path);
-            } else {
-                println!("Unsupported file type: {}", path.display());
+
+                //a song moved within this folder must not be removed when its old path isnt seen
+                if let FileOutcome::Moved(song) = &outcome {
+                    seen.insert(song.id);
+                }
+
+                report.record(path, &outcome);
+
+                if let FileOutcome::Added(song) | FileOutcome::Updated(song) | FileOutcome::Moved(song) = outcome {
+                    songs.push(song);
+                }
+
+                on_progress(&report, path);
+                next_write += 1;
+
+                if next_write % SCAN_BATCH_SIZE == 0 {
+                    commit_batch(tx, library, batch_changes(&songs, &albums, &artists), folder_id, batches > 0)?;
+                    songs.clear();
+                    batches += 1;
+
+                    tx = match store.begin() {
+                        Ok(tx) => tx,
+                        Err(e) => return Err(format!("failed to start sqlite db transaction: {e}")),
+                    };
+                }
+
+                if next_job < files.len() {
+                    send_job(next_job);
+                    next_job += 1;
+                }
             }
         }
+
+        //closing the queue lets the workers finish, anything they still send back is dropped
+        drop(job_tx);
+        Ok::<_, String>(tx)
+    })?;
+
+    //a cancel can land after the last directory was read, make sure we dont treat the folder as fully walked
+    if is_cancelled() {
+        report.cancelled = true;
     }
 
+    //files that were deleted or moved out of the folder since the last scan
+    let removed: Vec<Uuid> = if report.cancelled {
+        Vec::new()
+    } else {
+        existing
+            .values()
+            .map(|(id, _)| *id)
+            .filter(|id| !seen.contains(id))
+            .collect()
+    };
 
-    // commit the transaction
-    if let Err(e) = tx.commit() {
-        println!("Failed to commit scan transaction: {}", e);
-        return;
+    for id in &removed {
+        if let Err(e) = tx.delete_song(*id) {
+            return Err(format!("failed to remove missing song {id}: {e}"));
+        }
+        report.files_removed += 1;
     }
 
-    // update in-memory state
-    let mut state = state.lock().unwrap();
-    state.songs = songs;
-    state.albums = albums;
-    state.artist_manager.artists = artists;
-    state.artist_manager.known_artists = known_artists;
+    //changed tags or removed files can leave albums and artists without any songs
+    let (orphan_albums, orphan_artists) = match tx.prune_orphans() {
+        Ok(o) => o,
+        Err(e) => {
+            return Err(format!("failed to prune orphaned albums and artists: {e}"));
+        }
+    };
 
-    
+    //the report is kept even when the scan was cancelled so the problem files found so far can still be shown
+    if let Err(e) = tx.save_scan_report(folder_id, &report) {
+        println!("failed to save scan report for folder {folder_id}: {e}");
+    }
+
+    //only the part of the library that was walked changes, songs from other folders stay as they are
+    let mut changes = batch_changes(&songs, &albums, &artists);
+    changes.removed_albums = orphan_albums;
+    changes.removed_artists = orphan_artists;
+
+    commit_batch(tx, library, changes, folder_id, batches > 0)?;
+
+    println!(
+        "scanned {} directories: {} added, {} updated, {} unchanged, {} removed, {} skipped, {} failed",
+        report.dirs_visited,
+        report.files_added,
+        report.files_updated,
+        report.files_unchanged,
+        report.files_removed,
+        report.files_skipped,
+        report.files_failed
+    );
+
+    Ok(report)
 }
 
+//applies a batch of created, modified, removed or renamed paths reported by the filesystem watcher
+//a rename shows up as the old path disappearing and the new one appearing
+pub fn apply_changes(paths: &[PathBuf], state: &Mutex<MusicLibrary>) -> Result<ScanReport, String> {
+    let mut report = ScanReport::default();
+    let mut known_artists: HashMap<Uuid, ArtistType> = HashMap::new();
 
-pub fn remove_folder(state: State<AppState>, id: i64) {
-    let dir = db_dir();
-    let mut conn = match Connection::open(&dir) {
-        Ok(c) => c,
+    let (settings, folders, covers, store) = {
+        let state = state.lock().unwrap();
+        (state.scan_settings.clone(), state.folders.clone(), state.covers.clone(), state.store.clone())
+    };
+
+    let mut tx = match store.begin() {
+        Ok(tx) => tx,
         Err(e) => {
-            println!("Failed to open sqlite db: {}", e);
-            return;
+            return Err(format!("failed to start sqlite db transaction: {}", e));
         }
     };
 
-    if let Err(e) = init_db(&conn) {
-        println!("Failed to initialize database schema: {}", e);
-        return;
-    }
+    let mut albums = match tx.albums() {
+        Ok(a) => a,
+        Err(e) => {
+            println!("failed to read albums from db, continuing with limited information {e}");
+            HashMap::new()
+        }
+    };
 
-    let tx = match conn.transaction() {
-        Ok(tx) => tx,
+    let mut artists = match tx.artists() {
+        Ok(a) => a,
         Err(e) => {
-            println!("Failed to start transaction: {}", e);
-            return;
+            println!("failed to get all artists from db, continuing with limited information {e}");
+            HashMap::new()
         }
     };
 
-    let mut songs_to_delete = Vec::new();
-    let mut albums_to_check = HashSet::new();
-    let mut artists_to_check = HashSet::new();
+    let mut changed: Vec<Song> = Vec::new();
+    let mut removed: Vec<Uuid> = Vec::new();
+    let sidecars = SidecarCache::default();
 
-    // Get all affected data in one pass
-    {
-        let mut stmt = match tx.prepare("SELECT id, album_id, artist_id FROM songs WHERE folder_id = ?1") {
-            Ok(stmt) => stmt,
-            Err(e) => {
-                println!("Failed to prepare statement: {}", e);
-                return;
-            }
-        };
-        
-        let rows = match stmt.query_map([id], |row| {
-            let song_id: String = row.get(0)?;
-            let album_id: String = row.get(1)?;
-            let artist_id: String = row.get(2)?;
-            
-            Ok((
-                Uuid::parse_str(&song_id).map_err(|_| rusqlite::Error::InvalidColumnType(0, "song_id".to_string(), rusqlite::types::Type::Text))?,
-                Uuid::parse_str(&album_id).map_err(|_| rusqlite::Error::InvalidColumnType(1, "album_id".to_string(), rusqlite::types::Type::Text))?,
-                Uuid::parse_str(&artist_id).map_err(|_| rusqlite::Error::InvalidColumnType(2, "artist_id".to_string(), rusqlite::types::Type::Text))?,
-            ))
-        }) {
-            Ok(rows) => rows,
-            Err(e) => {
-                println!("Failed to execute query: {}", e);
-                return;
-            }
+    for path in paths {
+        let (folder_id, folder) = match owning_folder(&folders, path) {
+            Some(f) => f,
+            None => continue,
         };
 
-        for row in rows {
-            match row {
-                Ok((song_id, album_id, artist_id)) => {
-                    songs_to_delete.push(song_id);
-                    albums_to_check.insert(album_id);
-                    artists_to_check.insert(artist_id);
-                }
-                Err(e) => {
-                    println!("Error processing row: {}", e);
-                    return;
-                }
+        //a path that a full scan would skip, anything imported from there before is dropped
+        if path.exists() && is_excluded(folder, path, &settings) {
+            match tx.song_ids_under_path(path) {
+                Ok(ids) => removed.extend(ids),
+                Err(e) => println!("failed to look up ignored path {}: {e}", path.display()),
             }
+            continue;
         }
-    }
 
-    //get orphaned albums/artists before deletion
-    let mut albums_to_delete = Vec::new();
-    let mut artists_to_delete = Vec::new();
+        if path.exists() {
+            //a directory that was moved or copied in is walked like a small scan
+            let files = if path.is_dir() {
+                walk_parallel(folder, path, &settings, 1, || false, |_| {}).files
+            } else {
+                vec![path.clone()]
+            };
 
-    //check which albums will become orphaned
-    for album_id in &albums_to_check {
-        let count: i32 = match tx.query_row(
-            "SELECT COUNT(*) FROM songs WHERE album_id = ?1 AND folder_id != ?2",
-            [album_id.to_string(), id.to_string()],
-            |row| row.get(0)
-        ) {
-            Ok(count) => count,
-            Err(e) => {
-                println!("Failed to check album orphan status: {}", e);
-                return;
-            }
-        };
-        
-        if count == 0 {
-            albums_to_delete.push(*album_id);
-        }
-    }
+            for file in files {
+                let existing = match tx.song_stats_by_path(&file) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        println!("failed to look up {} in db: {e}", file.display());
+                        continue;
+                    }
+                };
 
-    //check which artists will become orphaned
-    for artist_id in &artists_to_check {
-        let song_count: i32 = match tx.query_row(
-            "SELECT COUNT(*) FROM songs WHERE artist_id = ?1 AND folder_id != ?2",
-            [artist_id.to_string(), id.to_string()],
-            |row| row.get(0)
-        ) {
-            Ok(count) => count,
-            Err(e) => {
-                println!("Failed to check artist song count: {}", e);
-                return;
-            }
-        };
-        
-        //check if artist has album associations with albums that wont be deleted
-        let album_count: i32 = match tx.query_row(
-            "SELECT COUNT(*) FROM album_artists aa 
-             WHERE aa.artist_id = ?1 
-             AND aa.album_id NOT IN (
-                 SELECT DISTINCT album_id FROM songs WHERE folder_id = ?2
-             )",
-            [artist_id.to_string(), id.to_string()],
-            |row| row.get(0)
-        ) {
-            Ok(count) => count,
-            Err(e) => {
-                println!("Failed to check artist album count: {}", e);
-                return;
+                let outcome = scan_file(&*tx, &file, existing, &settings, &sidecars, &mut albums, &mut artists, &mut known_artists, &covers, folder_id);
+                let outcome = save_outcome(&mut *tx, outcome, &artists, &albums);
+                report.record(&file, &outcome);
+
+                match outcome {
+                    FileOutcome::Added(song) | FileOutcome::Updated(song) | FileOutcome::Moved(song) => {
+                        changed.push(song);
+                    }
+                    FileOutcome::Skipped => {
+                        if let Some((id, _)) = existing {
+                            removed.push(id);
+                        }
+                    }
+                    _ => {}
+                }
             }
-        };
-        
-        let feature_count: i32 = match tx.query_row(
-            "SELECT COUNT(*) FROM song_features sf 
-             JOIN songs s ON sf.song_id = s.id 
-             WHERE sf.artist_id = ?1 AND s.folder_id != ?2",
-            [artist_id.to_string(), id.to_string()],
-            |row| row.get(0)
-        ) {
-            Ok(count) => count,
-            Err(e) => {
-                println!("Failed to check artist feature count: {}", e);
-                return;
+        } else {
+            match tx.song_ids_under_path(path) {
+                Ok(ids) => removed.extend(ids),
+                Err(e) => println!("failed to look up removed path {}: {e}", path.display()),
             }
-        };
-
-        println!("Artist {}: songs={}, albums={}, features={}", 
-                 artist_id, song_count, album_count, feature_count);
-
-        if song_count == 0 && album_count == 0 && feature_count == 0 {
-            artists_to_delete.push(*artist_id);
-            println!("Marking artist {} for deletion", artist_id);
         }
     }
 
-    //do all the deletions
-    if let Err(e) = tx.execute("DELETE FROM song_features WHERE song_id IN (SELECT id FROM songs WHERE folder_id = ?1)", [id]) {
-        println!("Failed to delete song features: {}", e);
-        return;
-    }
+    //the old path of a moved file is reported as removed too, dont delete the song that now lives at the new path
+    removed.retain(|id| !changed.iter().any(|song| song.id == *id));
+    removed.sort();
+    removed.dedup();
 
-    if let Err(e) = tx.execute("DELETE FROM songs WHERE folder_id = ?1", [id]) {
-        println!("Failed to delete songs: {}", e);
-        return;
+    for id in &removed {
+        if let Err(e) = tx.delete_song(*id) {
+            return Err(format!("failed to remove missing song {id}: {e}"));
+        }
+        report.files_removed += 1;
     }
-    
-    for album_id in &albums_to_delete {
-        if let Err(e) = tx.execute("DELETE FROM albums WHERE id = ?1", [album_id.to_string()]) {
-            println!("Failed to delete album {}: {}", album_id, e);
-            return;
+
+    let (orphan_albums, orphan_artists) = match tx.prune_orphans() {
+        Ok(o) => o,
+        Err(e) => {
+            return Err(format!("failed to prune orphaned albums and artists: {e}"));
         }
-        if let Err(e) = tx.execute("DELETE FROM album_artists WHERE album_id = ?1", [album_id.to_string()]) {
-            println!("Failed to delete album artists for {}: {}", album_id, e);
-            return;
+    };
+
+    let mut changes = LibraryChanges::with_songs(&changed, &albums, &artists);
+    changes.removed_albums = orphan_albums;
+    changes.removed_artists = orphan_artists;
+    changes.album_tags = match tx.update_album_tags() {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(format!("failed to update album tags: {e}"));
         }
+    };
+
+    let mut state = state.lock().unwrap();
+
+    if let Err(e) = tx.commit() {
+        return Err(format!("Failed to commit watcher transaction: {}", e));
     }
-    
-    for artist_id in &artists_to_delete {
-        if let Err(e) = tx.execute("DELETE FROM artists WHERE id = ?1", [artist_id.to_string()]) {
-            println!("Failed to delete artist {}: {}", artist_id, e);
-            return;
+
+    state.merge_changes(changes);
+
+    Ok(report)
+}
+
+//removes a library folder, songs that are also inside another library folder are handed over to it instead of deleted
+pub fn remove_folder(state: &Mutex<MusicLibrary>, id: i64) -> Result<(), String> {
+    let (store, mut remaining) = {
+        let state = state.lock().unwrap();
+        (state.store.clone(), state.folders.clone())
+    };
+    remaining.remove(&id);
+
+    let mut tx = store.begin().map_err(|e| format!("failed to start transaction: {e}"))?;
+
+    let songs = tx
+        .song_paths_in_folder(id)
+        .map_err(|e| format!("failed to load songs of folder {id}: {e}"))?;
+
+    let mut songs_to_delete = Vec::new();
+    let mut songs_to_move = Vec::new();
+
+    for (song_id, path) in songs {
+        match owning_folder(&remaining, &path) {
+            Some((other, _)) => {
+                tx.set_song_folder(song_id, other)
+                    .map_err(|e| format!("failed to move song {song_id} to folder {other}: {e}"))?;
+                songs_to_move.push((song_id, other));
+            }
+            None => {
+                tx.delete_song(song_id)
+                    .map_err(|e| format!("failed to delete song {song_id}: {e}"))?;
+                songs_to_delete.push(song_id);
+            }
         }
     }
-    
-    if let Err(e) = tx.execute("DELETE FROM folders WHERE id = ?1", [id]) {
-        println!("Failed to delete folder: {}", e);
-        return;
-    }
-    
-    //commit transaction
-    if let Err(e) = tx.commit() {
-        println!("Failed to commit transaction: {}", e);
-        return;
-    }
+
+    tx.delete_folder(id).map_err(|e| format!("failed to delete folder {id}: {e}"))?;
+
+    let (albums_to_delete, artists_to_delete) = tx
+        .prune_orphans()
+        .map_err(|e| format!("failed to prune orphaned albums and artists: {e}"))?;
+
+    //the albums of deleted songs lose their year, genres and so on if no other song has them
+    let album_tags = tx
+        .update_album_tags()
+        .map_err(|e| format!("failed to update album tags: {e}"))?;
 
     //update in-memory state
     {
         let mut state = state.lock().unwrap();
-        
-        for song_id in &songs_to_delete {
-            state.songs.remove(song_id);
+
+        //commit transaction
+        tx.commit().map_err(|e| format!("failed to commit transaction: {e}"))?;
+
+        for (album_id, tags) in &album_tags {
+            if let Some(album) = state.albums.get_mut(album_id) {
+                tags.apply(album);
+            }
         }
-        
+
         for album_id in &albums_to_delete {
             state.albums.remove(album_id);
         }
-        
+
         for artist_id in &artists_to_delete {
             state.artist_manager.artists.remove(artist_id);
             state.artist_manager.known_artists.remove(artist_id);
         }
-        
+
         state.folders.remove(&id);
-        
-        println!("updated in-memory state: removed {} songs, {} albums, {} artists", 
-                 songs_to_delete.len(), 
-                 albums_to_delete.len(), 
+
+        println!("updated in-memory state: removed {} songs, kept {} covered by other folders, removed {} albums, {} artists",
+                 songs_to_delete.len(),
+                 songs_to_move.len(),
+                 albums_to_delete.len(),
                  artists_to_delete.len());
     }
 
     println!("Successfully removed folder '{}'", id);
+    Ok(())
 }
 
-fn parse_duration_to_seconds(duration_str: &str) -> Result<f64, String> {
-    let parts: Vec<&str> = duration_str.split(':').collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use crate::store::{LibraryStore, MemoryStore, SqliteStore};
+    use crate::core::fixtures;
+
+    #[test]
+    fn test_duplicate_artists_resolve_to_lowest_id() {
+        let mut artists = HashMap::new();
+        let mut known_artists = HashMap::new();
+
+        let mut ids: Vec<Uuid> = (0..8).map(|_| Uuid::new_v4()).collect();
+        for id in &ids {
+            artists.insert(*id, Artist { id: *id, ..fixtures::artist("Russ") });
+        }
+        ids.sort();
+
+        assert_eq!(find_or_create_artist(&mut artists, &mut known_artists, "Russ"), ids[0]);
+        assert_eq!(artists.len(), 8);
+        assert!(known_artists.is_empty());
+    }
+
+    #[test]
+    fn test_find_overlap() {
+        let folders = HashMap::from([
+            (1, PathBuf::from("/music")),
+            (2, PathBuf::from("/podcasts/news")),
+            (3, PathBuf::from("/podcasts/tech")),
+        ]);
+
+        assert_eq!(find_overlap(&folders, Path::new("/music")), None);
+        assert_eq!(find_overlap(&folders, Path::new("/musicals")), None);
+        assert_eq!(
+            find_overlap(&folders, Path::new("/music/jazz")),
+            Some(FolderOverlap::Inside { id: 1, path: PathBuf::from("/music") })
+        );
+        assert_eq!(
+            find_overlap(&folders, Path::new("/podcasts")),
+            Some(FolderOverlap::Contains {
+                folders: vec![(2, PathBuf::from("/podcasts/news")), (3, PathBuf::from("/podcasts/tech"))]
+            })
+        );
+    }
+
+    //writes an mp3 with an id3v2.3 tag holding title, artist and album followed by silent mpeg frames
+    fn write_mp3(path: &Path, title: &str, artist: &str, album: &str) {
+        write_tagged_mp3(path, &[(b"TIT2", title), (b"TPE1", artist), (b"TALB", album)]);
+    }
+
+    fn write_tagged_mp3(path: &Path, tags: &[(&[u8; 4], &str)]) {
+        let mut frames = Vec::new();
+        for (id, text) in tags {
+            frames.extend_from_slice(*id);
+            frames.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+            frames.extend_from_slice(&[0, 0, 0]);
+            frames.extend_from_slice(text.as_bytes());
+        }
+
+        //the tag size is stored as a syncsafe integer, 7 bits per byte
+        let size = frames.len() as u32;
+        let mut data = b"ID3\x03\x00\x00".to_vec();
+        data.extend([(size >> 21) as u8 & 0x7F, (size >> 14) as u8 & 0x7F, (size >> 7) as u8 & 0x7F, size as u8 & 0x7F]);
+        data.extend(frames);
+
+        //mpeg 1 layer 3, 128kbps, 44.1khz, 417 bytes per frame
+        for _ in 0..20 {
+            data.extend([0xFF, 0xFB, 0x90, 0x64]);
+            data.extend([0; 413]);
+        }
+
+        fs::write(path, data).unwrap();
+    }
+
+    fn library_with_store(store: Arc<dyn LibraryStore>, covers: &Path) -> Mutex<MusicLibrary> {
+        let (sender, receiver) = mpsc::channel();
+        let player = crate::core::audio::PlayerController::new(sender, receiver);
+
+        Mutex::new(MusicLibrary::from_store(store, CoverCache::new(covers), player))
+    }
+
+    fn open_library(path: &Path) -> (Arc<dyn LibraryStore>, Mutex<MusicLibrary>) {
+        let store: Arc<dyn LibraryStore> = Arc::new(SqliteStore::new(Database::open(path).unwrap()));
+        let library = library_with_store(store.clone(), &path.with_file_name("covers"));
+
+        (store, library)
+    }
+
+    #[test]
+    fn test_merging_changes_keeps_the_rest_of_the_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = library_with_store(Arc::new(MemoryStore::default()), dir.path());
+        let mut library = library.lock().unwrap();
+
+        let kept = fixtures::artist("Band");
+        let removed = fixtures::artist("Trio");
+        for artist in [&kept, &removed] {
+            library.artist_manager.known_artists.insert(artist.id, ArtistType::KnownArtist(artist.id));
+            library.artist_manager.artists.insert(artist.id, artist.clone());
+        }
 
-    if parts.len() != 3 {
-        return Err(format!("invalid format: '{}'. expected HH:MM:SS.ms", duration_str));
+        let added = fixtures::artist("Duo");
+        library.merge_changes(LibraryChanges {
+            artists: HashMap::from([(added.id, added.clone())]),
+            removed_artists: vec![removed.id],
+            ..Default::default()
+        });
+
+        let mut known: Vec<Uuid> = library.artist_manager.known_artists.keys().copied().collect();
+        let mut expected = vec![kept.id, added.id];
+        known.sort();
+        expected.sort();
+        assert_eq!(known, expected);
+        assert_eq!(library.artist_manager.artists.len(), 2);
     }
 
-    let hours = parts[0]
-        .parse::<u64>()
-        .map_err(|e| format!("invalid hours part in '{}': {}", duration_str, e))?;
+    #[test]
+    fn test_scan_commits_in_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let music = dir.path().join("music");
+        fs::create_dir_all(&music).unwrap();
+        for i in 0..=SCAN_BATCH_SIZE {
+            write_mp3(&music.join(format!("{i:03}.mp3")), &format!("Song {i}"), "Band", "Loud");
+        }
+
+        let (store, library) = open_library(&dir.path().join("library.db"));
+
+        //by the time the last file is written the ones before it are already in the database and the library
+        let mut seen_mid_scan = None;
+        let report = scan_folder(&music, &library, || false, |report, _| {
+            if report.files_added == SCAN_BATCH_SIZE + 1 {
+                seen_mid_scan = Some((store.songs().unwrap().len(), library.lock().unwrap().albums.len()));
+            }
+        })
+        .unwrap();
+
+        assert_eq!(report.files_added, SCAN_BATCH_SIZE + 1, "{:?}", report.errors);
+        assert_eq!(seen_mid_scan, Some((SCAN_BATCH_SIZE, 1)));
+        assert_eq!(store.songs().unwrap().len(), SCAN_BATCH_SIZE + 1);
+        assert_eq!(library.lock().unwrap().folders.len(), 1);
+    }
+
+    #[test]
+    fn test_scanning_second_folder_keeps_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let rock = dir.path().join("rock");
+        let jazz = dir.path().join("jazz");
+        fs::create_dir_all(&rock).unwrap();
+        fs::create_dir_all(&jazz).unwrap();
+        write_mp3(&rock.join("1.mp3"), "Song A", "Band", "Loud");
+        write_mp3(&rock.join("2.mp3"), "Song B", "Band", "Loud");
+        write_mp3(&jazz.join("1.mp3"), "Song C", "Trio", "Smooth");
+
+        let (store, library) = open_library(&dir.path().join("library.db"));
+
+        let report = scan_folder(&rock, &library, || false, |_, _| {}).unwrap();
+        assert_eq!(report.files_added, 2, "{:?}", report.errors);
+
+        let report = scan_folder(&jazz, &library, || false, |_, _| {}).unwrap();
+        assert_eq!(report.files_added, 1, "{:?}", report.errors);
 
-    let minutes = parts[1]
-        .parse::<u64>()
-        .map_err(|e| format!("invalid minutes part in '{}': {}", duration_str, e))?;
+        {
+            let library = library.lock().unwrap();
+            assert_eq!(store.songs().unwrap().len(), 3);
+            assert_eq!(library.albums.len(), 2);
+            assert_eq!(library.artist_manager.artists.len(), 2);
+            assert_eq!(library.artist_manager.known_artists.len(), 2);
+            assert_eq!(library.folders.len(), 2);
+        }
+
+        //rescanning the first folder after a file was deleted only drops that file
+        fs::remove_file(rock.join("2.mp3")).unwrap();
+        let report = scan_folder(&rock, &library, || false, |_, _| {}).unwrap();
+        assert_eq!((report.files_unchanged, report.files_removed), (1, 1));
 
-    let seconds_ms_parts: Vec<&str> = parts[2].split('.').collect();
+        let songs = store.songs().unwrap();
+        let mut titles: Vec<&str> = songs.values().map(|s| s.title.as_str()).collect();
+        titles.sort();
+        assert_eq!(titles, vec!["Song A", "Song C"]);
 
-    let seconds = seconds_ms_parts[0]
-        .parse::<u64>()
-        .map_err(|e| format!("invalid seconds part in '{}': {}", duration_str, e))?;
+        //the in-memory albums match what a restart would load
+        let library = library.lock().unwrap();
+        assert_eq!(library.albums.len(), 2);
+        assert_eq!(store.albums().unwrap().len(), library.albums.len());
+    }
 
-    let mut milliseconds: f64 = 0.0;
-    if seconds_ms_parts.len() > 1 && !seconds_ms_parts[1].is_empty() {
-        let ms_str = seconds_ms_parts[1];
-        let ms_value = ms_str
-            .parse::<f64>()
-            .map_err(|e| format!("Invalid milliseconds part in '{}': {}", duration_str, e))?;
-        
-        milliseconds = ms_value / (10.0f64.powi(ms_str.len() as i32));
+    #[test]
+    fn test_rescan_picks_up_cover_added_to_album() {
+        let dir = tempfile::tempdir().unwrap();
+        let music = dir.path().join("music");
+        fs::create_dir_all(&music).unwrap();
+        write_mp3(&music.join("1.mp3"), "Song A", "Band", "Loud");
+
+        let (store, library) = open_library(&dir.path().join("library.db"));
+        let report = scan_folder(&music, &library, || false, |_, _| {}).unwrap();
+        assert_eq!(report.files_added, 1, "{:?}", report.errors);
+        assert!(library.lock().unwrap().albums.values().all(|a| a.cover.is_none()));
+
+        //the image turns up together with the next track of the album
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(8, 8)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        fs::write(music.join("cover.png"), png).unwrap();
+        write_mp3(&music.join("2.mp3"), "Song B", "Band", "Loud");
+        let report = scan_folder(&music, &library, || false, |_, _| {}).unwrap();
+        assert_eq!((report.files_added, report.files_unchanged), (1, 1), "{:?}", report.errors);
+
+        let library = library.lock().unwrap();
+        assert_eq!(library.albums.len(), 1);
+        let album = library.albums.values().next().unwrap();
+        assert!(album.cover.is_some());
+        assert!(album.palette.is_some());
+        assert_eq!(album.cover_source, Some(CoverSource::Sidecar(music.join("cover.png"))));
+
+        //and is saved, not only merged into the library
+        let saved = &store.albums().unwrap()[&album.id];
+        assert_eq!((&saved.cover, &saved.cover_source), (&album.cover, &album.cover_source));
     }
 
-    //calculating the seconds needed
-    let total_seconds = (hours as f64 * 3600.0) +
-                        (minutes as f64 * 60.0) +
-                        (seconds as f64) +
-                        milliseconds;
+    #[test]
+    fn test_sort_name_tags_order_artists_and_albums() {
+        let dir = tempfile::tempdir().unwrap();
+        let music = dir.path().join("music");
+        fs::create_dir_all(&music).unwrap();
+        write_tagged_mp3(&music.join("1.mp3"), &[
+            (b"TIT2", "1999"),
+            (b"TPE1", "Prince"),
+            (b"TALB", "1999"),
+            (b"TSOP", "Prince"),
+            (b"TSOA", "Nineteen Ninety-Nine"),
+        ]);
+        write_tagged_mp3(&music.join("2.mp3"), &[
+            (b"TIT2", "Heroes"),
+            (b"TPE1", "David Bowie"),
+            (b"TALB", "Heroes"),
+            (b"TSOP", "Bowie, David"),
+        ]);
+        write_mp3(&music.join("3.mp3"), "Believe", "Cher", "Believe");
+
+        let (store, library) = open_library(&dir.path().join("library.db"));
+        let report = scan_folder(&music, &library, || false, |_, _| {}).unwrap();
+        assert_eq!(report.files_added, 3, "{:?}", report.errors);
+
+        let library = library.lock().unwrap();
+        let artist_names: Vec<&str> = store.artist_order().unwrap().iter()
+            .map(|id| library.artist_manager.artists[id].name.as_str())
+            .collect();
+        assert_eq!(artist_names, vec!["David Bowie", "Cher", "Prince"]);
+
+        let album_names: Vec<&str> = store.album_order().unwrap().iter()
+            .map(|id| library.albums[id].title.as_str())
+            .collect();
+        assert_eq!(album_names, vec!["Believe", "Heroes", "1999"]);
+
+        //the tags only change the order, names are shown as they are
+        let bowie = library.artist_manager.artists.values().find(|a| a.name == "David Bowie").unwrap();
+        assert_eq!(bowie.sort_name.as_deref(), Some("Bowie, David"));
+    }
 
-    Ok(total_seconds)
+    #[test]
+    fn test_album_artist_sort_tag_from_another_artists_track() {
+        let dir = tempfile::tempdir().unwrap();
+        let music = dir.path().join("music");
+        fs::create_dir_all(&music).unwrap();
+        write_tagged_mp3(&music.join("1.mp3"), &[
+            (b"TIT2", "One More Time"),
+            (b"TPE1", "Daft Punk"),
+            (b"TPE2", "Daft Punk"),
+            (b"TALB", "Discovery"),
+        ]);
+        write_tagged_mp3(&music.join("2.mp3"), &[
+            (b"TIT2", "Too Long"),
+            (b"TPE1", "Romanthony"),
+            (b"TPE2", "Daft Punk"),
+            (b"TALB", "Discovery"),
+            (b"TSO2", "Punk, Daft"),
+        ]);
+
+        let (store, library) = open_library(&dir.path().join("library.db"));
+        let report = scan_folder(&music, &library, || false, |_, _| {}).unwrap();
+        assert_eq!(report.files_added, 2, "{:?}", report.errors);
+
+        //the album artist gets it even though the track was by someone else
+        let sort_names = |artists: &HashMap<Uuid, Artist>| {
+            let mut names: Vec<(String, Option<String>)> = artists.values().map(|a| (a.name.clone(), a.sort_name.clone())).collect();
+            names.sort();
+            names
+        };
+        let expected = vec![("Daft Punk".to_string(), Some("Punk, Daft".to_string())), ("Romanthony".to_string(), None)];
+        assert_eq!(sort_names(&library.lock().unwrap().artist_manager.artists), expected);
+        assert_eq!(sort_names(&store.artists().unwrap()), expected);
+    }
+
+    #[test]
+    fn test_scan_reads_detailed_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let music = dir.path().join("music");
+        fs::create_dir_all(&music).unwrap();
+        write_tagged_mp3(&music.join("1.mp3"), &[
+            (b"TIT2", "Little Red Corvette"),
+            (b"TPE1", "Prince"),
+            (b"TALB", "1999"),
+            (b"TYER", "1982"),
+            (b"TCON", "Funk; Pop"),
+            (b"TCOM", "Prince"),
+            (b"TRCK", "5/11"),
+            (b"TBPM", "123"),
+            (b"TPUB", "Warner Bros."),
+        ]);
+
+        let (store, library) = open_library(&dir.path().join("library.db"));
+        let report = scan_folder(&music, &library, || false, |_, _| {}).unwrap();
+        assert_eq!(report.files_added, 1, "{:?}", report.errors);
+
+        let song = store.songs().unwrap().into_values().next().unwrap();
+        assert_eq!((song.year, song.bpm), (Some(1982), Some(123)));
+        assert_eq!(song.genres, vec!["Funk", "Pop"]);
+        assert_eq!(song.composer.as_deref(), Some("Prince"));
+
+        let library = library.lock().unwrap();
+        let album = &library.albums[&song.album];
+        assert_eq!((album.year, album.track_total, album.label.as_deref()), (Some(1982), Some(11), Some("Warner Bros.")));
+        assert_eq!(album.genres, song.genres);
+    }
+
+    #[test]
+    fn test_remove_folder_hands_songs_to_outer_folder() {
+        let store = Arc::new(MemoryStore::default());
+        let artist = fixtures::artist("Band");
+        let album = fixtures::album("Loud", &artist);
+        let artists = HashMap::from([(artist.id, artist.clone())]);
+        let albums = HashMap::from([(album.id, album.clone())]);
+
+        let mut tx = store.begin().unwrap();
+        let music = tx.insert_folder(Path::new("/music")).unwrap();
+        let rock = tx.insert_folder(Path::new("/mnt/rock")).unwrap();
+        let kept = fixtures::song(music, "/music/a.mp3", &artist, &album);
+        let dropped = fixtures::song(rock, "/mnt/rock/b.mp3", &artist, &album);
+        tx.save_song(&kept, &artists, &albums).unwrap();
+        tx.save_song(&dropped, &artists, &albums).unwrap();
+        tx.commit().unwrap();
+
+        let library = library_with_store(store.clone(), Path::new("/nonexistent"));
+        assert_eq!(store.songs().unwrap().len(), 2);
+
+        remove_folder(&library, rock).unwrap();
+
+        let songs = store.songs().unwrap();
+        assert_eq!(songs.keys().collect::<Vec<_>>(), vec![&kept.id]);
+        assert_eq!(songs[&kept.id].folder_id, music);
+
+        let library = library.lock().unwrap();
+        assert_eq!(library.folders.keys().collect::<Vec<_>>(), vec![&music]);
+        assert_eq!(library.albums.len(), 1);
+    }
 }
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::core::song::{Album, Artist};
+use crate::SongToSend;
+
+//the most results of each kind a search returns
+pub const MAX_SEARCH_RESULTS: usize = 50;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchKind {
+    Song,
+    Album,
+    Artist,
+}
+
+impl SearchKind {
+    //how the kind is stored in search_docs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchKind::Song => "song",
+            SearchKind::Album => "album",
+            SearchKind::Artist => "artist",
+        }
+    }
+}
+
+//what the store found, best match first. albums and artists are only ids since the library keeps those in memory
+#[derive(Default)]
+pub struct SearchMatches {
+    pub top_hit: Option<(SearchKind, Uuid)>,
+    pub songs: Vec<SongToSend>,
+    pub albums: Vec<Uuid>,
+    pub artists: Vec<Uuid>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", content = "item", rename_all = "snake_case")]
+pub enum TopHit {
+    Song(SongToSend),
+    Album(Album),
+    Artist(Artist),
+}
+
+#[derive(Serialize, Default)]
+pub struct SearchResults {
+    pub top_hit: Option<TopHit>,
+    pub songs: Vec<SongToSend>,
+    pub albums: Vec<Album>,
+    pub artists: Vec<Artist>,
+}
+
+impl SearchResults {
+    //ids the in-memory library doesnt know yet, like ones a running scan hasnt merged, are left out
+    pub fn new(matches: SearchMatches, albums: &HashMap<Uuid, Album>, artists: &HashMap<Uuid, Artist>) -> Self {
+        let top_hit = match matches.top_hit {
+            Some((SearchKind::Song, id)) => matches.songs.iter().find(|s| s.id == id).cloned().map(TopHit::Song),
+            Some((SearchKind::Album, id)) => albums.get(&id).cloned().map(TopHit::Album),
+            Some((SearchKind::Artist, id)) => artists.get(&id).cloned().map(TopHit::Artist),
+            None => None,
+        };
+
+        SearchResults {
+            top_hit,
+            songs: matches.songs,
+            albums: matches.albums.iter().filter_map(|id| albums.get(id).cloned()).collect(),
+            artists: matches.artists.iter().filter_map(|id| artists.get(id).cloned()).collect(),
+        }
+    }
+}
+
+//the words typed into the search box, every one of them has to match the start of a word somewhere
+pub fn search_terms(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|t| t.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string())
+        .collect()
+}
+
+//turns the terms into an fts5 query, each one quoted so nothing typed is read as query syntax
+pub fn match_expression(text: &str) -> Option<String> {
+    let terms = search_terms(text);
+    if terms.is_empty() {
+        return None;
+    }
+
+    let quoted: Vec<String> = terms.iter().map(|t| format!("\"{}\"*", t.replace('"', "\"\""))).collect();
+    Some(quoted.join(" "))
+}
+
+//the best scored first result of each kind, lower scores are better like fts5 ranks.
+//on a tie artists win over albums and albums over songs, a name is usually what was meant
+pub fn pick_top_hit(
+    songs: &[(Uuid, f64)],
+    albums: &[(Uuid, f64)],
+    artists: &[(Uuid, f64)],
+) -> Option<(SearchKind, Uuid)> {
+    [
+        (SearchKind::Artist, artists.first()),
+        (SearchKind::Album, albums.first()),
+        (SearchKind::Song, songs.first()),
+    ]
+    .into_iter()
+    .filter_map(|(kind, hit)| hit.map(|(id, score)| (kind, *id, *score)))
+    .min_by(|a, b| a.2.total_cmp(&b.2))
+    .map(|(kind, id, _)| (kind, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_expression_quotes_terms() {
+        assert_eq!(match_expression("  "), None);
+        assert_eq!(match_expression("beyo"), Some("\"beyo\"*".into()));
+        assert_eq!(match_expression("AC/DC \"back"), Some("\"AC/DC\"* \"back\"*".into()));
+        assert_eq!(match_expression("- * ("), None);
+    }
+
+    #[test]
+    fn test_top_hit_prefers_names_on_a_tie() {
+        let song = Uuid::new_v4();
+        let artist = Uuid::new_v4();
+
+        assert_eq!(pick_top_hit(&[(song, -2.0)], &[], &[(artist, -2.0)]), Some((SearchKind::Artist, artist)));
+        assert_eq!(pick_top_hit(&[(song, -3.0)], &[], &[(artist, -2.0)]), Some((SearchKind::Song, song)));
+        assert_eq!(pick_top_hit(&[], &[], &[]), None);
+    }
+}
@@ -1,11 +1,14 @@
 use audiotags::Picture;
 use core::fmt;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock, Weak};
 use uuid::Uuid;
 
 use serde::{Serialize, Deserialize};
 
+use crate::core::palette::Palette;
+
 use std::cell::RefCell;
 
 //tauri requires
@@ -22,12 +25,28 @@ pub struct Song {
     pub path: PathBuf,
     pub duration: f64,
     pub folder_id: i64,
+    pub file_size: u64,
+    pub modified: i64,
+    pub fingerprint: Option<String>,
+    pub year: Option<i32>,
+    pub genres: Vec<String>,
+    pub composer: Option<String>,
+    pub conductor: Option<String>,
+    pub bpm: Option<u16>,
+    pub comment: Option<String>,
+    //album tags as this file has them, the album works its own out from all of its songs
+    pub original_year: Option<i32>,
+    pub track_total: Option<u16>,
+    pub disc_total: Option<u16>,
+    pub label: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Artist {
     pub id: Uuid,
-    pub name: String
+    pub name: String,
+    //from the artist sort tag, "Bowie, David" for David Bowie. sorted by instead of the name when set
+    pub sort_name: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
@@ -60,11 +79,89 @@ impl ArtistType {
 pub struct Album {
     pub id: Uuid,
     pub artists: Vec<(Option<Uuid>, String)>,
-    pub cover: Option<Image>,
+    //hash of the cover in the cover cache, the image itself is fetched separately at the size needed
+    pub cover: Option<String>,
+    pub cover_source: Option<CoverSource>,
+    pub palette: Option<Palette>,
     pub title: String,
+    //from the album sort tag, sorted by instead of the title when set
+    pub sort_name: Option<String>,
+    //release year most of its songs are tagged with
+    pub year: Option<i32>,
+    //year of the first release for reissues and remasters
+    pub original_year: Option<i32>,
+    //every genre its songs are tagged with
+    pub genres: Vec<String>,
+    pub track_total: Option<u16>,
+    pub disc_total: Option<u16>,
+    pub label: Option<String>,
     pub songs: Vec<Uuid>,
 }
 
+//the tags an album takes from its songs, worked out again whenever one of them is saved or removed
+//so a retagged or deleted track doesnt leave its year or genres behind
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlbumTags {
+    pub year: Option<i32>,
+    pub original_year: Option<i32>,
+    pub genres: Vec<String>,
+    pub track_total: Option<u16>,
+    pub disc_total: Option<u16>,
+    pub label: Option<String>,
+}
+
+impl AlbumTags {
+    //the year and label most tracks agree on, the earliest original year, the highest totals
+    //and every genre once in the order the tracks list them
+    pub fn from_songs<'a>(songs: impl IntoIterator<Item = &'a Song>) -> Self {
+        let mut songs: Vec<&Song> = songs.into_iter().collect();
+        songs.sort_by(|a, b| (a.disc_num, a.track_num, &a.path).cmp(&(b.disc_num, b.track_num, &b.path)));
+
+        let mut genres: Vec<String> = Vec::new();
+        for genre in songs.iter().flat_map(|s| &s.genres) {
+            if !genres.iter().any(|g| g.eq_ignore_ascii_case(genre)) {
+                genres.push(genre.clone());
+            }
+        }
+
+        AlbumTags {
+            year: most_common(songs.iter().filter_map(|s| s.year)),
+            original_year: songs.iter().filter_map(|s| s.original_year).min(),
+            genres,
+            track_total: songs.iter().filter_map(|s| s.track_total).max(),
+            disc_total: songs.iter().filter_map(|s| s.disc_total).max(),
+            label: most_common(songs.iter().filter_map(|s| s.label.clone())),
+        }
+    }
+
+    pub fn apply(&self, album: &mut Album) {
+        album.year = self.year;
+        album.original_year = self.original_year;
+        album.genres = self.genres.clone();
+        album.track_total = self.track_total;
+        album.disc_total = self.disc_total;
+        album.label = self.label.clone();
+    }
+}
+
+//ties go to the smallest value so the pick doesnt depend on the order songs were scanned in
+fn most_common<T: Ord>(values: impl Iterator<Item = T>) -> Option<T> {
+    let mut counts: BTreeMap<T, usize> = BTreeMap::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+
+    //max_by_key keeps the last of equal counts, so go from the largest value down
+    counts.into_iter().rev().max_by_key(|(_, count)| *count).map(|(value, _)| value)
+}
+
+//where an album's cover came from
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", content = "path", rename_all = "snake_case")]
+pub enum CoverSource {
+    Embedded,
+    Sidecar(PathBuf),
+}
 
 #[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
 pub struct Image {
@@ -83,10 +180,7 @@ impl PartialEq for Album {
 
 impl fmt::Display for Album {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let cover = match &self.cover {
-            Some(cover) => &cover.extension,
-            None => "none",
-        };
+        let cover = self.cover.as_deref().unwrap_or("none");
 
         writeln!(
             f,
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+//numbers are padded to this many digits so "Track 10" comes after "Track 9"
+const NUMBER_WIDTH: usize = 10;
+
+//leading articles that are skipped when sorting, by language code.
+//ones ending in an apostrophe are joined straight onto the next word
+pub const LANGUAGE_ARTICLES: &[(&str, &[&str])] = &[
+    ("en", &["the", "a", "an"]),
+    ("de", &["der", "die", "das", "ein", "eine"]),
+    ("fr", &["le", "la", "les", "l'", "un", "une"]),
+    ("es", &["el", "la", "los", "las", "un", "una"]),
+    ("it", &["il", "lo", "la", "i", "gli", "le", "l'", "un", "una"]),
+    ("nl", &["de", "het", "een"]),
+    ("pt", &["o", "a", "os", "as", "um", "uma"]),
+];
+
+pub const SORT_SETTINGS_KEY: &str = "sort_settings";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SortSettings {
+    //language codes from LANGUAGE_ARTICLES whose articles are skipped
+    pub languages: Vec<String>,
+    //skipped on top of the ones from the languages
+    pub extra_articles: Vec<String>,
+}
+
+impl Default for SortSettings {
+    fn default() -> Self {
+        SortSettings {
+            languages: vec!["en".to_string()],
+            extra_articles: Vec::new(),
+        }
+    }
+}
+
+impl SortSettings {
+    //folded the same way as the names they are matched against
+    pub fn articles(&self) -> Vec<String> {
+        let mut articles: Vec<String> = LANGUAGE_ARTICLES
+            .iter()
+            .filter(|(code, _)| self.languages.iter().any(|l| l == code))
+            .flat_map(|(_, articles)| articles.iter().map(|a| fold(a)))
+            .chain(self.extra_articles.iter().map(|a| fold(a.trim())))
+            .filter(|a| !a.is_empty())
+            .collect();
+
+        articles.sort();
+        articles.dedup();
+        articles
+    }
+
+    pub fn key(&self, text: &str) -> String {
+        sort_key(text, &self.articles())
+    }
+}
+
+//lowercase with accents taken off, so "Ásgeir" sorts with the a's.
+//letters that dont decompose into a base letter and an accent are spelled out
+fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+
+    for c in text.nfkd().filter(|c| !is_combining_mark(*c)).flat_map(char::to_lowercase) {
+        match c {
+            'ß' => folded.push_str("ss"),
+            'æ' => folded.push_str("ae"),
+            'œ' => folded.push_str("oe"),
+            'þ' => folded.push_str("th"),
+            'ø' => folded.push('o'),
+            'đ' | 'ð' => folded.push('d'),
+            'ł' => folded.push('l'),
+            'ı' => folded.push('i'),
+            '’' | '‘' | 'ʼ' => folded.push('\''),
+            c => folded.push(c),
+        }
+    }
+
+    folded
+}
+
+fn skip_punctuation(text: &str) -> &str {
+    text.trim_start_matches(|c: char| !c.is_alphanumeric())
+}
+
+//only when something is left over, "The The" still sorts as "the"
+fn strip_article<'a>(text: &'a str, articles: &[String]) -> &'a str {
+    for article in articles {
+        let rest = match text.strip_prefix(article.as_str()) {
+            Some(r) => r,
+            None => continue,
+        };
+
+        let rest = if article.ends_with('\'') {
+            rest
+        } else if rest.starts_with(char::is_whitespace) {
+            rest.trim_start()
+        } else {
+            continue;
+        };
+
+        let rest = skip_punctuation(rest);
+        if !rest.is_empty() {
+            return rest;
+        }
+    }
+
+    text
+}
+
+fn pad_numbers(text: &str) -> String {
+    let mut padded = String::with_capacity(text.len());
+    let mut digits = String::new();
+
+    let flush = |digits: &mut String, padded: &mut String| {
+        if digits.is_empty() {
+            return;
+        }
+
+        let trimmed = digits.trim_start_matches('0');
+        let trimmed = if trimmed.is_empty() { "0" } else { trimmed };
+        for _ in trimmed.len()..NUMBER_WIDTH {
+            padded.push('0');
+        }
+        padded.push_str(trimmed);
+        digits.clear();
+    };
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+        } else {
+            flush(&mut digits, &mut padded);
+            padded.push(c);
+        }
+    }
+    flush(&mut digits, &mut padded);
+
+    padded
+}
+
+//what a name is ordered by, compared byte by byte so sqlite can sort on it with a plain index.
+//articles have to be folded already, see SortSettings::articles
+pub fn sort_key(text: &str, articles: &[String]) -> String {
+    let folded = fold(text.trim());
+
+    let name = skip_punctuation(&folded);
+    //a name made only of punctuation keeps it, otherwise it would sort as empty
+    let name = if name.is_empty() { folded.as_str() } else { strip_article(name, articles) };
+
+    pad_numbers(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str], settings: &SortSettings) -> Vec<String> {
+        let mut names: Vec<&str> = names.to_vec();
+        names.sort_by_key(|n| settings.key(n));
+        names.into_iter().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let settings = SortSettings::default();
+
+        assert_eq!(
+            sorted(&["Zola Jesus", "The Beatles", "Ásgeir", "abba", "\"Heroes\"", "Björk"], &settings),
+            vec!["abba", "Ásgeir", "The Beatles", "Björk", "\"Heroes\"", "Zola Jesus"]
+        );
+        assert_eq!(sorted(&["Track 10", "Track 9", "track 1"], &settings), vec!["track 1", "Track 9", "Track 10"]);
+        assert_eq!(settings.key("The The"), "the");
+        assert_eq!(settings.key("Theatre"), "theatre");
+        assert_eq!(settings.key("STRASSE"), settings.key("Straße"));
+        assert_eq!(settings.key("..."), "...");
+    }
+
+    #[test]
+    fn test_articles_follow_languages() {
+        let english = SortSettings::default();
+        let french = SortSettings { languages: vec!["en".into(), "fr".into()], extra_articles: vec!["Los".into()] };
+
+        assert_eq!(english.key("L’Impératrice"), "l'imperatrice");
+        assert_eq!(french.key("L’Impératrice"), "imperatrice");
+        assert_eq!(french.key("Les Rita Mitsouko"), "rita mitsouko");
+        assert_eq!(french.key("Los Lobos"), "lobos");
+        assert_eq!(english.key("Los Lobos"), "los lobos");
+    }
+}
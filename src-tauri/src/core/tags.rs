@@ -0,0 +1,196 @@
+use symphonia::core::meta::{StandardTagKey, Tag};
+
+//the names a file says its artist, album artist and album should be sorted by
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortTags {
+    //TSOP in id3, soar in mp4, ARTISTSORT in vorbis comments
+    pub artist: Option<String>,
+    //TSO2, soaa, ALBUMARTISTSORT
+    pub album_artist: Option<String>,
+    //TSOA, soal, ALBUMSORT
+    pub album: Option<String>,
+}
+
+//tags audiotags doesnt give us, read through symphonia while probing the audio
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileTags {
+    //TDRC/TYER in id3, DATE in vorbis comments, only the year is kept
+    pub year: Option<i32>,
+    //TDOR/TORY, ORIGINALDATE
+    pub original_year: Option<i32>,
+    //in the order the file lists them, a name only once
+    pub genres: Vec<String>,
+    pub composer: Option<String>,
+    pub conductor: Option<String>,
+    //from TRACKTOTAL or the second half of a "3/12" track number
+    pub track_total: Option<u16>,
+    pub disc_total: Option<u16>,
+    pub bpm: Option<u16>,
+    pub comment: Option<String>,
+    //TPUB, LABEL or ORGANIZATION
+    pub label: Option<String>,
+    pub sort: SortTags,
+}
+
+impl FileTags {
+    //later tags replace earlier ones, so the container tags win over an id3 tag in front of them
+    pub fn read(&mut self, tags: &[Tag]) {
+        let mut genres: Vec<String> = Vec::new();
+
+        for tag in tags {
+            let value = tag.value.to_string();
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            let text = || Some(value.to_string());
+            match tag.std_key {
+                Some(StandardTagKey::Date) => replace(&mut self.year, parse_year(value)),
+                Some(StandardTagKey::OriginalDate) => replace(&mut self.original_year, parse_year(value)),
+                Some(StandardTagKey::Genre) => {
+                    for genre in split_genres(value) {
+                        if !genres.iter().any(|g| g.eq_ignore_ascii_case(&genre)) {
+                            genres.push(genre);
+                        }
+                    }
+                }
+                Some(StandardTagKey::Composer) => replace(&mut self.composer, text()),
+                Some(StandardTagKey::Conductor) => replace(&mut self.conductor, text()),
+                Some(StandardTagKey::TrackNumber) => replace(&mut self.track_total, parse_total(value)),
+                Some(StandardTagKey::TrackTotal) => replace(&mut self.track_total, value.parse().ok()),
+                Some(StandardTagKey::DiscNumber) => replace(&mut self.disc_total, parse_total(value)),
+                Some(StandardTagKey::DiscTotal) => replace(&mut self.disc_total, value.parse().ok()),
+                Some(StandardTagKey::Bpm) => replace(&mut self.bpm, parse_bpm(value)),
+                Some(StandardTagKey::Comment) if !is_player_data(value) => replace(&mut self.comment, text()),
+                Some(StandardTagKey::Label) => replace(&mut self.label, text()),
+                Some(StandardTagKey::SortArtist) => replace(&mut self.sort.artist, text()),
+                Some(StandardTagKey::SortAlbumArtist) => replace(&mut self.sort.album_artist, text()),
+                Some(StandardTagKey::SortAlbum) => replace(&mut self.sort.album, text()),
+                _ => {}
+            }
+        }
+
+        if !genres.is_empty() {
+            self.genres = genres;
+        }
+    }
+}
+
+//a tag that is there but cant be read doesnt wipe out one that could
+fn replace<T>(field: &mut Option<T>, value: Option<T>) {
+    if value.is_some() {
+        *field = value;
+    }
+}
+
+//"1999", "1999-03-01" and "1999-03-01T12:00" all give 1999
+fn parse_year(value: &str) -> Option<i32> {
+    let digits = value.get(..4)?;
+    if !digits.bytes().all(|b| b.is_ascii_digit()) || value[4..].starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    digits.parse().ok().filter(|year| *year > 0)
+}
+
+//the total out of a "3/12" track or disc number
+fn parse_total(value: &str) -> Option<u16> {
+    let (_, total) = value.split_once('/')?;
+    total.trim().parse().ok().filter(|total| *total > 0)
+}
+
+//some taggers write fractional bpm like "120.5"
+fn parse_bpm(value: &str) -> Option<u16> {
+    let bpm: f64 = value.parse().ok()?;
+    if bpm > 0.0 && bpm <= u16::MAX as f64 {
+        Some(bpm.round() as u16)
+    } else {
+        None
+    }
+}
+
+//one genre tag can hold several separated by semicolons, id3v2.3 files sometimes still use
+//the "(17)Rock" form from id3v1 with the number of the genre in front
+fn split_genres(value: &str) -> Vec<String> {
+    value
+        .split(['\0', ';'])
+        .map(|genre| {
+            let genre = genre.trim();
+            match genre.strip_prefix('(').and_then(|rest| rest.split_once(')')) {
+                Some((number, name)) if number.bytes().all(|b| b.is_ascii_digit()) && !name.trim().is_empty() => name.trim(),
+                _ => genre,
+            }
+        })
+        .filter(|genre| !genre.is_empty())
+        .map(|genre| genre.to_string())
+        .collect()
+}
+
+//itunes keeps its volume (iTunNORM) and gapless (iTunSMPB) data in comment frames. symphonia drops the
+//description that names them, so they are told apart by their shape: ten groups of 8 hex digits,
+//or twelve with the fourth one 16 digits long
+fn is_player_data(value: &str) -> bool {
+    let groups: Vec<&str> = value.split_whitespace().collect();
+    let hex = |group: &str, len: usize| group.len() == len && group.bytes().all(|b| b.is_ascii_hexdigit());
+
+    match groups.len() {
+        10 => groups.iter().all(|group| hex(group, 8)),
+        12 => groups.iter().enumerate().all(|(i, group)| hex(group, if i == 3 { 16 } else { 8 })),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::meta::Value;
+
+    fn tag(key: StandardTagKey, value: &str) -> Tag {
+        Tag::new(Some(key), "", Value::from(value))
+    }
+
+    #[test]
+    fn test_read_tags() {
+        let mut tags = FileTags::default();
+        tags.read(&[
+            tag(StandardTagKey::Date, "1977-01-14"),
+            tag(StandardTagKey::OriginalDate, "1976"),
+            tag(StandardTagKey::Genre, "(17)Rock"),
+            tag(StandardTagKey::Genre, "Art Rock; rock"),
+            tag(StandardTagKey::TrackNumber, "3/11"),
+            tag(StandardTagKey::DiscNumber, "1"),
+            tag(StandardTagKey::Bpm, "120.6"),
+            tag(StandardTagKey::Comment, "first pressing"),
+            tag(StandardTagKey::Comment, " 00000226 00000243 00001E27 00001D7C 0001F6A9 0001F6A9 00007D0C 00007D22 0001F6A9 0001F6A9"),
+            tag(StandardTagKey::Label, "RCA"),
+        ]);
+
+        assert_eq!(tags.year, Some(1977));
+        assert_eq!(tags.original_year, Some(1976));
+        assert_eq!(tags.genres, vec!["Rock", "Art Rock"]);
+        assert_eq!((tags.track_total, tags.disc_total), (Some(11), None));
+        assert_eq!(tags.bpm, Some(121));
+        assert_eq!(tags.comment.as_deref(), Some("first pressing"));
+        assert_eq!(tags.label.as_deref(), Some("RCA"));
+    }
+
+    #[test]
+    fn test_player_data_is_told_apart_from_comments() {
+        assert!(is_player_data(" 00000000 00000210 000007B4 0000000000A8F6BC 00000000 005A1E1A 00000000 00000000 00000000 00000000 00000000 00000000"));
+        assert!(!is_player_data("DEADBEEF"));
+        assert!(!is_player_data("cafe babe face"));
+        assert!(!is_player_data("00000226 00000243 00001E27"));
+    }
+
+    #[test]
+    fn test_later_tags_replace_earlier_ones() {
+        let mut tags = FileTags::default();
+        tags.read(&[tag(StandardTagKey::Genre, "Pop"), tag(StandardTagKey::Date, "2001")]);
+        tags.read(&[tag(StandardTagKey::Genre, "Jazz"), tag(StandardTagKey::Date, "unknown")]);
+
+        assert_eq!(tags.genres, vec!["Jazz"]);
+        assert_eq!(tags.year, Some(2001));
+        assert_eq!(parse_year("19999"), None);
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::core::ignore::IgnoreRules;
+use crate::core::scan::ScanSettings;
+
+//how often the calling thread reports walk progress while the walkers run
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+//identifies a directory no matter which path or symlink it was reached through
+#[cfg(unix)]
+type DirId = (u64, u64);
+
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(path: &Path) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path) -> Option<DirId> {
+    fs::canonicalize(path).ok()
+}
+
+pub struct WalkResult {
+    pub files: Vec<PathBuf>,
+    pub dirs_visited: usize,
+}
+
+struct QueuedDir {
+    path: PathBuf,
+    depth: usize,
+    //rules inherited from the directories above, the directory's own ignore file is added when its read
+    rules: Arc<IgnoreRules>,
+}
+
+struct WalkQueue {
+    dirs: VecDeque<QueuedDir>,
+    //walkers currently reading a directory, they might still push more work
+    busy: usize,
+}
+
+struct Walk<'a> {
+    settings: &'a ScanSettings,
+    queue: Mutex<WalkQueue>,
+    ready: Condvar,
+    files: Mutex<Vec<PathBuf>>,
+    visited: Mutex<HashSet<DirId>>,
+    dirs_visited: AtomicUsize,
+}
+
+pub fn worker_count() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(4)
+}
+
+//lists every file below dir using several threads, since listing directories on a network share is mostly waiting
+//library_root is the library folder dir belongs to, ignore files and the depth limit are counted from there
+//the files come back sorted so everything after the walk happens in the same order every time
+pub fn walk_parallel<C, F>(
+    library_root: &Path,
+    dir: &Path,
+    settings: &ScanSettings,
+    threads: usize,
+    is_cancelled: C,
+    mut on_progress: F,
+) -> WalkResult
+where
+    C: Fn() -> bool + Sync,
+    F: FnMut(usize),
+{
+    let (rules, depth) = rules_for(library_root, dir, settings);
+
+    let walk = Walk {
+        settings,
+        queue: Mutex::new(WalkQueue {
+            dirs: VecDeque::from([QueuedDir {
+                path: dir.to_path_buf(),
+                depth,
+                rules,
+            }]),
+            busy: 0,
+        }),
+        ready: Condvar::new(),
+        files: Mutex::new(Vec::new()),
+        visited: Mutex::new(dir_id(dir).into_iter().collect()),
+        dirs_visited: AtomicUsize::new(0),
+    };
+
+    thread::scope(|scope| {
+        let walkers: Vec<_> = (0..threads.max(1))
+            .map(|_| scope.spawn(|| walk.run(&is_cancelled)))
+            .collect();
+
+        while !walkers.iter().all(|w| w.is_finished()) {
+            on_progress(walk.dirs_visited.load(Ordering::Relaxed));
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+
+    let mut files = walk.files.into_inner().unwrap();
+    files.sort();
+
+    WalkResult {
+        files,
+        dirs_visited: walk.dirs_visited.into_inner(),
+    }
+}
+
+//whether a single path reported by the watcher would have been skipped by a full scan of library_root
+pub fn is_excluded(library_root: &Path, path: &Path, settings: &ScanSettings) -> bool {
+    let relative = match path.strip_prefix(library_root) {
+        Ok(r) => r,
+        Err(_) => return true,
+    };
+
+    let components = relative.components().count();
+    let depth = if path.is_dir() { components } else { components.saturating_sub(1) };
+    if depth > settings.max_depth {
+        return true;
+    }
+
+    let mut rules = IgnoreRules::new(library_root, &settings.ignore);
+    let mut current = library_root.to_path_buf();
+
+    for component in relative.components() {
+        rules = rules.child(&current);
+        current.push(component);
+
+        let is_symlink = fs::symlink_metadata(&current)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+
+        if is_symlink && !settings.follow_symlinks {
+            return true;
+        }
+
+        if is_skipped(settings, &rules, &current, current.is_dir()) {
+            return true;
+        }
+    }
+
+    false
+}
+
+//the rules that apply to dir itself and how deep it is below the library folder
+fn rules_for(library_root: &Path, dir: &Path, settings: &ScanSettings) -> (Arc<IgnoreRules>, usize) {
+    let mut rules = IgnoreRules::new(library_root, &settings.ignore);
+    let mut depth = 0;
+
+    if let Ok(relative) = dir.strip_prefix(library_root) {
+        let mut current = library_root.to_path_buf();
+
+        for component in relative.components() {
+            rules = rules.child(&current);
+            current.push(component);
+            depth += 1;
+        }
+    }
+
+    (rules, depth)
+}
+
+fn is_skipped(settings: &ScanSettings, rules: &IgnoreRules, path: &Path, is_dir: bool) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+    (settings.skip_hidden && hidden) || rules.is_ignored(path, is_dir)
+}
+
+impl Walk<'_> {
+    fn run<C: Fn() -> bool>(&self, is_cancelled: &C) {
+        loop {
+            let dir = {
+                let mut q = self.queue.lock().unwrap();
+                loop {
+                    if is_cancelled() {
+                        self.ready.notify_all();
+                        return;
+                    }
+
+                    if let Some(dir) = q.dirs.pop_front() {
+                        q.busy += 1;
+                        break dir;
+                    }
+
+                    //nothing queued and nobody left who could queue more, the walk is done
+                    if q.busy == 0 {
+                        self.ready.notify_all();
+                        return;
+                    }
+
+                    q = self.ready.wait(q).unwrap();
+                }
+            };
+
+            let (found, subdirs) = self.read_entries(&dir);
+            self.dirs_visited.fetch_add(1, Ordering::Relaxed);
+            self.files.lock().unwrap().extend(found);
+
+            let mut q = self.queue.lock().unwrap();
+            q.dirs.extend(subdirs);
+            q.busy -= 1;
+            self.ready.notify_all();
+        }
+    }
+
+    fn read_entries(&self, dir: &QueuedDir) -> (Vec<PathBuf>, Vec<QueuedDir>) {
+        let mut files = Vec::new();
+        let mut subdirs = Vec::new();
+
+        let entries = match fs::read_dir(&dir.path) {
+            Ok(entries) => entries,
+            Err(err) => {
+                println!("failed to read directory {}: {}", dir.path.display(), err);
+                return (files, subdirs);
+            }
+        };
+
+        let rules = dir.rules.child(&dir.path);
+
+        for entry_result in entries {
+            let entry = match entry_result {
+                Ok(e) => e,
+                Err(e) => {
+                    println!("failed to read directory entry: {}", e);
+                    continue;
+                }
+            };
+
+            let path = entry.path();
+
+            let file_type = match entry.file_type() {
+                Ok(t) => t,
+                Err(e) => {
+                    println!("failed to read file type of {}: {e}", path.display());
+                    continue;
+                }
+            };
+
+            //is_file and is_dir on the path follow the link, the entry's own file type doesnt
+            let (is_file, is_dir) = if file_type.is_symlink() {
+                if !self.settings.follow_symlinks {
+                    continue;
+                }
+                (path.is_file(), path.is_dir())
+            } else {
+                (file_type.is_file(), file_type.is_dir())
+            };
+
+            if is_skipped(self.settings, &rules, &path, is_dir) {
+                continue;
+            }
+
+            if is_file {
+                files.push(path);
+            } else if is_dir {
+                if dir.depth + 1 > self.settings.max_depth {
+                    println!("not descending into {}, deeper than {} folders", path.display(), self.settings.max_depth);
+                    continue;
+                }
+
+                //a symlink back up the tree or a bind mount would otherwise be walked forever
+                match dir_id(&path) {
+                    Some(id) => {
+                        if !self.visited.lock().unwrap().insert(id) {
+                            println!("skipping {}, already visited through another path", path.display());
+                            continue;
+                        }
+                    }
+                    None => {
+                        println!("failed to identify directory {}", path.display());
+                        continue;
+                    }
+                }
+
+                subdirs.push(QueuedDir {
+                    path,
+                    depth: dir.depth + 1,
+                    rules: rules.clone(),
+                });
+            } else {
+                println!("Unsupported file type: {}", path.display());
+            }
+        }
+
+        (files, subdirs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ignore::IGNORE_FILE;
+
+    fn walk(root: &Path, settings: &ScanSettings) -> Vec<PathBuf> {
+        walk_parallel(root, root, settings, 4, || false, |_| {}).files
+    }
+
+    fn touch(root: &Path, files: &[&str]) {
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_walk_parallel_finds_nested_files_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        touch(root, &["b/2.mp3", "a/1.mp3", "a/deep/er/3.flac", "c/4.m4a", "top.mp3"]);
+
+        let walk = walk_parallel(root, root, &ScanSettings::default(), 4, || false, |_| {});
+
+        let expected: Vec<PathBuf> = ["a/1.mp3", "a/deep/er/3.flac", "b/2.mp3", "c/4.m4a", "top.mp3"]
+            .iter()
+            .map(|f| root.join(f))
+            .collect();
+
+        assert_eq!(walk.files, expected);
+        assert_eq!(walk.dirs_visited, 6);
+    }
+
+    #[test]
+    fn test_walk_skips_hidden_and_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        touch(root, &[
+            "album/1.mp3",
+            "album/@eaDir/1.mp3",
+            ".Trash-1000/old.mp3",
+            ".hidden/2.mp3",
+            "live/3.mp3",
+            "live/rehearsal/4.mp3",
+        ]);
+        fs::write(root.join("live").join(IGNORE_FILE), "rehearsal/\n").unwrap();
+
+        assert_eq!(walk(root, &ScanSettings::default()), vec![root.join("album/1.mp3"), root.join("live/3.mp3")]);
+
+        assert!(is_excluded(root, &root.join("album/@eaDir/1.mp3"), &ScanSettings::default()));
+        assert!(is_excluded(root, &root.join("live/rehearsal/4.mp3"), &ScanSettings::default()));
+        assert!(!is_excluded(root, &root.join("live/3.mp3"), &ScanSettings::default()));
+    }
+
+    #[test]
+    fn test_walk_respects_max_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        touch(root, &["1.mp3", "a/2.mp3", "a/b/3.mp3"]);
+
+        let settings = ScanSettings {
+            max_depth: 1,
+            ..ScanSettings::default()
+        };
+
+        assert_eq!(walk(root, &settings), vec![root.join("1.mp3"), root.join("a/2.mp3")]);
+        assert!(is_excluded(root, &root.join("a/b/3.mp3"), &settings));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_survives_symlink_loops() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        touch(root, &["a/1.mp3"]);
+        std::os::unix::fs::symlink(root, root.join("a/loop")).unwrap();
+
+        //links arent followed by default
+        assert_eq!(walk(root, &ScanSettings::default()), vec![root.join("a/1.mp3")]);
+
+        let settings = ScanSettings {
+            follow_symlinks: true,
+            ..ScanSettings::default()
+        };
+        assert_eq!(walk(root, &settings), vec![root.join("a/1.mp3")]);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use notify_debouncer_mini::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer,
+};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::core::scan::apply_changes;
+use crate::AppState;
+
+pub type WatcherState = Mutex<LibraryWatcher>;
+
+//downloads and copies fire lots of write events, wait for things to settle before rescanning
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct LibraryWatcher {
+    debouncer: Option<Debouncer<RecommendedWatcher>>,
+    watched: HashMap<i64, PathBuf>,
+}
+
+impl LibraryWatcher {
+    pub fn new(app: AppHandle) -> Self {
+        let debouncer = match new_debouncer(DEBOUNCE_TIMEOUT, move |res: DebounceEventResult| {
+            handle_events(&app, res);
+        }) {
+            Ok(d) => Some(d),
+            Err(e) => {
+                println!("failed to start filesystem watcher, library wont update live: {e}");
+                None
+            }
+        };
+
+        LibraryWatcher {
+            debouncer,
+            watched: HashMap::new(),
+        }
+    }
+
+    pub fn watch(&mut self, id: i64, path: &Path) {
+        if self.watched.contains_key(&id) {
+            return;
+        }
+
+        if let Some(debouncer) = &mut self.debouncer {
+            if let Err(e) = debouncer.watcher().watch(path, RecursiveMode::Recursive) {
+                println!("failed to watch folder {}: {e}", path.display());
+                return;
+            }
+
+            self.watched.insert(id, path.to_path_buf());
+        }
+    }
+
+    //watches every folder in the library and stops watching any that were removed or merged away
+    pub fn sync(&mut self, folders: &HashMap<i64, PathBuf>) {
+        let stale: Vec<i64> = self.watched.keys().filter(|id| !folders.contains_key(id)).copied().collect();
+        for id in stale {
+            self.unwatch(id);
+        }
+
+        for (id, path) in folders {
+            self.watch(*id, path);
+        }
+    }
+
+    pub fn unwatch(&mut self, id: i64) {
+        if let Some(path) = self.watched.remove(&id) {
+            if let Some(debouncer) = &mut self.debouncer {
+                if let Err(e) = debouncer.watcher().unwatch(&path) {
+                    println!("failed to stop watching folder {}: {e}", path.display());
+                }
+            }
+        }
+    }
+}
+
+fn handle_events(app: &AppHandle, res: DebounceEventResult) {
+    let events = match res {
+        Ok(events) => events,
+        Err(e) => {
+            println!("filesystem watcher error: {e}");
+            return;
+        }
+    };
+
+    let mut paths: Vec<PathBuf> = events.into_iter().map(|e| e.path).collect();
+    paths.sort();
+    paths.dedup();
+
+    let state = app.state::<AppState>();
+
+    match apply_changes(&paths, &state) {
+        Ok(report) => {
+            if report.has_changes() {
+                if let Err(e) = app.emit("library-changed", &report) {
+                    println!("failed to emit library-changed event: {e}");
+                }
+            }
+        }
+        Err(e) => println!("failed to apply filesystem changes: {e}"),
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::Duration,
+};
+
+use rusqlite::{Connection, OpenFlags, Result};
+
+use crate::state::init_db;
+
+//how long a connection waits on a lock held by another one before giving up with SQLITE_BUSY
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+//per connection, the scanner runs the same handful of statements for every file
+const STATEMENT_CACHE: usize = 64;
+
+//how often a writer handing over checks whether the others have had their turn
+const HANDOVER_INTERVAL: Duration = Duration::from_millis(1);
+
+//idle read connections kept around, more are opened when needed and closed when handed back
+const MAX_IDLE_READERS: usize = 4;
+
+//the one handle to the library database, cheap to clone and shared by the library, scans and commands.
+//all writes go through a single connection behind a mutex, so a scan and a watcher batch queue up
+//instead of failing with SQLITE_BUSY. scans write in batches and hand the writer over in between. with WAL, reads get their own connections and arent blocked by a scan.
+//lock order is the writer before the library mutex, never take the writer while holding the library
+#[derive(Clone)]
+pub struct Database {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    writer: Mutex<Connection>,
+    //threads blocked on the writer, see hand_over_writer
+    waiting: AtomicUsize,
+    //none for in-memory databases, which only exist on the writer connection
+    path: Option<PathBuf>,
+    readers: Mutex<Vec<Connection>>,
+}
+
+//a read connection borrowed from the pool, goes back to it when dropped
+pub struct Reader<'a> {
+    db: &'a Database,
+    conn: ReaderConn<'a>,
+}
+
+enum ReaderConn<'a> {
+    Pooled(Option<Connection>),
+    Writer(MutexGuard<'a, Connection>),
+}
+
+impl Database {
+    //opens the database and brings the schema up to date before anything else can use it
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Database> {
+        let path = path.as_ref().to_path_buf();
+        let writer = Connection::open(&path)?;
+        configure(&writer)?;
+
+        //WAL is stored in the file, readers opened after this pick it up
+        let mode: String = writer.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            println!("database is in {mode} journal mode, reads will wait for scans to finish");
+        }
+        writer.pragma_update(None, "synchronous", "NORMAL")?;
+
+        init_db(&writer)?;
+
+        Ok(Database::with_writer(writer, Some(path)))
+    }
+
+    //for tests, reads share the writer connection
+    pub fn open_in_memory() -> Result<Database> {
+        let writer = Connection::open_in_memory()?;
+        configure(&writer)?;
+        init_db(&writer)?;
+
+        Ok(Database::with_writer(writer, None))
+    }
+
+    fn with_writer(writer: Connection, path: Option<PathBuf>) -> Database {
+        Database {
+            inner: Arc::new(Inner {
+                writer: Mutex::new(writer),
+                waiting: AtomicUsize::new(0),
+                path,
+                readers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    //exclusive access for anything that changes the database, a scan only holds it for one batch at a time
+    pub fn writer(&self) -> MutexGuard<'_, Connection> {
+        self.inner.waiting.fetch_add(1, Ordering::SeqCst);
+        let conn = self.inner.writer.lock().unwrap();
+        self.inner.waiting.fetch_sub(1, Ordering::SeqCst);
+        conn
+    }
+
+    //the mutex isnt fair, a scan starting its next batch straight after committing would usually get the
+    //writer back before a waiting command wakes up. this waits until everyone already queued has had it
+    pub fn hand_over_writer(&self) {
+        while self.inner.waiting.load(Ordering::SeqCst) > 0 {
+            thread::sleep(HANDOVER_INTERVAL);
+        }
+    }
+
+    pub fn reader(&self) -> Result<Reader<'_>> {
+        let path = match &self.inner.path {
+            Some(p) => p,
+            None => {
+                return Ok(Reader {
+                    db: self,
+                    conn: ReaderConn::Writer(self.writer()),
+                })
+            }
+        };
+
+        let idle = self.inner.readers.lock().unwrap().pop();
+        let conn = match idle {
+            Some(c) => c,
+            None => {
+                let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+                let conn = Connection::open_with_flags(path, flags)?;
+                configure(&conn)?;
+                conn
+            }
+        };
+
+        Ok(Reader {
+            db: self,
+            conn: ReaderConn::Pooled(Some(conn)),
+        })
+    }
+}
+
+//settings that only last as long as the connection, so every one of them needs them
+fn configure(conn: &Connection) -> Result<()> {
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE);
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+
+    Ok(())
+}
+
+impl Deref for Reader<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match &self.conn {
+            ReaderConn::Pooled(c) => c.as_ref().unwrap(),
+            ReaderConn::Writer(c) => c,
+        }
+    }
+}
+
+impl Drop for Reader<'_> {
+    fn drop(&mut self) {
+        if let ReaderConn::Pooled(conn) = &mut self.conn {
+            let mut readers = self.db.inner.readers.lock().unwrap();
+            if readers.len() < MAX_IDLE_READERS {
+                readers.extend(conn.take());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc, thread};
+
+    #[test]
+    fn test_reads_dont_wait_for_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path().join("library.db")).unwrap();
+
+        db.writer().execute("INSERT INTO folders (path) VALUES ('/music')", []).unwrap();
+
+        //a scan holds the writer with an open transaction
+        let (started_tx, started_rx) = mpsc::channel();
+        let (finish_tx, finish_rx) = mpsc::channel::<()>();
+        let scan_db = db.clone();
+        let scan = thread::spawn(move || {
+            let mut conn = scan_db.writer();
+            let tx = conn.transaction().unwrap();
+            tx.execute("INSERT INTO folders (path) VALUES ('/podcasts')", []).unwrap();
+            started_tx.send(()).unwrap();
+            finish_rx.recv().unwrap();
+            tx.commit().unwrap();
+        });
+
+        started_rx.recv().unwrap();
+        let count = |db: &Database| -> i64 {
+            db.reader().unwrap().query_row("SELECT COUNT(*) FROM folders", [], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(count(&db), 1);
+
+        finish_tx.send(()).unwrap();
+        scan.join().unwrap();
+        assert_eq!(count(&db), 2);
+
+        //the reader went back to the pool
+        assert_eq!(db.inner.readers.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_handing_over_lets_waiting_writers_go_first() {
+        let db = Database::open_in_memory().unwrap();
+        db.writer().execute_batch("CREATE TABLE turns (who TEXT)").unwrap();
+
+        //a scan finishing a batch while a command waits for the writer
+        let held = db.writer();
+        let command_db = db.clone();
+        let command = thread::spawn(move || {
+            command_db.writer().execute("INSERT INTO turns VALUES ('command')", []).unwrap();
+        });
+        while db.inner.waiting.load(Ordering::SeqCst) == 0 {
+            thread::yield_now();
+        }
+
+        drop(held);
+        db.hand_over_writer();
+        db.writer().execute("INSERT INTO turns VALUES ('scan')", []).unwrap();
+        command.join().unwrap();
+
+        let first: String = db.writer().query_row("SELECT who FROM turns ORDER BY rowid LIMIT 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(first, "command");
+    }
+}
@@ -1,15 +1,28 @@
 pub mod core;
+pub mod db;
+pub mod migrations;
+pub mod profiles;
 pub mod state;
+pub mod store;
 
 use std::fs;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
 use uuid::Uuid;
 
-use crate::core::scan::{scan_dir, remove_folder};
-use crate::core::song::{Album, Song, SongDto, Artist, Image};
+use crate::core::scan::{find_overlap, remove_folder, FolderOverlap, ScanReport, ScanSettings};
+use crate::core::song::{Album, Artist};
 use crate::core::audio;
+use crate::core::query::{GenreCount, SongFilter, SongPage, SongQuery, SongSort};
+use crate::core::search::{SearchResults, MAX_SEARCH_RESULTS};
+use crate::core::sort_key::SortSettings;
+use crate::core::cover_protocol::{cover_response, COVER_SCHEME};
+use crate::core::palette::Palette;
+use crate::core::watcher::{LibraryWatcher, WatcherState};
+use crate::core::jobs::{run_scan, ScanJobInfo, ScanJobs, ScanJobsState};
+use crate::profiles::{AppConfig, LibraryLocation, ProfilesInfo};
 use crate::state::MusicLibrary;
 
 use serde::{Deserialize, Serialize};
@@ -17,19 +30,55 @@ use tauri::{Manager, State, AppHandle, Emitter};
 
 pub type AppState = Mutex<MusicLibrary>;
 
+//the database of the profile picked during setup, see profiles for how it is chosen
 pub fn db_dir() -> PathBuf {
-    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    path.push("db");
+    let location = profiles::current();
 
-    if let Err(e) = fs::create_dir_all(&path) {
+    if let Err(e) = fs::create_dir_all(location.profile_dir()) {
         panic!("failed to create db directory: {e}");
     }
 
-    path.push("library.db"); 
+    location.db_path()
+}
+
+//album art lives next to the database, see CoverCache for the layout
+pub fn covers_dir() -> PathBuf {
+    profiles::current().covers_dir()
+}
+
+//where builds before profiles kept the database, only read to move it over
+fn legacy_db_dir() -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("db");
 
     path
 }
 
+//works out which profile to open from the env, the saved config and the app data dir,
+//and moves a database left over from older builds into it
+fn resolve_library_location(app: &AppHandle) -> Result<LibraryLocation, String> {
+    let data_dir = match app.path().app_data_dir() {
+        Ok(d) => d,
+        Err(e) => return Err(format!("failed to find the app data directory: {e}")),
+    };
+
+    let config_dir = match app.path().app_config_dir() {
+        Ok(d) => d,
+        Err(e) => return Err(format!("failed to find the app config directory: {e}")),
+    };
+
+    let config = profiles::load_config(&config_dir);
+    let location = LibraryLocation::resolve(data_dir, &config, |key| std::env::var(key).ok());
+
+    match profiles::migrate_legacy_library(&legacy_db_dir(), &location) {
+        Ok(true) => println!("moved the library from {} to {}", legacy_db_dir().display(), location.profile_dir().display()),
+        Ok(false) => {}
+        Err(e) => println!("failed to move the old library into {}: {e}", location.profile_dir().display()),
+    }
+
+    Ok(location)
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum SongEvent {
@@ -38,13 +87,15 @@ pub enum SongEvent {
         artist: (Uuid, String),
         features: Option<Vec<(Option<Uuid>, String)>>,
         album: (Uuid, String),
+        cover: Option<String>,
+        palette: Option<Palette>,
         duration: f64,
     }
 }
 
 
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct SongToSend {
     pub id: Uuid,
     pub title: String,
@@ -53,9 +104,16 @@ pub struct SongToSend {
     pub features: Option<Vec<(Option<Uuid>, String)>>,
     pub track_num: u16,
     pub disc_num: u16,
-    pub cover: Option<Image>,
+    //hash of the album cover, loaded through cover:// like the album views do
+    pub cover: Option<String>,
     pub path: PathBuf,
-    pub duration: f64
+    pub duration: f64,
+    pub year: Option<i32>,
+    pub genres: Vec<String>,
+    pub composer: Option<String>,
+    pub conductor: Option<String>,
+    pub bpm: Option<u16>,
+    pub comment: Option<String>,
 }
 
 #[tauri::command]
@@ -73,33 +131,33 @@ fn toggle_play(state: State<AppState>) {
 fn play_song(app: AppHandle, id: &str) -> Result<(), String>{
     let state = app.state::<AppState>();
 
-    let state = state.lock().unwrap();
-
     let uuid = match Uuid::parse_str(id) {
         Ok(u) => u,
         Err(e) => return Err("invalid song id".into()),
     };
 
-    if let Some(s) = state.songs.get(&uuid) {
-        state.player.play_now(s.clone());
+    //the song comes from the database without holding the library lock
+    let store = state.lock().unwrap().store.clone();
+    let song = match store.song(uuid) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("failed to load song {uuid}: {e}");
+            return Err("failed to load song".into());
+        }
+    };
+
+    let state = state.lock().unwrap();
 
+    if let Some(s) = song {
         let artist_string = match state.artist_manager.artists.get(&s.artist) {
             Some(a) => &a.name,
             None => "Unknown Artist"
             
         };
 
-        let (album_string, cover): (String, Option<Image>) = match state.albums.get(&s.album) {
-            Some(a) => {
-                if let Some(c) = &a.cover {
-                    (a.title.clone(), Some(c.clone()))
-                }
-                else{
-                    (a.title.clone(), None)
-                }
-            }
-
-            None => ("Unknown Artist".into(), None)
+        let (album_string, cover, palette) = match state.albums.get(&s.album) {
+            Some(a) => (a.title.clone(), a.cover.clone(), a.palette.clone()),
+            None => ("Unknown Artist".into(), None, None)
             
         };
 
@@ -109,11 +167,14 @@ fn play_song(app: AppHandle, id: &str) -> Result<(), String>{
             artist: (s.artist.clone(), artist_string.into()),
             album: (s.album.clone(), album_string.into()),
             features: s.features.clone(),
+            cover,
+            palette,
             duration: s.duration,
         };
 
         println!("{:?}", &msg);
 
+        state.player.play_now(s);
 
         app.emit("playing-song", &msg).unwrap();
     }
@@ -147,141 +208,280 @@ fn play_song(app: AppHandle, id: &str) -> Result<(), String>{
 
 }
 
+//one page of the song list, the frontend asks for more as it scrolls
 #[tauri::command]
-fn get_songs(state: State<AppState>) -> Vec<SongToSend>{
-    let mut state = state.lock().unwrap();
+fn get_songs(state: State<AppState>, offset: usize, limit: usize, sort: Option<SongSort>, filter: Option<SongFilter>) -> Result<SongPage, String> {
+    let store = state.lock().unwrap().store.clone();
 
-    //for now i load all the songs into memory
-    //this isnt scalable so i wil lhave to think up some buffered appraoch probs
-    let mut required_album_uuids = Vec::new();
+    let query = SongQuery::new(offset, limit, sort.unwrap_or_default(), filter.unwrap_or_default());
 
-    let songs: Vec<SongToSend> = state.songs.values().map(|song| {
-
-        let artist_string = match state.artist_manager.artists.get(&song.artist) {
-            Some(a) => &a.name,
-            None => "Unknown Artist"
-            
-        };
+    match store.song_page(&query) {
+        Ok(page) => Ok(page),
+        Err(e) => {
+            println!("failed to load songs {offset}..{}: {e}", offset.saturating_add(query.limit));
+            Err("failed to load songs".into())
+        }
+    }
+}
 
-        let album_string = match state.albums.get(&song.album) {
-            Some(a) => &a.title,
-            None => "Unknown Artist"
-            
-        };
+//what the song list can be narrowed down to with the genre filter
+#[tauri::command]
+fn get_genres(state: State<AppState>) -> Result<Vec<GenreCount>, String> {
+    let store = state.lock().unwrap().store.clone();
 
-        required_album_uuids.push(song.album);
-
-        SongToSend {
-            id: song.id.clone(),
-            title: song.title.clone(),
-            artist: (song.artist, artist_string.to_string()),
-           album: (song.album, album_string.to_string()),
-            features: song.features.clone(),
-            track_num: song.track_num,
-            disc_num: song.disc_num,
-            cover: song.cover.clone(),
-            path: song.path.clone(),
-            duration: song.duration,
+    match store.genres() {
+        Ok(genres) => Ok(genres),
+        Err(e) => {
+            println!("failed to load genres: {e}");
+            Err("failed to load genres".into())
         }
-    }).collect();
-
-    for album_uuid in required_album_uuids {
-        state.required_covers.insert(album_uuid);
     }
-
-    songs
 }
 
+//songs, albums and artists matching what was typed, each group best first
 #[tauri::command]
-fn get_artists(state: State<AppState>) -> Vec<Artist> {
+fn search(state: State<AppState>, query: &str, limit: usize) -> Result<SearchResults, String> {
+    let store = state.lock().unwrap().store.clone();
+
+    let matches = match store.search(query, limit.min(MAX_SEARCH_RESULTS)) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("search for {query:?} failed: {e}");
+            return Err("search failed".into());
+        }
+    };
+
     let state = state.lock().unwrap();
+    Ok(SearchResults::new(matches, &state.albums, &state.artist_manager.artists))
+}
 
-    let artist_vec = state.artist_manager.artists
-        .iter()
-        .map(|a| a.1.clone())
-        .collect();
+//artists and albums come back in sort key order, see core::sort_key
+#[tauri::command]
+fn get_artists(state: State<AppState>) -> Result<Vec<Artist>, String> {
+    let store = state.lock().unwrap().store.clone();
 
-    artist_vec
+    let order = match store.artist_order() {
+        Ok(o) => o,
+        Err(e) => {
+            println!("failed to load artist order: {e}");
+            return Err("failed to load artists".into());
+        }
+    };
+
+    let state = state.lock().unwrap();
+    Ok(order.iter().filter_map(|id| state.artist_manager.artists.get(id).cloned()).collect())
 }
 
 
 #[tauri::command]
-fn get_albums(state: State<AppState>) -> Vec<Album> {
-    let state = state.lock().unwrap();
+fn get_albums(state: State<AppState>) -> Result<Vec<Album>, String> {
+    let store = state.lock().unwrap().store.clone();
 
-    let album_vec = state.albums
-        .iter()
-        .map(|a| a.1.clone())
-        .collect();
+    let order = match store.album_order() {
+        Ok(o) => o,
+        Err(e) => {
+            println!("failed to load album order: {e}");
+            return Err("failed to load albums".into());
+        }
+    };
 
-    album_vec
+    let state = state.lock().unwrap();
+    Ok(order.iter().filter_map(|id| state.albums.get(id).cloned()).collect())
 }
 
+//starts scanning in the background and returns the job id straight away,
+//progress comes through scan-progress and scan-finished events
+//a folder overlapping one already in the library is only scanned when merge is set,
+//the frontend asks get_folder_overlap first so it can explain what will happen
 #[tauri::command]
-fn get_covers(state: State<AppState>) -> HashMap<Uuid, Image> {
-    let state = state.lock().unwrap();
+fn read_directory(app: AppHandle, state: State<AppState>, jobs: State<ScanJobsState>, path: &str, merge: Option<bool>) -> Result<Uuid, String> {
+    let dir = Path::new(path);
+    if !dir.exists() {
+        return Err(String::from("invalid path"));
+    }
 
-    let mut covers = HashMap::new();
+    let overlap = find_overlap(&state.lock().unwrap().folders, dir);
+    if overlap.is_some() && !merge.unwrap_or(false) {
+        return Err(format!("{} overlaps a folder already in the library", dir.display()));
+    }
 
-    for uuid in &state.required_covers {
-        if let Some(a) = state.albums.get(uuid){
-            if let Some(c) = &a.cover {
-                covers.insert(*uuid, c.clone());
-            }
-        }
+    start_scan(app, jobs, dir)
+}
+
+//incremental scan of a folder already in the library, the rest of the library is left alone
+#[tauri::command]
+fn rescan_directory(app: AppHandle, state: State<AppState>, jobs: State<ScanJobsState>, id: i64) -> Result<Uuid, String> {
+    let dir = match state.lock().unwrap().folders.get(&id) {
+        Some(d) => d.clone(),
+        None => return Err(format!("folder {id} is not in the library")),
+    };
+
+    if !dir.exists() {
+        return Err(format!("{} no longer exists", dir.display()));
     }
 
-    covers
+    start_scan(app, jobs, &dir)
 }
 
-#[tauri::command] 
-fn get_cover(state: State<AppState>, id: &str) -> Option<Image> {
+fn start_scan(app: AppHandle, jobs: State<ScanJobsState>, dir: &Path) -> Result<Uuid, String> {
+    let job = jobs.lock().unwrap().start(dir)?;
+    let job_id = job.id;
+
+    thread::spawn(move || run_scan(app, job));
+
+    Ok(job_id)
+}
+
+#[tauri::command]
+fn get_folder_overlap(state: State<AppState>, path: &str) -> Option<FolderOverlap> {
     let state = state.lock().unwrap();
+    find_overlap(&state.folders, Path::new(path))
+}
 
+#[tauri::command]
+fn cancel_scan(jobs: State<ScanJobsState>, id: &str) -> Result<(), String> {
     let uuid = match Uuid::parse_str(id) {
         Ok(u) => u,
-        Err(e) => {
-            println!("invalid uuid: {e}");
-            return None;
-        },
+        Err(_) => return Err("invalid job id".into()),
     };
 
-    if let Some(a) = state.albums.get(&uuid) {
-        if let Some(c) = &a.cover {
-            return Some(c.clone())
-        }
+    if !jobs.lock().unwrap().cancel(uuid) {
+        return Err("scan is not running".into());
     }
 
-    None
+    Ok(())
 }
 
 #[tauri::command]
-fn read_directory(state: State<AppState>, path: &str) -> Result<(), String> {
-    let dir = Path::new(path);
-    if !dir.exists() {
-        return Err(String::from("invalid path"));
+fn get_scan_jobs(jobs: State<ScanJobsState>) -> Vec<ScanJobInfo> {
+    jobs.lock().unwrap().running()
+}
+
+//the result of the last scan of a folder, including every file that couldnt be imported
+#[tauri::command]
+fn get_scan_report(state: State<AppState>, folder_id: i64) -> Result<Option<ScanReport>, String> {
+    let store = state.lock().unwrap().store.clone();
+
+    match store.scan_report(folder_id) {
+        Ok(r) => Ok(r),
+        Err(e) => Err(format!("failed to load scan report: {e}")),
     }
+}
 
-    scan_dir(&path, state.clone());
+#[tauri::command]
+fn delete_directory(state: State<AppState>, watcher: State<WatcherState>, id: i64) -> Result<(), String> {
+    //the folder keeps being watched if removing it failed, it is still part of the library
+    remove_folder(&state, id)?;
+    watcher.lock().unwrap().unwatch(id);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_directories(state: State<AppState>) -> HashMap<i64, PathBuf> {
+    let state = state.lock().unwrap();
+    state.folders.clone()
+}
 
+
+
+#[tauri::command]
+fn get_scan_settings(state: State<AppState>) -> ScanSettings {
     let state = state.lock().unwrap();
-    state.songs.iter().for_each(|s| println!("{}", s.1));
+    state.scan_settings.clone()
+}
+
+#[tauri::command]
+fn set_scan_settings(state: State<AppState>, settings: ScanSettings) -> Result<(), String> {
+    //the library is unlocked while waiting for the store, a running scan holds the writer and needs the library to merge each batch
+    let store = state.lock().unwrap().store.clone();
+
+    if let Err(e) = store.set_scan_settings(&settings) {
+        return Err(format!("failed to save scan settings: {e}"));
+    }
+
+    state.lock().unwrap().scan_settings = settings;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_sort_settings(state: State<AppState>) -> Result<SortSettings, String> {
+    let store = state.lock().unwrap().store.clone();
+
+    match store.sort_settings() {
+        Ok(s) => Ok(s.unwrap_or_default()),
+        Err(e) => Err(format!("failed to load sort settings: {e}")),
+    }
+}
+
+//resorts the whole library, which waits for the batch a running scan is writing to be committed first
+#[tauri::command]
+fn set_sort_settings(state: State<AppState>, settings: SortSettings) -> Result<(), String> {
+    let store = state.lock().unwrap().store.clone();
+
+    if let Err(e) = store.set_sort_settings(&settings) {
+        return Err(format!("failed to save sort settings: {e}"));
+    }
 
     Ok(())
 }
 
 #[tauri::command]
-fn delete_directory(state: State<AppState>, id: i64) {
-    remove_folder(state, id);
+fn get_profiles() -> ProfilesInfo {
+    let location = profiles::current();
+
+    let mut names = profiles::list_profiles(&location.data_dir);
+    if !names.contains(&location.profile) {
+        names.push(location.profile.clone());
+        names.sort();
+    }
+
+    ProfilesInfo {
+        current: location.profile.clone(),
+        data_dir: location.data_dir.clone(),
+        profiles: names,
+    }
 }
 
+//saves the profile to open and restarts into it, a profile that doesnt exist yet starts as an empty library
 #[tauri::command]
-fn get_directories(state: State<AppState>) -> HashMap<i64, PathBuf> {
-    let state = state.lock().unwrap();
-    state.folders.clone()
+fn switch_profile(app: AppHandle, name: &str) -> Result<(), String> {
+    if !profiles::is_valid_profile_name(name) {
+        return Err(format!("{name:?} isnt a valid profile name"));
+    }
+
+    update_config(&app, |config| config.profile = Some(name.to_string()))?;
+    app.restart()
+}
+
+//moves where profiles are kept, none goes back to the app data dir. existing libraries are not copied over
+#[tauri::command]
+fn set_data_dir(app: AppHandle, path: Option<String>) -> Result<(), String> {
+    let dir = path.map(PathBuf::from);
+
+    if let Some(d) = &dir {
+        if let Err(e) = fs::create_dir_all(d) {
+            return Err(format!("failed to use {} as the data directory: {e}", d.display()));
+        }
+    }
+
+    update_config(&app, |config| config.data_dir = dir)?;
+    app.restart()
 }
 
+fn update_config<F: FnOnce(&mut AppConfig)>(app: &AppHandle, change: F) -> Result<(), String> {
+    let config_dir = match app.path().app_config_dir() {
+        Ok(d) => d,
+        Err(e) => return Err(format!("failed to find the app config directory: {e}")),
+    };
 
+    let mut config = profiles::load_config(&config_dir);
+    change(&mut config);
+
+    match profiles::save_config(&config_dir, &config) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("failed to save config: {e}")),
+    }
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -296,11 +496,34 @@ pub fn run() {
                 )?;
             }
 
-            app.manage(Mutex::new(MusicLibrary::new()));
+            //has to happen before anything touches db_dir
+            let location = resolve_library_location(app.handle())?;
+            println!("opening library profile {} in {}", location.profile, location.profile_dir().display());
+            profiles::init(location);
+
+            let library = MusicLibrary::new();
+
+            //watch every saved folder so new downloads show up without a manual rescan
+            let mut watcher = LibraryWatcher::new(app.handle().clone());
+            for (id, folder) in &library.folders {
+                watcher.watch(*id, folder);
+            }
+
+            app.manage(Mutex::new(library));
+            app.manage(Mutex::new(watcher));
+            app.manage(Mutex::new(ScanJobs::default()));
 
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![read_directory, get_songs, get_covers, get_artists, get_albums, get_cover, play_song, toggle_play, delete_directory, get_directories, seek_to])
+        //covers are read from disk off the main thread so a grid full of them doesnt stall the window
+        .register_asynchronous_uri_scheme_protocol(COVER_SCHEME, |ctx, request, responder| {
+            let app = ctx.app_handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                let state = app.state::<AppState>();
+                responder.respond(cover_response(&state, &request));
+            });
+        })
+        .invoke_handler(tauri::generate_handler![read_directory, get_songs, get_genres, search, get_artists, get_albums, play_song, toggle_play, delete_directory, get_directories, seek_to, get_scan_settings, set_scan_settings, get_sort_settings, set_sort_settings, cancel_scan, get_scan_jobs, get_scan_report, get_folder_overlap, rescan_directory, get_profiles, switch_profile, set_data_dir])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
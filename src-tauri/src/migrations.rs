@@ -0,0 +1,583 @@
+use rusqlite::{ffi, Connection, Result, Transaction, TransactionBehavior};
+
+//a schema change, applied once to every database that hasnt had it yet
+pub struct Migration {
+    pub name: &'static str,
+    pub run: fn(&Transaction) -> Result<()>,
+}
+
+//PRAGMA user_version holds how many of these a database has had, so they must only ever be appended to.
+//databases from before versioning all start at 0 in whatever shape they were left in,
+//so the first four check what is already there before changing anything
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { name: "create tables", run: create_tables },
+    Migration { name: "repair albums table", run: repair_albums },
+    Migration { name: "add scan bookkeeping", run: add_scan_bookkeeping },
+    Migration { name: "add album cover columns", run: add_cover_columns },
+    Migration { name: "allow unknown feature artists", run: nullable_feature_artists },
+    Migration { name: "index song lists", run: index_song_lists },
+    Migration { name: "add search index", run: add_search_index },
+    Migration { name: "add sort keys", run: add_sort_keys },
+    Migration { name: "add sort name tags", run: add_sort_tags },
+    Migration { name: "add detailed tags", run: add_detailed_tags },
+    Migration { name: "keep album tags on songs", run: add_song_album_tags },
+];
+
+pub fn schema_version(conn: &Connection) -> Result<usize> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+pub fn migrate(conn: &Connection) -> Result<()> {
+    run_migrations(conn, MIGRATIONS)
+}
+
+//each migration gets its own transaction together with the version bump, a failure leaves the database
+//exactly as the previous migration left it. foreign keys are off meanwhile so tables can be rebuilt
+//without cascading deletes, and checked before every commit instead
+fn run_migrations(conn: &Connection, migrations: &[Migration]) -> Result<()> {
+    let current = schema_version(conn)?;
+    if current > migrations.len() {
+        return Err(rusqlite::Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_ERROR),
+            Some(format!(
+                "database is at schema version {current} but this build only knows {}, it was opened by a newer version",
+                migrations.len()
+            )),
+        ));
+    }
+
+    if current == migrations.len() {
+        return Ok(());
+    }
+
+    conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+    let result = apply_pending(conn, migrations);
+    conn.execute_batch("PRAGMA foreign_keys = ON")?;
+
+    result
+}
+
+fn apply_pending(conn: &Connection, migrations: &[Migration]) -> Result<()> {
+    for (index, migration) in migrations.iter().enumerate() {
+        let version = index + 1;
+
+        //immediate so another connection opening the same file waits here instead of migrating alongside
+        let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+
+        if schema_version(&tx)? >= version {
+            continue;
+        }
+
+        println!("migrating database to version {version}: {}", migration.name);
+        (migration.run)(&tx)?;
+
+        let broken_keys: usize = tx.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| row.get(0))?;
+        if broken_keys > 0 {
+            return Err(rusqlite::Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_CONSTRAINT_FOREIGNKEY),
+                Some(format!("migration {version} ({}) left {broken_keys} broken foreign keys", migration.name)),
+            ));
+        }
+
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+fn has_column(tx: &Transaction, table: &str, column: &str) -> Result<bool> {
+    let count: usize = tx.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+        (table, column),
+        |row| row.get(0),
+    )?;
+
+    Ok(count > 0)
+}
+
+fn add_column_if_missing(tx: &Transaction, table: &str, column: &str, definition: &str) -> Result<()> {
+    if !has_column(tx, table, column)? {
+        tx.execute(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"), [])?;
+    }
+
+    Ok(())
+}
+
+//the tables as the first release made them, with the missing comma in albums put back
+fn create_tables(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS artists (
+            id TEXT PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS folders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path TEXT NOT NULL UNIQUE
+        );
+
+        CREATE TABLE IF NOT EXISTS albums (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            cover_data BLOB,
+            cover_extension TEXT DEFAULT 'image/jpeg'
+        );
+
+        CREATE TABLE IF NOT EXISTS album_artists (
+            album_id TEXT NOT NULL,
+            artist_id TEXT,
+            artist_name TEXT NOT NULL,
+            is_primary BOOLEAN DEFAULT 0,
+            order_index INTEGER DEFAULT 0,
+            PRIMARY KEY(album_id, artist_name),
+            FOREIGN KEY(album_id) REFERENCES albums(id) ON DELETE CASCADE,
+            FOREIGN KEY(artist_id) REFERENCES artists(id) ON DELETE SET NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS songs (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            artist_id TEXT NOT NULL,
+            album_id TEXT NOT NULL,
+            folder_id ID NOT NULL,
+            cover_data BLOB,
+            track_num INTEGER,
+            disc_num INTEGER,
+            path TEXT NOT NULL UNIQUE,
+            duration REAL DEFAULT 0.0,
+            FOREIGN KEY(artist_id) REFERENCES artists(id) ON DELETE CASCADE,
+            FOREIGN KEY(album_id) REFERENCES albums(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS song_features (
+            artist_id TEXT NOT NULL,
+            song_id TEXT NOT NULL,
+            artist_name TEXT NOT NULL,
+            PRIMARY KEY(song_id, artist_id),
+            FOREIGN KEY(artist_id) REFERENCES artists(id),
+            FOREIGN KEY(song_id) REFERENCES songs(id) ON DELETE CASCADE
+        );",
+    )
+}
+
+//without the comma `cover_data BLOB cover_extension TEXT DEFAULT 'image/jpeg'` is one column called cover_data,
+//typed "BLOB cover_extension TEXT" and defaulting to the text 'image/jpeg', and there is no cover_extension.
+//sqlite cant drop or retype a column so the table is rebuilt, keeping only covers that really are images
+fn repair_albums(tx: &Transaction) -> Result<()> {
+    if has_column(tx, "albums", "cover_extension")? {
+        return Ok(());
+    }
+
+    tx.execute_batch(
+        "CREATE TABLE albums_repaired (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            cover_data BLOB,
+            cover_extension TEXT DEFAULT 'image/jpeg'
+        );
+
+        INSERT INTO albums_repaired (id, name, cover_data)
+            SELECT id, name, CASE WHEN typeof(cover_data) = 'blob' THEN cover_data END FROM albums;
+
+        DROP TABLE albums;
+        ALTER TABLE albums_repaired RENAME TO albums;",
+    )
+}
+
+fn add_scan_bookkeeping(tx: &Transaction) -> Result<()> {
+    add_column_if_missing(tx, "songs", "file_size", "INTEGER DEFAULT 0")?;
+    add_column_if_missing(tx, "songs", "modified", "INTEGER DEFAULT 0")?;
+    add_column_if_missing(tx, "songs", "fingerprint", "TEXT")?;
+
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY NOT NULL,
+            value TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS scan_reports (
+            folder_id INTEGER PRIMARY KEY NOT NULL,
+            scanned_at INTEGER NOT NULL,
+            report TEXT NOT NULL,
+            FOREIGN KEY(folder_id) REFERENCES folders(id) ON DELETE CASCADE
+        );",
+    )
+}
+
+fn add_cover_columns(tx: &Transaction) -> Result<()> {
+    add_column_if_missing(tx, "albums", "cover_source", "TEXT")?;
+    add_column_if_missing(tx, "albums", "cover_path", "TEXT")?;
+    add_column_if_missing(tx, "albums", "cover_hash", "TEXT")?;
+    add_column_if_missing(tx, "albums", "palette", "TEXT")
+}
+
+//features are saved before we know who most of them are, so artist_id has to be allowed to be empty
+//and the name is what keeps two features of a song apart, the same way album_artists works
+fn nullable_feature_artists(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE song_features_new (
+            artist_id TEXT,
+            song_id TEXT NOT NULL,
+            artist_name TEXT NOT NULL,
+            PRIMARY KEY(song_id, artist_name),
+            FOREIGN KEY(artist_id) REFERENCES artists(id) ON DELETE SET NULL,
+            FOREIGN KEY(song_id) REFERENCES songs(id) ON DELETE CASCADE
+        );
+
+        INSERT OR IGNORE INTO song_features_new (artist_id, song_id, artist_name)
+            SELECT artist_id, song_id, artist_name FROM song_features;
+
+        DROP TABLE song_features;
+        ALTER TABLE song_features_new RENAME TO song_features;",
+    )
+}
+
+//song pages are sorted and filtered in sql, these keep that from scanning the whole table for every page
+fn index_song_lists(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE INDEX songs_title ON songs(title COLLATE NOCASE);
+        CREATE INDEX songs_album_track ON songs(album_id, disc_num, track_num);
+        CREATE INDEX songs_artist ON songs(artist_id);
+        CREATE INDEX songs_folder ON songs(folder_id);
+        CREATE INDEX songs_duration ON songs(duration);
+        CREATE INDEX songs_fingerprint ON songs(fingerprint);
+        CREATE INDEX song_features_artist ON song_features(artist_id);
+        CREATE INDEX artists_name ON artists(name COLLATE NOCASE);
+        CREATE INDEX albums_name ON albums(name COLLATE NOCASE);",
+    )
+}
+
+//one fts5 table for songs, albums and artists so their scores can be compared for the top hit.
+//fts rowids arent tied to our uuids, search_docs maps between them
+fn add_search_index(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE search_docs (
+            rowid INTEGER PRIMARY KEY,
+            kind TEXT NOT NULL,
+            item_id TEXT NOT NULL,
+            UNIQUE(kind, item_id)
+        );
+
+        CREATE VIRTUAL TABLE search_index USING fts5(
+            name, artists, album, path,
+            tokenize = 'unicode61 remove_diacritics 2',
+            prefix = '2 3'
+        );
+
+        INSERT INTO search_docs (kind, item_id) SELECT 'artist', id FROM artists;
+        INSERT INTO search_docs (kind, item_id) SELECT 'album', id FROM albums;
+        INSERT INTO search_docs (kind, item_id) SELECT 'song', id FROM songs;
+
+        INSERT INTO search_index (rowid, name, artists, album, path)
+        SELECT d.rowid, ar.name, '', '', ''
+        FROM search_docs d JOIN artists ar ON ar.id = d.item_id
+        WHERE d.kind = 'artist';
+
+        INSERT INTO search_index (rowid, name, artists, album, path)
+        SELECT d.rowid, al.name,
+            COALESCE((SELECT group_concat(artist_name, ' ') FROM album_artists WHERE album_id = al.id), ''),
+            '', ''
+        FROM search_docs d JOIN albums al ON al.id = d.item_id
+        WHERE d.kind = 'album';
+
+        INSERT INTO search_index (rowid, name, artists, album, path)
+        SELECT d.rowid, s.title,
+            trim(COALESCE(ar.name, '') || ' ' ||
+                COALESCE((SELECT group_concat(artist_name, ' ') FROM song_features WHERE song_id = s.id), '')),
+            COALESCE(al.name, ''), s.path
+        FROM search_docs d
+        JOIN songs s ON s.id = d.item_id
+        LEFT JOIN artists ar ON ar.id = s.artist_id
+        LEFT JOIN albums al ON al.id = s.album_id
+        WHERE d.kind = 'song';",
+    )
+}
+
+//the keys themselves are worked out in rust when the library opens, see fill_sort_keys.
+//they replace the case insensitive name indexes, which nothing sorts by anymore
+fn add_sort_keys(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE songs ADD COLUMN sort_title TEXT;
+        ALTER TABLE albums ADD COLUMN sort_name TEXT;
+        ALTER TABLE artists ADD COLUMN sort_name TEXT;
+
+        DROP INDEX songs_title;
+        DROP INDEX albums_name;
+        DROP INDEX artists_name;
+
+        CREATE INDEX songs_sort_title ON songs(sort_title);
+        CREATE INDEX albums_sort_name ON albums(sort_name);
+        CREATE INDEX artists_sort_name ON artists(sort_name);",
+    )
+}
+
+//sort names read from the files, sort_name is the key worked out from them or the name
+fn add_sort_tags(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE artists ADD COLUMN sort_tag TEXT;
+        ALTER TABLE albums ADD COLUMN sort_tag TEXT;",
+    )
+}
+
+//genres are lists so they get their own tables like features and album artists do.
+//songs already in the library are read again on the next scan to pick the new tags up
+fn add_detailed_tags(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE songs ADD COLUMN year INTEGER;
+        ALTER TABLE songs ADD COLUMN composer TEXT;
+        ALTER TABLE songs ADD COLUMN conductor TEXT;
+        ALTER TABLE songs ADD COLUMN bpm INTEGER;
+        ALTER TABLE songs ADD COLUMN comment TEXT;
+
+        ALTER TABLE albums ADD COLUMN year INTEGER;
+        ALTER TABLE albums ADD COLUMN original_year INTEGER;
+        ALTER TABLE albums ADD COLUMN track_total INTEGER;
+        ALTER TABLE albums ADD COLUMN disc_total INTEGER;
+        ALTER TABLE albums ADD COLUMN label TEXT;
+
+        CREATE TABLE song_genres (
+            song_id TEXT NOT NULL,
+            genre TEXT NOT NULL COLLATE NOCASE,
+            order_index INTEGER NOT NULL,
+            PRIMARY KEY(song_id, genre),
+            FOREIGN KEY(song_id) REFERENCES songs(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE album_genres (
+            album_id TEXT NOT NULL,
+            genre TEXT NOT NULL COLLATE NOCASE,
+            order_index INTEGER NOT NULL,
+            PRIMARY KEY(album_id, genre),
+            FOREIGN KEY(album_id) REFERENCES albums(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX song_genres_genre ON song_genres(genre);
+        CREATE INDEX songs_year ON songs(year);
+        CREATE INDEX songs_composer ON songs(composer COLLATE NOCASE);
+
+        UPDATE songs SET modified = 0;",
+    )
+}
+
+//albums work out their year, totals and label from their songs, so the songs keep them too.
+//every file is read again on the next scan to fill them in
+fn add_song_album_tags(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE songs ADD COLUMN original_year INTEGER;
+        ALTER TABLE songs ADD COLUMN track_total INTEGER;
+        ALTER TABLE songs ADD COLUMN disc_total INTEGER;
+        ALTER TABLE songs ADD COLUMN label TEXT;
+
+        UPDATE songs SET modified = 0;",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)").unwrap();
+        stmt.query_map([table], |row| row.get(0)).unwrap().map(|c| c.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_fresh_database_gets_every_migration() {
+        let conn = Connection::open_in_memory().unwrap();
+
+        migrate(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+
+        let albums = columns(&conn, "albums");
+        for column in ["cover_data", "cover_extension", "cover_source", "cover_path", "cover_hash", "palette"] {
+            assert!(albums.contains(&column.to_string()), "albums is missing {column}");
+        }
+        assert!(columns(&conn, "songs").contains(&"fingerprint".to_string()));
+        assert!(columns(&conn, "song_genres").contains(&"genre".to_string()));
+
+        //running again is a no-op
+        migrate(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_first_release_database_is_repaired() {
+        let conn = Connection::open_in_memory().unwrap();
+
+        //the schema the first release created, comma bug included
+        conn.execute_batch(
+            "CREATE TABLE artists (id TEXT PRIMARY KEY NOT NULL, name TEXT NOT NULL);
+            CREATE TABLE folders (id INTEGER PRIMARY KEY AUTOINCREMENT, path TEXT NOT NULL UNIQUE);
+            CREATE TABLE albums (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                cover_data BLOB
+                cover_extension TEXT DEFAULT 'image/jpeg'
+            );
+            CREATE TABLE album_artists (
+                album_id TEXT NOT NULL, artist_id TEXT, artist_name TEXT NOT NULL,
+                is_primary BOOLEAN DEFAULT 0, order_index INTEGER DEFAULT 0,
+                PRIMARY KEY(album_id, artist_name),
+                FOREIGN KEY(album_id) REFERENCES albums(id) ON DELETE CASCADE,
+                FOREIGN KEY(artist_id) REFERENCES artists(id) ON DELETE SET NULL
+            );
+            CREATE TABLE songs (
+                id TEXT PRIMARY KEY, title TEXT NOT NULL, artist_id TEXT NOT NULL, album_id TEXT NOT NULL,
+                folder_id ID NOT NULL, cover_data BLOB, track_num INTEGER, disc_num INTEGER,
+                path TEXT NOT NULL UNIQUE, duration REAL DEFAULT 0.0,
+                FOREIGN KEY(artist_id) REFERENCES artists(id) ON DELETE CASCADE,
+                FOREIGN KEY(album_id) REFERENCES albums(id) ON DELETE CASCADE
+            );
+            CREATE TABLE song_features (
+                artist_id TEXT NOT NULL, song_id TEXT NOT NULL, artist_name TEXT NOT NULL,
+                PRIMARY KEY(song_id, artist_id),
+                FOREIGN KEY(artist_id) REFERENCES artists(id),
+                FOREIGN KEY(song_id) REFERENCES songs(id) ON DELETE CASCADE
+            );
+
+            INSERT INTO folders (path) VALUES ('/music');
+            INSERT INTO artists VALUES ('ar1', 'Band');
+            INSERT INTO albums (id, name) VALUES ('al1', 'Loud');
+            INSERT INTO albums (id, name, cover_data) VALUES ('al2', 'Quiet', x'FFD8FF');
+            INSERT INTO album_artists (album_id, artist_id, artist_name) VALUES ('al1', 'ar1', 'Band');
+            INSERT INTO songs (id, title, artist_id, album_id, folder_id, path) VALUES ('s1', 'Song', 'ar1', 'al1', 1, '/music/s1.mp3');
+            INSERT INTO song_features VALUES ('ar1', 's1', 'Band');",
+        )
+        .unwrap();
+        //the way Database opens it, so rebuilding albums would cascade if migrate didnt turn them off
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+
+        migrate(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+
+        //the text default that ended up in cover_data is dropped, real image bytes are kept
+        let covers: Vec<(String, Option<Vec<u8>>, Option<String>)> = conn
+            .prepare("SELECT id, cover_data, cover_extension FROM albums ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(covers, vec![
+            ("al1".to_string(), None, Some("image/jpeg".to_string())),
+            ("al2".to_string(), Some(vec![0xFF, 0xD8, 0xFF]), Some("image/jpeg".to_string())),
+        ]);
+
+        //rebuilding albums mustnt have cascaded into the tables pointing at it
+        let counts: (usize, usize, usize) = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM songs), (SELECT COUNT(*) FROM album_artists), (SELECT COUNT(*) FROM song_features)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(counts, (1, 1, 1));
+
+        //whatever was already in the library can be searched straight away
+        let found: Vec<String> = conn
+            .prepare("SELECT d.kind FROM search_index JOIN search_docs d ON d.rowid = search_index.rowid WHERE search_index MATCH 'ban*' ORDER BY d.kind")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(found, vec!["album", "artist", "song"]);
+
+        //unknown feature artists can be saved now
+        conn.execute("INSERT INTO song_features (artist_id, song_id, artist_name) VALUES (NULL, 's1', 'Guest')", []).unwrap();
+    }
+
+    #[test]
+    fn test_in_between_database_is_upgraded() {
+        let conn = Connection::open_in_memory().unwrap();
+
+        //builds before versioning added columns in place as they started, so a library
+        //made by one of them has some of the later columns and a user_version of 0
+        conn.execute_batch(
+            "CREATE TABLE artists (id TEXT PRIMARY KEY NOT NULL, name TEXT NOT NULL);
+            CREATE TABLE folders (id INTEGER PRIMARY KEY AUTOINCREMENT, path TEXT NOT NULL UNIQUE);
+            CREATE TABLE albums (id TEXT PRIMARY KEY, name TEXT NOT NULL, cover_data BLOB, cover_extension TEXT DEFAULT 'image/jpeg');
+            CREATE TABLE album_artists (
+                album_id TEXT NOT NULL, artist_id TEXT, artist_name TEXT NOT NULL,
+                is_primary BOOLEAN DEFAULT 0, order_index INTEGER DEFAULT 0,
+                PRIMARY KEY(album_id, artist_name),
+                FOREIGN KEY(album_id) REFERENCES albums(id) ON DELETE CASCADE,
+                FOREIGN KEY(artist_id) REFERENCES artists(id) ON DELETE SET NULL
+            );
+            CREATE TABLE songs (
+                id TEXT PRIMARY KEY, title TEXT NOT NULL, artist_id TEXT NOT NULL, album_id TEXT NOT NULL,
+                folder_id ID NOT NULL, cover_data BLOB, track_num INTEGER, disc_num INTEGER,
+                path TEXT NOT NULL UNIQUE, duration REAL DEFAULT 0.0,
+                file_size INTEGER DEFAULT 0, modified INTEGER DEFAULT 0,
+                FOREIGN KEY(artist_id) REFERENCES artists(id) ON DELETE CASCADE,
+                FOREIGN KEY(album_id) REFERENCES albums(id) ON DELETE CASCADE
+            );
+            CREATE TABLE song_features (
+                artist_id TEXT NOT NULL, song_id TEXT NOT NULL, artist_name TEXT NOT NULL,
+                PRIMARY KEY(song_id, artist_id),
+                FOREIGN KEY(artist_id) REFERENCES artists(id),
+                FOREIGN KEY(song_id) REFERENCES songs(id) ON DELETE CASCADE
+            );
+            CREATE TABLE settings (key TEXT PRIMARY KEY NOT NULL, value TEXT NOT NULL);
+
+            INSERT INTO folders (path) VALUES ('/music');
+            INSERT INTO artists VALUES ('ar1', 'Band');
+            INSERT INTO albums (id, name) VALUES ('al1', 'Loud');
+            INSERT INTO songs (id, title, artist_id, album_id, folder_id, path, file_size, modified)
+                VALUES ('s1', 'Song', 'ar1', 'al1', 1, '/music/s1.mp3', 4096, 1700000000);
+            INSERT INTO settings VALUES ('scan', '{}');",
+        )
+        .unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+
+        migrate(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+
+        let songs = columns(&conn, "songs");
+        for column in ["file_size", "modified", "fingerprint", "year", "composer", "label"] {
+            assert!(songs.contains(&column.to_string()), "songs is missing {column}");
+        }
+        assert!(columns(&conn, "albums").contains(&"palette".to_string()));
+
+        //what was already there survives, only modified is cleared so the next scan reads the new tags
+        let (size, modified): (i64, i64) = conn.query_row("SELECT file_size, modified FROM songs WHERE id = 's1'", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!((size, modified), (4096, 0));
+        let setting: String = conn.query_row("SELECT value FROM settings WHERE key = 'scan'", [], |row| row.get(0)).unwrap();
+        assert_eq!(setting, "{}");
+    }
+
+    fn create_a(tx: &Transaction) -> Result<()> {
+        tx.execute_batch("CREATE TABLE a (id INTEGER)")
+    }
+
+    fn create_b_then_fail(tx: &Transaction) -> Result<()> {
+        tx.execute_batch("CREATE TABLE b (id INTEGER); INSERT INTO missing VALUES (1);")
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration { name: "a", run: create_a },
+            Migration { name: "b", run: create_b_then_fail },
+        ];
+
+        assert!(run_migrations(&conn, &migrations).is_err());
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+
+        let tables: Vec<String> = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(tables, vec!["a"]);
+        assert!(conn.is_autocommit());
+
+        //a database from a newer build is left alone
+        conn.pragma_update(None, "user_version", 3).unwrap();
+        assert!(run_migrations(&conn, &migrations).is_err());
+    }
+}
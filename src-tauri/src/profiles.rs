@@ -0,0 +1,247 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+
+//points the whole app at a different data directory, handy for testing against a throwaway library
+pub const DATA_DIR_ENV: &str = "BLEH_DATA_DIR";
+
+//opens a profile other than the one saved in the config for this run only
+pub const PROFILE_ENV: &str = "BLEH_PROFILE";
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+//lives in the app config dir rather than the database, since it decides which database to open
+const CONFIG_FILE: &str = "config.json";
+
+const DB_FILE: &str = "library.db";
+const COVERS_DIR: &str = "covers";
+
+static LOCATION: OnceLock<LibraryLocation> = OnceLock::new();
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AppConfig {
+    //where profiles are kept instead of the app data dir
+    pub data_dir: Option<PathBuf>,
+    pub profile: Option<String>,
+}
+
+//the library profile this run uses, each profile has its own database and cover cache
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryLocation {
+    pub data_dir: PathBuf,
+    pub profile: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfilesInfo {
+    pub current: String,
+    pub data_dir: PathBuf,
+    pub profiles: Vec<String>,
+}
+
+impl LibraryLocation {
+    //env vars win over the config, which wins over the defaults
+    pub fn resolve<E: Fn(&str) -> Option<String>>(app_data_dir: PathBuf, config: &AppConfig, env: E) -> Self {
+        let data_dir = env(DATA_DIR_ENV)
+            .map(PathBuf::from)
+            .or_else(|| config.data_dir.clone())
+            .unwrap_or(app_data_dir);
+
+        let profile = env(PROFILE_ENV)
+            .or_else(|| config.profile.clone())
+            .filter(|p| {
+                let valid = is_valid_profile_name(p);
+                if !valid {
+                    println!("ignoring invalid profile name {p:?}, using {DEFAULT_PROFILE}");
+                }
+                valid
+            })
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+
+        LibraryLocation { data_dir, profile }
+    }
+
+    pub fn profile_dir(&self) -> PathBuf {
+        self.data_dir.join("profiles").join(&self.profile)
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.profile_dir().join(DB_FILE)
+    }
+
+    pub fn covers_dir(&self) -> PathBuf {
+        self.profile_dir().join(COVERS_DIR)
+    }
+}
+
+//set once during setup, before anything opens the database
+pub fn init(location: LibraryLocation) {
+    if LOCATION.set(location).is_err() {
+        println!("library location was already set, keeping the first one");
+    }
+}
+
+pub fn current() -> &'static LibraryLocation {
+    match LOCATION.get() {
+        Some(l) => l,
+        None => panic!("library location used before it was resolved during setup"),
+    }
+}
+
+//profile names become folder names, so nothing that could climb out of the profiles folder
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == ' ')
+        && name.trim() == name
+}
+
+pub fn list_profiles(data_dir: &Path) -> Vec<String> {
+    let entries = match fs::read_dir(data_dir.join("profiles")) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut profiles: Vec<String> = entries
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| is_valid_profile_name(name))
+        .collect();
+
+    profiles.sort();
+    profiles
+}
+
+pub fn load_config(config_dir: &Path) -> AppConfig {
+    let contents = match fs::read_to_string(config_dir.join(CONFIG_FILE)) {
+        Ok(c) => c,
+        Err(_) => return AppConfig::default(),
+    };
+
+    match serde_json::from_str(&contents) {
+        Ok(c) => c,
+        Err(e) => {
+            println!("failed to read {CONFIG_FILE}, using defaults: {e}");
+            AppConfig::default()
+        }
+    }
+}
+
+pub fn save_config(config_dir: &Path, config: &AppConfig) -> io::Result<()> {
+    fs::create_dir_all(config_dir)?;
+    let contents = serde_json::to_string_pretty(config).map_err(io::Error::other)?;
+    fs::write(config_dir.join(CONFIG_FILE), contents)
+}
+
+//builds before this kept library.db and the cover cache in the source tree, <crate>/db.
+//the first time the default profile is opened without a database they are moved over, returns whether anything was
+pub fn migrate_legacy_library(legacy_dir: &Path, location: &LibraryLocation) -> io::Result<bool> {
+    let legacy_db = legacy_dir.join(DB_FILE);
+
+    if location.profile != DEFAULT_PROFILE || !legacy_db.is_file() || location.db_path().exists() {
+        return Ok(false);
+    }
+
+    fs::create_dir_all(location.profile_dir())?;
+    move_path(&legacy_db, &location.db_path())?;
+
+    let legacy_covers = legacy_dir.join(COVERS_DIR);
+    if legacy_covers.is_dir() && !location.covers_dir().exists() {
+        move_path(&legacy_covers, &location.covers_dir())?;
+    }
+
+    Ok(true)
+}
+
+//rename when both are on the same drive, copy and delete when they arent
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    copy_recursive(from, to)?;
+
+    if from.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_file() {
+        fs::copy(from, to)?;
+        return Ok(());
+    }
+
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_location() {
+        let app_data = PathBuf::from("/data/bleh");
+        let no_env = |_: &str| None;
+
+        let location = LibraryLocation::resolve(app_data.clone(), &AppConfig::default(), no_env);
+        assert_eq!(location.db_path(), PathBuf::from("/data/bleh/profiles/default/library.db"));
+
+        let config = AppConfig {
+            data_dir: Some(PathBuf::from("/mnt/music-data")),
+            profile: Some("work".into()),
+        };
+        let location = LibraryLocation::resolve(app_data.clone(), &config, no_env);
+        assert_eq!(location.covers_dir(), PathBuf::from("/mnt/music-data/profiles/work/covers"));
+
+        let env = |key: &str| match key {
+            DATA_DIR_ENV => Some("/tmp/scratch".to_string()),
+            PROFILE_ENV => Some("../escape".to_string()),
+            _ => None,
+        };
+        let location = LibraryLocation::resolve(app_data, &config, env);
+        assert_eq!(location.profile_dir(), PathBuf::from("/tmp/scratch/profiles/default"));
+    }
+
+    #[test]
+    fn test_legacy_library_moves_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("src-tauri/db");
+        fs::create_dir_all(legacy.join("covers/ab")).unwrap();
+        fs::write(legacy.join("library.db"), b"db").unwrap();
+        fs::write(legacy.join("covers/ab/abc.jpg"), b"art").unwrap();
+
+        let location = LibraryLocation {
+            data_dir: dir.path().join("data"),
+            profile: DEFAULT_PROFILE.into(),
+        };
+
+        let other = LibraryLocation {
+            profile: "work".into(),
+            ..location.clone()
+        };
+        assert!(!migrate_legacy_library(&legacy, &other).unwrap());
+
+        assert!(migrate_legacy_library(&legacy, &location).unwrap());
+        assert_eq!(fs::read(location.db_path()).unwrap(), b"db");
+        assert_eq!(fs::read(location.covers_dir().join("ab/abc.jpg")).unwrap(), b"art");
+        assert!(!legacy.join("library.db").exists());
+
+        assert!(!migrate_legacy_library(&legacy, &location).unwrap());
+        assert_eq!(list_profiles(&location.data_dir), vec![DEFAULT_PROFILE.to_string()]);
+    }
+}
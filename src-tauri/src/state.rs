@@ -1,24 +1,34 @@
-use crate::{audio, db_dir, AppState};
-use crate::core::song::{Album, Artist, ArtistType, Image, Song};
+use crate::{audio, covers_dir, db_dir, migrations};
+use crate::db::Database;
+use crate::store::{LibraryStore, SqliteStore};
+use crate::core::cover_cache::CoverCache;
+use crate::core::palette::Palette;
+use crate::core::song::{Album, AlbumTags, Artist, ArtistType, CoverSource, Image, Song};
+use crate::core::query::{GenreCount, SongPage, SongQuery, SongSort, SongSortKey};
+use crate::core::scan::{FileStats, ScanReport, ScanSettings};
+use crate::core::search::{match_expression, pick_top_hit, SearchKind, SearchMatches};
+use crate::core::sort_key::{sort_key, SortSettings, SORT_SETTINGS_KEY};
+use crate::SongToSend;
 
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::sync::{Arc, mpsc};
 use std::collections::{HashMap, HashSet};
 
-use tauri::State;
 use uuid::Uuid;
-use rusqlite::{Connection, Result, Transaction};
+use rusqlite::{Connection, OptionalExtension, Result};
+use serde::{de::DeserializeOwned, Serialize};
 
 pub struct MusicLibrary {
-    pub songs: HashMap<Uuid, Song>,
     pub albums: HashMap<Uuid, Album>,
     pub artist_manager: ArtistManager,
     pub player: audio::PlayerController,
-    pub db_conn: Connection,
-    pub required_covers: HashSet<Uuid>,
+    pub store: Arc<dyn LibraryStore>,
+    pub covers: CoverCache,
     pub folders: HashMap<i64, PathBuf>,
+    pub scan_settings: ScanSettings,
 }
 
 pub struct ArtistManager {
@@ -34,19 +44,35 @@ impl MusicLibrary {
         //rodio already uses a seperate thread but rodio structs arent send or sync so cant be stored in tauri app state
         //detached thread is used to mitigate this
 
-        let dir = db_dir();
-        let conn = match Connection::open(&dir) {
-            Ok(c) => c,
+        let db = match Database::open(db_dir()) {
+            Ok(d) => d,
             Err(e) => panic!("failed to init db {e}"),
         };
-        
-        if let Err(e) = init_db(&conn) {
-            panic!("Failed to initialize database schema: {}", e);
+
+        let covers = CoverCache::new(covers_dir());
+        update_album_covers(&db.writer(), &covers);
+
+        match fill_sort_keys(&db.writer()) {
+            Ok(0) => {}
+            Ok(n) => println!("worked out {n} missing sort keys"),
+            Err(e) => println!("failed to work out missing sort keys: {e}"),
         }
 
+        let (sender, receiver) = mpsc::channel();
+        let (sender2, receiver2) = mpsc::channel();
+
+        thread::spawn(move || {
+            audio::audio_thread_loop(receiver, sender2);
+        });
+
+        Self::from_store(Arc::new(SqliteStore::new(db)), covers, audio::PlayerController::new(sender, receiver2))
+    }
+
+    //loads everything from an already open store, split out of new so tests can skip the audio thread
+    pub fn from_store(store: Arc<dyn LibraryStore>, covers: CoverCache, player: audio::PlayerController) -> Self {
         println!("Loading music library from database...");
 
-        let folders = match get_all_folders(&conn) {
+        let folders = match store.folders() {
             Ok(f) => {
                 println!("loaded saved folders from databse {}", f.len());
                 f
@@ -57,18 +83,7 @@ impl MusicLibrary {
             }
         };
         
-        let songs = match get_all_songs(&conn) {
-            Ok(s) => {
-                println!("Loaded {} songs from database", s.len());
-                s
-            },
-            Err(e) => {
-                println!("Failed to load songs from database: {}", e);
-                HashMap::new()
-            }
-        };
-        
-        let albums = match get_all_albums(&conn) {
+        let albums = match store.albums() {
             Ok(a) => {
                 println!("Loaded {} albums from database", a.len());
                 a
@@ -79,7 +94,7 @@ impl MusicLibrary {
             }
         };
         
-        let artists = match get_all_artists(&conn) {
+        let artists = match store.artists() {
             Ok(a) => {
                 println!("Loaded {} artists from database", a.len());
                 a
@@ -90,115 +105,178 @@ impl MusicLibrary {
             }
         };
         
+        let scan_settings = match store.scan_settings() {
+            Ok(s) => s.unwrap_or_default(),
+            Err(e) => {
+                println!("failed to load scan settings, using defaults: {e}");
+                ScanSettings::default()
+            }
+        };
+
         let mut known_artists = HashMap::new();
         for (id, _) in &artists {
             known_artists.insert(*id, ArtistType::KnownArtist(*id));
         }
         println!("Initialized {} known artists", known_artists.len());
 
-        let (sender, receiver) = mpsc::channel();
-        let (sender2, receiver2) = mpsc::channel();
-
-        thread::spawn(move || {
-            audio::audio_thread_loop(receiver, sender2);
-        });
-
         MusicLibrary {
-            songs,
             albums,
             artist_manager: ArtistManager {
                 artists,
                 known_artists,
             },
-            player: audio::PlayerController::new(sender, receiver2),
-            db_conn: conn,
-            required_covers: HashSet::new(),
+            player,
+            store,
+            covers,
             folders: folders,
-            
+            scan_settings,
         }
     }
 
-    
-    
+    //applies what a scan or watcher batch committed, everything it didnt touch stays as it is
+    pub fn merge_changes(&mut self, changes: LibraryChanges) {
+        self.albums.extend(changes.albums);
+
+        for (id, tags) in &changes.album_tags {
+            if let Some(album) = self.albums.get_mut(id) {
+                tags.apply(album);
+            }
+        }
+
+        for (id, artist) in changes.artists {
+            self.artist_manager.known_artists.insert(id, ArtistType::KnownArtist(id));
+            self.artist_manager.artists.insert(id, artist);
+        }
+
+        for id in &changes.removed_albums {
+            self.albums.remove(id);
+        }
+
+        for id in &changes.removed_artists {
+            self.artist_manager.artists.remove(id);
+            self.artist_manager.known_artists.remove(id);
+        }
+
+        for id in &changes.removed_folders {
+            self.folders.remove(id);
+        }
+
+        self.folders.extend(changes.folders);
+    }
+}
+
+//everything a scan changed in the database, applied to the in-memory library once the transaction is committed
+#[derive(Default)]
+pub struct LibraryChanges {
+    pub albums: HashMap<Uuid, Album>,
+    pub removed_albums: Vec<Uuid>,
+    //worked out again from the songs, for albums the batch added, removed or moved songs of
+    pub album_tags: HashMap<Uuid, AlbumTags>,
+    pub artists: HashMap<Uuid, Artist>,
+    pub removed_artists: Vec<Uuid>,
+    pub folders: HashMap<i64, PathBuf>,
+    pub removed_folders: Vec<i64>,
+}
+
+impl LibraryChanges {
+    //the albums and artists the songs point to are copied along so new ones show up straight away
+    pub fn with_songs(songs: &[Song], albums: &HashMap<Uuid, Album>, artists: &HashMap<Uuid, Artist>) -> Self {
+        let mut changes = LibraryChanges::default();
+
+        for song in songs {
+            if let Some(artist) = artists.get(&song.artist) {
+                changes.artists.insert(artist.id, artist.clone());
+            }
+
+            if let Some(album) = albums.get(&song.album) {
+                for id in album.artists.iter().filter_map(|(id, _)| *id) {
+                    if let Some(artist) = artists.get(&id) {
+                        changes.artists.insert(id, artist.clone());
+                    }
+                }
+
+                changes.albums.insert(album.id, album.clone());
+            }
+        }
+
+        changes
+    }
 }
 
+pub const SCAN_SETTINGS_KEY: &str = "scan_settings";
+
+//brings the schema up to date, foreign keys are a per connection setting so every connection needs this
 pub fn init_db(conn: &Connection) -> Result<()>{
+    migrations::migrate(conn)?;
     conn.execute("PRAGMA foreign_keys = ON", [])?;
-    
-    conn.execute(
-    "CREATE TABLE IF NOT EXISTS artists (
-        id TEXT PRIMARY KEY NOT NULL,
-        name TEXT NOT NULL)",
-        [],
-    )?;
 
-    conn.execute(
-    "CREATE TABLE IF NOT EXISTS folders (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            path TEXT NOT NULL UNIQUE
-        )",
-    [],
-    )?;
+    Ok(())
+}
 
-    conn.execute(
-    "CREATE TABLE IF NOT EXISTS albums (
-            id TEXT PRIMARY KEY,
-            name TEXT NOT NULL,
-            cover_data BLOB
-            cover_extension TEXT DEFAULT 'image/jpeg'
-        )",
-    []
-    )?;
+//settings are stored as json so new fields can be added without touching the schema
+pub fn get_setting<T: DeserializeOwned>(conn: &Connection, key: &str) -> Result<Option<T>, rusqlite::Error> {
+    let value: Option<String> = conn.query_row(
+        "SELECT value FROM settings WHERE key = ?1",
+        [key],
+        |row| row.get(0)
+    ).optional()?;
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS album_artists (
-            album_id TEXT NOT NULL,
-            artist_id TEXT,                    
-            artist_name TEXT NOT NULL,         
-            is_primary BOOLEAN DEFAULT 0,     
-            order_index INTEGER DEFAULT 0,    
-            PRIMARY KEY(album_id, artist_name),
-            FOREIGN KEY(album_id) REFERENCES albums(id) ON DELETE CASCADE,
-            FOREIGN KEY(artist_id) REFERENCES artists(id) ON DELETE SET NULL
-        )",
-        []
-    )?;
+    match value {
+        Some(v) => serde_json::from_str(&v)
+            .map(Some)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        None => Ok(None),
+    }
+}
+
+pub fn set_setting<T: Serialize>(conn: &Connection, key: &str, value: &T) -> Result<(), rusqlite::Error> {
+    let value = serde_json::to_string(value)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
 
     conn.execute(
-    "CREATE TABLE IF NOT EXISTS songs (
-            id TEXT PRIMARY KEY,
-            title TEXT NOT NULL,
-            artist_id TEXT NOT NULL,
-            album_id TEXT NOT NULL,
-            folder_id ID NOT NULL,
-            cover_data BLOB,
-            track_num INTEGER,
-            disc_num INTEGER,
-            path TEXT NOT NULL UNIQUE,
-            duration REAL DEFAULT 0.0,
-
-            FOREIGN KEY(artist_id) REFERENCES artists(id) ON DELETE CASCADE,
-            FOREIGN KEY(album_id) REFERENCES albums(id) ON DELETE CASCADE
-        )",
-[]
+        "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+        (key, value),
     )?;
 
+    Ok(())
+}
+
+//only the latest scan of each folder is kept, stored as json like the settings
+pub fn save_scan_report(conn: &Connection, folder_id: i64, report: &ScanReport) -> Result<(), rusqlite::Error> {
+    let value = serde_json::to_string(report)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    let scanned_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+
     conn.execute(
-    "CREATE TABLE IF NOT EXISTS song_features (
-            artist_id TEXT NOT NULL,
-            song_id TEXT NOT NULL,
-            artist_name TEXT NOT NULL,
-            PRIMARY KEY(song_id, artist_id),
-            FOREIGN KEY(artist_id) REFERENCES artists(id),
-            FOREIGN KEY(song_id) REFERENCES songs(id) ON DELETE CASCADE
-        )",
-[]
+        "INSERT OR REPLACE INTO scan_reports (folder_id, scanned_at, report) VALUES (?1, ?2, ?3)",
+        (folder_id, scanned_at, value),
     )?;
 
     Ok(())
 }
 
-pub fn insert_folder_and_get_id<P: AsRef<Path>>(tx: &Transaction, path: P, state: State<AppState>) -> Result<i64, rusqlite::Error> {
+pub fn get_scan_report(conn: &Connection, folder_id: i64) -> Result<Option<ScanReport>, rusqlite::Error> {
+    let value: Option<String> = conn.query_row(
+        "SELECT report FROM scan_reports WHERE folder_id = ?1",
+        [folder_id],
+        |row| row.get(0)
+    ).optional()?;
+
+    match value {
+        Some(v) => serde_json::from_str(&v)
+            .map(Some)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))),
+        None => Ok(None),
+    }
+}
+
+//the functions taking tx write and are only called inside a transaction, see SqliteTransaction
+//the in-memory folder list is only updated by the caller once the transaction is committed
+pub fn insert_folder_and_get_id<P: AsRef<Path>>(tx: &Connection, path: P) -> Result<i64, rusqlite::Error> {
     match tx.execute(
         "INSERT OR IGNORE INTO folders (path) VALUES (?1)",
         [path.as_ref().to_string_lossy()],
@@ -211,49 +289,170 @@ pub fn insert_folder_and_get_id<P: AsRef<Path>>(tx: &Transaction, path: P, state
                 |row| row.get(0)
             )
         }
-        _ => {
-            // new folder inserted
-            let mut state = state.lock().unwrap();
-            let id = tx.last_insert_rowid();
-            state.folders.insert(id, path.as_ref().to_path_buf());
-            Ok(id)
-        }
+        _ => Ok(tx.last_insert_rowid()),
     }
 }
 
 
+//moves the songs of every library folder below path into folder_id and drops those folders
+//returns the ids of the folders that were merged away
+pub fn absorb_nested_folders(tx: &Connection, folder_id: i64, path: &Path) -> Result<Vec<i64>, rusqlite::Error> {
+    let nested: Vec<i64> = get_all_folders(tx)?
+        .into_iter()
+        .filter(|(id, p)| *id != folder_id && p.starts_with(path))
+        .map(|(id, _)| id)
+        .collect();
+
+    for id in &nested {
+        tx.execute("UPDATE songs SET folder_id = ?1 WHERE folder_id = ?2", [folder_id, *id])?;
+        tx.execute("DELETE FROM folders WHERE id = ?1", [*id])?;
+    }
+
+    Ok(nested)
+}
+
+pub fn get_song_paths_in_folder(conn: &Connection, folder_id: i64) -> Result<Vec<(Uuid, PathBuf)>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT id, path FROM songs WHERE folder_id = ?1")?;
+
+    let songs = stmt.query_map([folder_id], |row| {
+        let id_str: String = row.get(0)?;
+        let path: String = row.get(1)?;
+        let id = Uuid::parse_str(&id_str)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text))?;
+        Ok((id, PathBuf::from(path)))
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(songs)
+}
+
+//what a rescan compares the files it finds against, only the stats and never the whole song
+pub fn get_song_stats_in_folder(conn: &Connection, folder_id: i64, dir: &Path) -> Result<Vec<(Uuid, PathBuf, FileStats)>, rusqlite::Error> {
+    let file = dir.to_string_lossy().to_string();
+    let mut prefix = file.clone();
+    if !prefix.ends_with(std::path::MAIN_SEPARATOR) {
+        prefix.push(std::path::MAIN_SEPARATOR);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id, path, file_size, modified FROM songs
+         WHERE folder_id = ?1 AND (path = ?2 OR substr(path, 1, length(?3)) = ?3)"
+    )?;
+
+    let songs = stmt.query_map((folder_id, file, prefix), |row| {
+        let id_str: String = row.get(0)?;
+        let path: String = row.get(1)?;
+        let size: i64 = row.get(2)?;
+        let id = Uuid::parse_str(&id_str)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text))?;
+        Ok((id, PathBuf::from(path), FileStats { size: size as u64, modified: row.get(3)? }))
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(songs)
+}
+
+pub fn set_song_folder(tx: &Connection, song_id: Uuid, folder_id: i64) -> Result<(), rusqlite::Error> {
+    tx.execute(
+        "UPDATE songs SET folder_id = ?1 WHERE id = ?2",
+        (folder_id, song_id.to_string()),
+    )?;
+    Ok(())
+}
+
+//articles are the ones skipped in sort keys, see SortSettings::articles
 pub fn insert_song_to_db(
-    tx: &Transaction,
+    tx: &Connection,
     song: &Song,
     artists: &HashMap<Uuid, Artist>,
-    albums: &HashMap<Uuid, Album>
+    albums: &HashMap<Uuid, Album>,
+    articles: &[String],
 ) -> Result<(), rusqlite::Error> {
 
-    if let Some(artist) = artists.get(&song.artist) {
-        tx.execute(
-            "INSERT OR IGNORE INTO artists (id, name) VALUES (?1, ?2)",
-            (song.artist.to_string(), &artist.name),
+    //the album artists are saved too, their sort name can come from this song's album artist sort tag
+    let album_artists = albums.get(&song.album).into_iter().flat_map(|album| album.artists.iter().filter_map(|(id, _)| *id));
+    for artist in std::iter::once(song.artist).chain(album_artists).filter_map(|id| artists.get(&id)) {
+        tx.prepare_cached(
+            "INSERT INTO artists (id, name, sort_tag, sort_name) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(id) DO UPDATE SET sort_tag = excluded.sort_tag, sort_name = excluded.sort_name
+             WHERE excluded.sort_tag IS NOT NULL"
+        )?.execute(
+            (
+                artist.id.to_string(),
+                &artist.name,
+                &artist.sort_name,
+                sort_key(artist.sort_name.as_deref().unwrap_or(&artist.name), articles),
+            ),
         )?;
     }
 
     if let Some(album) = albums.get(&song.album) {
-        tx.execute(
-            "INSERT OR IGNORE INTO albums (id, name, cover_data, cover_extension) VALUES (?1, ?2, ?3, ?4)",
-            (
-            album.id.to_string(),
-            &album.title,
-            album.cover.as_ref().map(|img| &img.data),
-            album.cover.as_ref().map(|img| &img.extension),
-            ),
+        tx.prepare_cached(
+            "INSERT INTO albums (id, name, cover_hash, cover_source, cover_path, palette, sort_tag, sort_name)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(id) DO UPDATE SET
+                cover_hash = COALESCE(excluded.cover_hash, cover_hash),
+                cover_source = CASE WHEN excluded.cover_hash IS NULL OR excluded.cover_hash IS cover_hash THEN cover_source ELSE excluded.cover_source END,
+                cover_path = CASE WHEN excluded.cover_hash IS NULL OR excluded.cover_hash IS cover_hash THEN cover_path ELSE excluded.cover_path END,
+                palette = CASE WHEN excluded.cover_hash IS NULL OR excluded.cover_hash IS cover_hash THEN palette ELSE excluded.palette END,
+                sort_tag = COALESCE(excluded.sort_tag, sort_tag),
+                sort_name = CASE WHEN excluded.sort_tag IS NULL THEN sort_name ELSE excluded.sort_name END"
+        )?.execute(
+            rusqlite::params![
+                album.id.to_string(),
+                &album.title,
+                &album.cover,
+                album.cover_source.as_ref().map(cover_source_kind),
+                match &album.cover_source {
+                    Some(CoverSource::Sidecar(path)) => Some(path.to_string_lossy()),
+                    _ => None,
+                },
+                album.palette.as_ref().and_then(|p| serde_json::to_string(p).ok()),
+                &album.sort_name,
+                sort_key(album.sort_name.as_deref().unwrap_or(&album.title), articles),
+            ],
         )?;
 
+        //the year, genres and the rest are filled in by update_album_tags once the album's songs are saved
         insert_album_artists(tx, album.id, &album.artists)?;
     }
 
-    tx.execute(
-        "INSERT OR REPLACE INTO songs (id, title, artist_id, album_id, folder_id, track_num, disc_num, path, duration) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-        (
+    //paths are unique too, a row left at this path under another id would make the upsert fail
+    if let Some((id, _)) = get_song_stats_by_path(tx, &song.path)? {
+        if id != song.id {
+            delete_song_from_db(tx, id)?;
+        }
+    }
+
+    //upsert on the id so a rescan updates the existing row in place, moved files keep their id and get the new path
+    tx.prepare_cached(
+        "INSERT INTO songs (id, title, artist_id, album_id, folder_id, track_num, disc_num, path, duration, file_size, modified, fingerprint, sort_title,
+            year, composer, conductor, bpm, comment, original_year, track_total, disc_total, label)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)
+         ON CONFLICT(id) DO UPDATE SET
+            path = excluded.path,
+            title = excluded.title,
+            sort_title = excluded.sort_title,
+            artist_id = excluded.artist_id,
+            album_id = excluded.album_id,
+            folder_id = excluded.folder_id,
+            track_num = excluded.track_num,
+            disc_num = excluded.disc_num,
+            duration = excluded.duration,
+            file_size = excluded.file_size,
+            modified = excluded.modified,
+            fingerprint = excluded.fingerprint,
+            year = excluded.year,
+            composer = excluded.composer,
+            conductor = excluded.conductor,
+            bpm = excluded.bpm,
+            comment = excluded.comment,
+            original_year = excluded.original_year,
+            track_total = excluded.track_total,
+            disc_total = excluded.disc_total,
+            label = excluded.label"
+    )?.execute(
+        rusqlite::params![
             song.id.to_string(),
             &song.title,
             song.artist.to_string(),
@@ -263,13 +462,36 @@ pub fn insert_song_to_db(
             song.disc_num,
             song.path.to_string_lossy(),
             song.duration,
-        ),
+            song.file_size as i64,
+            song.modified,
+            &song.fingerprint,
+            sort_key(&song.title, articles),
+            song.year,
+            &song.composer,
+            &song.conductor,
+            song.bpm,
+            &song.comment,
+            song.original_year,
+            song.track_total,
+            song.disc_total,
+            &song.label,
+        ],
+    )?;
+
+    tx.prepare_cached("DELETE FROM song_genres WHERE song_id = ?1")?.execute([song.id.to_string()])?;
+    insert_genres(tx, "song_genres", "song_id", song.id, &song.genres)?;
+
+    tx.prepare_cached(
+        "DELETE FROM song_features WHERE song_id = ?1"
+    )?.execute(
+        [song.id.to_string()],
     )?;
 
     if let Some(features) = &song.features {
         for (artist_id, artist_name) in features {
-            tx.execute(
-                "INSERT OR IGNORE INTO song_features (song_id, artist_id, artist_name) VALUES (?1, ?2, ?3)",
+            tx.prepare_cached(
+                "INSERT OR IGNORE INTO song_features (song_id, artist_id, artist_name) VALUES (?1, ?2, ?3)"
+            )?.execute(
                 (
                     song.id.to_string(),
                     artist_id.map(|id| id.to_string()),
@@ -279,18 +501,134 @@ pub fn insert_song_to_db(
         }
     }
 
+    //the artists and albums are indexed once per transaction with index_artists_and_albums
+    index_for_search(tx, SearchKind::Song, song.id)?;
+
     Ok(())
 }
 
+pub fn get_song_stats_by_path(conn: &Connection, path: &Path) -> Result<Option<(Uuid, FileStats)>, rusqlite::Error> {
+    let row: Option<(String, i64, i64)> = conn.prepare_cached(
+        "SELECT id, file_size, modified FROM songs WHERE path = ?1"
+    )?.query_row(
+        [path.to_string_lossy()],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    ).optional()?;
+
+    match row {
+        Some((id_str, size, modified)) => {
+            let id = Uuid::parse_str(&id_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text))?;
+
+            Ok(Some((id, FileStats { size: size as u64, modified })))
+        }
+        None => Ok(None),
+    }
+}
+
+//ids of the song at exactly this path, or every song below it if the path was a directory
+pub fn get_song_ids_under_path(conn: &Connection, path: &Path) -> Result<Vec<Uuid>, rusqlite::Error> {
+    let file = path.to_string_lossy().to_string();
+    let mut dir = file.clone();
+    if !dir.ends_with(std::path::MAIN_SEPARATOR) {
+        dir.push(std::path::MAIN_SEPARATOR);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id FROM songs WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2"
+    )?;
+
+    let ids = stmt.query_map([file, dir], |row| {
+        let id_str: String = row.get(0)?;
+        Uuid::parse_str(&id_str)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text))
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ids)
+}
+
+pub fn get_song_paths_by_fingerprint(conn: &Connection, fingerprint: &str) -> Result<Vec<(Uuid, PathBuf)>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached("SELECT id, path FROM songs WHERE fingerprint = ?1")?;
+
+    let songs = stmt.query_map([fingerprint], |row| {
+        let id_str: String = row.get(0)?;
+        let path: String = row.get(1)?;
+
+        let id = Uuid::parse_str(&id_str)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text))?;
+
+        Ok((id, PathBuf::from(path)))
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(songs)
+}
+
+pub fn delete_song_from_db(tx: &Connection, song_id: Uuid) -> Result<(), rusqlite::Error> {
+    tx.prepare_cached("DELETE FROM song_features WHERE song_id = ?1")?.execute([song_id.to_string()])?;
+    tx.prepare_cached("DELETE FROM song_genres WHERE song_id = ?1")?.execute([song_id.to_string()])?;
+    tx.prepare_cached("DELETE FROM songs WHERE id = ?1")?.execute([song_id.to_string()])?;
+    remove_from_search(tx, SearchKind::Song, song_id)?;
+    Ok(())
+}
+
+//removes albums that no longer have any songs and artists that arent referenced anywhere
+//returns the ids that were deleted so the in-memory maps can be updated
+pub fn prune_orphans(tx: &Connection) -> Result<(Vec<Uuid>, Vec<Uuid>), rusqlite::Error> {
+    let albums = query_ids(
+        tx,
+        "SELECT id FROM albums WHERE id NOT IN (SELECT album_id FROM songs)",
+    )?;
+
+    for album_id in &albums {
+        tx.execute("DELETE FROM album_artists WHERE album_id = ?1", [album_id.to_string()])?;
+        tx.execute("DELETE FROM album_genres WHERE album_id = ?1", [album_id.to_string()])?;
+        tx.execute("DELETE FROM albums WHERE id = ?1", [album_id.to_string()])?;
+        remove_from_search(tx, SearchKind::Album, *album_id)?;
+    }
+
+    let artists = query_ids(
+        tx,
+        "SELECT id FROM artists
+         WHERE id NOT IN (SELECT artist_id FROM songs)
+         AND id NOT IN (SELECT artist_id FROM album_artists WHERE artist_id IS NOT NULL)
+         AND id NOT IN (SELECT artist_id FROM song_features WHERE artist_id IS NOT NULL)",
+    )?;
+
+    for artist_id in &artists {
+        tx.execute("DELETE FROM artists WHERE id = ?1", [artist_id.to_string()])?;
+        remove_from_search(tx, SearchKind::Artist, *artist_id)?;
+    }
+
+    Ok((albums, artists))
+}
+
+fn query_ids(conn: &Connection, sql: &str) -> Result<Vec<Uuid>, rusqlite::Error> {
+    let mut stmt = conn.prepare(sql)?;
+
+    let ids = stmt.query_map([], |row| {
+        let id_str: String = row.get(0)?;
+        Uuid::parse_str(&id_str)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text))
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ids)
+}
+
 pub fn insert_album_artists(
     conn: &Connection,
     album_id: Uuid,
     artists: &[(Option<Uuid>, String)]
 ) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT OR IGNORE INTO album_artists (album_id, artist_id, artist_name, is_primary, order_index) 
+         VALUES (?1, ?2, ?3, ?4, ?5)"
+    )?;
+
     for (index, (artist_id, artist_name)) in artists.iter().enumerate() {
-        conn.execute(
-            "INSERT OR IGNORE INTO album_artists (album_id, artist_id, artist_name, is_primary, order_index) 
-             VALUES (?1, ?2, ?3, ?4, ?5)",
+        stmt.execute(
             (
                 album_id.to_string(),
                 artist_id.map(|id| id.to_string()),
@@ -303,6 +641,77 @@ pub fn insert_album_artists(
     Ok(())
 }
 
+//genres keep the order the tags list them in, table is song_genres or album_genres and column its id
+fn insert_genres(tx: &Connection, table: &str, column: &str, id: Uuid, genres: &[String]) -> Result<(), rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(&format!(
+        "INSERT OR IGNORE INTO {table} ({column}, genre, order_index)
+         VALUES (?1, ?2, (SELECT COUNT(*) FROM {table} WHERE {column} = ?1))"
+    ))?;
+
+    for genre in genres {
+        stmt.execute((id.to_string(), genre))?;
+    }
+
+    Ok(())
+}
+
+//works the tags of each album out again from the songs it has now, albums that were pruned are skipped
+pub fn update_album_tags(tx: &Connection, album_ids: &HashSet<Uuid>) -> Result<HashMap<Uuid, AlbumTags>, rusqlite::Error> {
+    let mut updated = HashMap::new();
+
+    for album_id in album_ids {
+        let songs = tx
+            .prepare_cached(&format!("SELECT {SONG_COLUMNS} FROM songs s WHERE s.album_id = ?1"))?
+            .query_map([album_id.to_string()], read_song)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if songs.is_empty() {
+            continue;
+        }
+
+        let songs: Vec<Song> = songs.into_iter().map(|s| with_features(tx, s)).collect();
+        let tags = AlbumTags::from_songs(&songs);
+
+        tx.prepare_cached(
+            "UPDATE albums SET year = ?2, original_year = ?3, track_total = ?4, disc_total = ?5, label = ?6 WHERE id = ?1"
+        )?.execute(rusqlite::params![
+            album_id.to_string(),
+            tags.year,
+            tags.original_year,
+            tags.track_total,
+            tags.disc_total,
+            &tags.label,
+        ])?;
+
+        tx.prepare_cached("DELETE FROM album_genres WHERE album_id = ?1")?.execute([album_id.to_string()])?;
+        insert_genres(tx, "album_genres", "album_id", *album_id, &tags.genres)?;
+
+        updated.insert(*album_id, tags);
+    }
+
+    Ok(updated)
+}
+
+pub fn get_album_of_song(conn: &Connection, song_id: Uuid) -> Result<Option<Uuid>, rusqlite::Error> {
+    let album: Option<String> = conn
+        .prepare_cached("SELECT album_id FROM songs WHERE id = ?1")?
+        .query_row([song_id.to_string()], |row| row.get(0))
+        .optional()?;
+
+    Ok(album.and_then(|id| Uuid::parse_str(&id).ok()))
+}
+
+fn get_genres_of(conn: &Connection, table: &str, column: &str, id: Uuid) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT genre FROM {table} WHERE {column} = ?1 ORDER BY order_index"
+    ))?;
+
+    let genres = stmt.query_map([id.to_string()], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+
+    Ok(genres)
+}
+
 pub fn get_all_folders(conn: &Connection) -> Result<HashMap<i64, PathBuf>, rusqlite::Error> {
     let mut stmt = conn.prepare("SELECT id, path FROM folders")?;
     
@@ -326,17 +735,18 @@ pub fn get_all_folders(conn: &Connection) -> Result<HashMap<i64, PathBuf>, rusql
 }
 
 pub fn get_all_artists(conn: &Connection) -> Result<HashMap<Uuid, Artist>, rusqlite::Error> {
-    let mut stmt = conn.prepare("SELECT id, name FROM artists")?;
+    let mut stmt = conn.prepare("SELECT id, name, sort_tag FROM artists")?;
     
     let artist_iter = stmt.query_map([], |row| {
         let id_str: String = row.get(0)?;  
         let name: String = row.get(1)?;    
+        let sort_name: Option<String> = row.get(2)?;
         
 
         let id = Uuid::parse_str(&id_str)
             .map_err(|_| rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text))?;
             
-        Ok(Artist { id, name })
+        Ok(Artist { id, name, sort_name })
     })?;
 
     
@@ -354,48 +764,229 @@ pub fn get_all_artists(conn: &Connection) -> Result<HashMap<Uuid, Artist>, rusql
 
 pub fn get_all_albums(conn: &Connection) -> Result<HashMap<Uuid, Album>, rusqlite::Error> {
     // get basic album info
-    let mut stmt = conn.prepare("SELECT id, name, cover_data, cover_extension FROM albums")?;
+    let mut stmt = conn.prepare(
+        "SELECT id, name, cover_hash, cover_source, cover_path, palette, sort_tag,
+            year, original_year, track_total, disc_total, label
+         FROM albums"
+    )?;
     
+    //artists, genres and songs are filled in below from their own tables
     let album_iter = stmt.query_map([], |row| {
         let id_str: String = row.get(0)?;
         let name: String = row.get(1)?;
-        let cover_data: Option<Vec<u8>> = row.get(2)?;
-        let cover_extension: String = row.get(3)?;
+        let cover_hash: Option<String> = row.get(2)?;
+        let cover_source: Option<String> = row.get(3)?;
+        let cover_path: Option<String> = row.get(4)?;
+        let palette: Option<String> = row.get(5)?;
+        let palette: Option<Palette> = palette.and_then(|p| serde_json::from_str(&p).ok());
         
         let id = Uuid::parse_str(&id_str)
             .map_err(|_| rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text))?;
+
+        //albums scanned before sources were recorded can only have had embedded art
+        let cover_source = match (cover_source.as_deref(), cover_path) {
+            (Some("sidecar"), Some(path)) => Some(CoverSource::Sidecar(PathBuf::from(path))),
+            _ if cover_hash.is_some() => Some(CoverSource::Embedded),
+            _ => None,
+        };
             
-        Ok((id, name, cover_data, cover_extension))
+        Ok(Album {
+            id,
+            title: name,
+            sort_name: row.get(6)?,
+            year: row.get(7)?,
+            original_year: row.get(8)?,
+            genres: Vec::new(),
+            track_total: row.get(9)?,
+            disc_total: row.get(10)?,
+            label: row.get(11)?,
+            artists: Vec::new(),
+            cover: cover_hash,
+            cover_source,
+            palette,
+            songs: Vec::new(),
+        })
     })?;
 
     let mut albums = HashMap::new();
     
     for album_result in album_iter {
-        if let Ok((album_id, title, cover_data, cover_extension)) = album_result {
-            let artists = get_album_artists(conn, album_id)?;
-            
-            let songs = get_album_songs(conn, album_id)?;
-            
-            let cover = cover_data.map(|data| Image {
-                data,
-                extension: cover_extension,
-            });
-            
-            let album = Album {
-                id: album_id,
-                title,
-                artists,
-                cover,
-                songs,
-            };
-            
-            albums.insert(album_id, album);
-        }
+        //a row that doesnt read is reported rather than quietly leaving the album out of the library
+        let mut album = match album_result {
+            Ok(album) => album,
+            Err(e) => {
+                println!("failed to read album row, skipping it: {e}");
+                continue;
+            }
+        };
+
+        album.artists = get_album_artists(conn, album.id)?;
+        album.genres = get_genres_of(conn, "album_genres", "album_id", album.id)?;
+        album.songs = get_album_songs(conn, album.id)?;
+        
+        albums.insert(album.id, album);
     }
 
     Ok(albums)
 }
 
+//cover upkeep that only applies to databases written by older builds
+fn update_album_covers(conn: &Connection, covers: &CoverCache) {
+    match move_album_covers_to_cache(conn, covers) {
+        Ok(0) => {}
+        Ok(n) => println!("moved {n} album covers from the database into the cover cache"),
+        Err(e) => println!("failed to move album covers into the cover cache: {e}"),
+    }
+
+    match fill_album_palettes(conn, covers) {
+        Ok((0, 0)) => {}
+        Ok((filled, failed)) => println!("worked out {filled} missing album palettes, {failed} covers couldnt be read and wont be tried again"),
+        Err(e) => println!("failed to work out missing album palettes: {e}"),
+    }
+}
+
+//albums scanned before the cover cache existed kept their art in the table,
+//it gets written to the cache once and the blob cleared so the database stays small
+pub fn move_album_covers_to_cache(conn: &Connection, covers: &CoverCache) -> Result<usize, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, cover_data, cover_extension FROM albums
+         WHERE cover_data IS NOT NULL AND cover_hash IS NULL"
+    )?;
+
+    let legacy = stmt.query_map([], |row| {
+        let id: String = row.get(0)?;
+        let data: Vec<u8> = row.get(1)?;
+        let extension: Option<String> = row.get(2)?;
+        Ok((id, data, extension))
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let mut moved = 0;
+
+    for (id, data, extension) in legacy {
+        let image = Image {
+            data,
+            extension: extension.unwrap_or_else(|| "image/jpeg".into()),
+        };
+
+        let hash = match covers.store(&image) {
+            Ok(h) => h,
+            Err(e) => {
+                println!("failed to cache cover of album {id}: {e}");
+                continue;
+            }
+        };
+
+        conn.execute(
+            "UPDATE albums SET cover_hash = ?1, cover_data = NULL WHERE id = ?2",
+            (&hash, &id),
+        )?;
+        moved += 1;
+    }
+
+    Ok(moved)
+}
+
+//stored for covers no palette can be worked out for so they arent decoded again on every launch,
+//it isnt a palette so it reads back as none
+const NO_PALETTE: &str = "null";
+
+//works out the palette of covers cached before palettes were stored
+//returns how many were filled in and how many covers couldnt be read, those get NO_PALETTE
+pub fn fill_album_palettes(conn: &Connection, covers: &CoverCache) -> Result<(usize, usize), rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT id, cover_hash FROM albums WHERE cover_hash IS NOT NULL AND palette IS NULL")?;
+
+    let missing = stmt.query_map([], |row| {
+        let id: String = row.get(0)?;
+        let hash: String = row.get(1)?;
+        Ok((id, hash))
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let mut filled = 0;
+    let mut failed = 0;
+
+    for (id, hash) in missing {
+        let palette = match covers.palette(&hash).and_then(|p| serde_json::to_string(&p).map_err(|e| e.to_string())) {
+            Ok(p) => {
+                filled += 1;
+                p
+            }
+            Err(_) => {
+                failed += 1;
+                NO_PALETTE.to_string()
+            }
+        };
+
+        conn.execute("UPDATE albums SET palette = ?1 WHERE id = ?2", (&palette, &id))?;
+    }
+
+    Ok((filled, failed))
+}
+
+//the sort key column of each table and what it is worked out from, a sort name tag wins over the name
+const SORT_KEY_COLUMNS: [(&str, &str, &str); 3] = [
+    ("songs", "sort_title", "title"),
+    ("albums", "sort_name", "COALESCE(sort_tag, name)"),
+    ("artists", "sort_name", "COALESCE(sort_tag, name)"),
+];
+
+//works out the sort keys again, only the missing ones or all of them when the articles changed
+pub fn update_sort_keys(tx: &Connection, articles: &[String], only_missing: bool) -> Result<usize, rusqlite::Error> {
+    let mut updated = 0;
+
+    for (table, key_column, name_column) in SORT_KEY_COLUMNS {
+        let filter = if only_missing { format!("WHERE {key_column} IS NULL") } else { String::new() };
+
+        let rows: Vec<(String, String)> = tx
+            .prepare(&format!("SELECT id, {name_column} FROM {table} {filter}"))?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut update = tx.prepare(&format!("UPDATE {table} SET {key_column} = ?1 WHERE id = ?2"))?;
+        for (id, name) in rows {
+            update.execute((sort_key(&name, articles), id))?;
+            updated += 1;
+        }
+    }
+
+    Ok(updated)
+}
+
+//rows written before sort keys existed get theirs when the library opens
+pub fn fill_sort_keys(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    let settings: SortSettings = get_setting(conn, SORT_SETTINGS_KEY)?.unwrap_or_default();
+
+    let tx = conn.unchecked_transaction()?;
+    let filled = update_sort_keys(&tx, &settings.articles(), true)?;
+    tx.commit()?;
+
+    Ok(filled)
+}
+
+//saves the settings and sorts everything by them straight away
+pub fn save_sort_settings(conn: &Connection, settings: &SortSettings) -> Result<(), rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    set_setting(&tx, SORT_SETTINGS_KEY, settings)?;
+    update_sort_keys(&tx, &settings.articles(), false)?;
+    tx.commit()
+}
+
+pub fn get_album_order(conn: &Connection) -> Result<Vec<Uuid>, rusqlite::Error> {
+    query_ids(conn, "SELECT id FROM albums ORDER BY sort_name, id")
+}
+
+pub fn get_artist_order(conn: &Connection) -> Result<Vec<Uuid>, rusqlite::Error> {
+    query_ids(conn, "SELECT id FROM artists ORDER BY sort_name, id")
+}
+
+fn cover_source_kind(source: &CoverSource) -> &'static str {
+    match source {
+        CoverSource::Embedded => "embedded",
+        CoverSource::Sidecar(_) => "sidecar",
+    }
+}
+
 pub fn get_album_artists(conn: &Connection, album_id: Uuid) -> Result<Vec<(Option<Uuid>, String)>, rusqlite::Error> {
     let mut stmt = conn.prepare(
         "SELECT artist_id, artist_name FROM album_artists 
@@ -431,80 +1022,429 @@ pub fn get_album_songs(conn: &Connection, album_id: Uuid) -> Result<Vec<Uuid>, r
     Ok(songs)
 }
 
+const SONG_COLUMNS: &str = "s.id, s.title, s.artist_id, s.album_id, s.folder_id, s.cover_data,
+    s.track_num, s.disc_num, s.path, s.duration, s.file_size, s.modified, s.fingerprint,
+    s.year, s.composer, s.conductor, s.bpm, s.comment, s.original_year, s.track_total, s.disc_total, s.label";
+
+//features and genres are left empty, they live in their own tables
+fn read_song(row: &rusqlite::Row) -> Result<Song, rusqlite::Error> {
+    let id_str: String = row.get("id")?;
+    let title: String = row.get("title")?;
+    let artist_id_str: String = row.get("artist_id")?;
+    let album_id_str: String = row.get("album_id")?;
+    let folder_id: i64 = row.get("folder_id")?;
+    let cover_data: Option<Vec<u8>> = row.get("cover_data")?;
+    let track_num: u16 = row.get("track_num")?;
+    let disc_num: u16 = row.get("disc_num")?;
+    let path_str: String = row.get("path")?;
+    let duration: f64 = row.get("duration").unwrap_or(0.0);
+    let file_size: i64 = row.get("file_size").unwrap_or(0);
+    let modified: i64 = row.get("modified").unwrap_or(0);
+    let fingerprint: Option<String> = row.get("fingerprint")?;
+
+    // parse UUIDs
+    let id = Uuid::parse_str(&id_str)
+        .map_err(|_| rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text))?;
+    let artist_id = Uuid::parse_str(&artist_id_str)
+        .map_err(|_| rusqlite::Error::InvalidColumnType(0, "artist_id".to_string(), rusqlite::types::Type::Text))?;
+    let album_id = Uuid::parse_str(&album_id_str)
+        .map_err(|_| rusqlite::Error::InvalidColumnType(0, "album_id".to_string(), rusqlite::types::Type::Text))?;
+
+    let cover = cover_data.map(|data| Image {
+        data,
+        extension: "image/jpeg".to_string(),
+    });
+
+    Ok(Song {
+        id,
+        title,
+        artist: artist_id,
+        album: album_id,
+        features: None,
+        track_num,
+        disc_num,
+        cover,
+        path: std::path::PathBuf::from(path_str),
+        duration,
+        folder_id,
+        file_size: file_size as u64,
+        modified,
+        fingerprint,
+        year: row.get("year")?,
+        genres: Vec::new(),
+        composer: row.get("composer")?,
+        conductor: row.get("conductor")?,
+        bpm: row.get("bpm")?,
+        comment: row.get("comment")?,
+        original_year: row.get("original_year")?,
+        track_total: row.get("track_total")?,
+        disc_total: row.get("disc_total")?,
+        label: row.get("label")?,
+    })
+}
+
+fn with_features(conn: &Connection, mut song: Song) -> Song {
+    song.features = match get_song_features(conn, song.id) {
+        Ok(features) => {
+            if features.is_empty() {
+                None
+            } else {
+                Some(features)
+            }
+        },
+        Err(e) => {
+            println!("Failed to load features for song {}: {}", song.id, e);
+            None
+        }
+    };
+
+    song.genres = match get_genres_of(conn, "song_genres", "song_id", song.id) {
+        Ok(genres) => genres,
+        Err(e) => {
+            println!("failed to load genres for song {}: {e}", song.id);
+            Vec::new()
+        }
+    };
+
+    song
+}
+
 pub fn get_all_songs(conn: &Connection) -> Result<HashMap<Uuid, Song>, rusqlite::Error> {
-    let mut stmt = conn.prepare(
-        "SELECT s.id, s.title, s.artist_id, s.album_id, s.folder_id, s.cover_data, 
-                s.track_num, s.disc_num, s.path, s.duration
-         FROM songs s")?;
-    
-    let songs_iter = stmt.query_map([], |row| {
-        let id_str: String = row.get("id")?;
-        let title: String = row.get("title")?;
-        let artist_id_str: String = row.get("artist_id")?;
-        let album_id_str: String = row.get("album_id")?;
-        let folder_id: i64 = row.get("folder_id")?;
-        let cover_data: Option<Vec<u8>> = row.get("cover_data")?;
-        let track_num: u16 = row.get("track_num")?;
-        let disc_num: u16 = row.get("disc_num")?;
-        let path_str: String = row.get("path")?;
-        let duration: f64 = row.get("duration").unwrap_or(0.0);
-        
-        // parse UUIDs
-        let id = Uuid::parse_str(&id_str)
-            .map_err(|_| rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text))?;
-        let artist_id = Uuid::parse_str(&artist_id_str)
-            .map_err(|_| rusqlite::Error::InvalidColumnType(0, "artist_id".to_string(), rusqlite::types::Type::Text))?;
-        let album_id = Uuid::parse_str(&album_id_str)
-            .map_err(|_| rusqlite::Error::InvalidColumnType(0, "album_id".to_string(), rusqlite::types::Type::Text))?;
-        
-        let cover = cover_data.map(|data| Image {
-            data,
-            extension: "image/jpeg".to_string(),
-        });
-        
-        let song = Song {
-            id,
-            title,
-            artist: artist_id,
-            album: album_id,
-            features: None, 
-            track_num,
-            disc_num,
-            cover,
-            path: std::path::PathBuf::from(path_str),
-            duration,
-            folder_id,
-        };
-        
-        Ok((id, song))
-    })?;
-    
+    let mut stmt = conn.prepare(&format!("SELECT {SONG_COLUMNS} FROM songs s"))?;
+
+    let songs_iter = stmt.query_map([], read_song)?;
+
     let mut songs = HashMap::new();
     for song_result in songs_iter {
-        if let Ok((id, mut song)) = song_result {
-            song.features = match get_song_features(conn, id) {
-                Ok(features) => {
-                    if features.is_empty() {
-                        None
-                    } else {
-                        Some(features)
-                    }
-                },
-                Err(e) => {
-                    println!("Failed to load features for song {}: {}", id, e);
-                    None
-                }
-            };
-            
-            songs.insert(id, song);
+        if let Ok(song) = song_result {
+            songs.insert(song.id, with_features(conn, song));
         }
     }
-    
+
     Ok(songs)
 }
 
-pub fn get_song_features(conn: &Connection, song_id: Uuid) -> Result<Vec<(Option<Uuid>, String)>, rusqlite::Error> {
+pub fn get_song(conn: &Connection, id: Uuid) -> Result<Option<Song>, rusqlite::Error> {
+    let song = conn
+        .prepare_cached(&format!("SELECT {SONG_COLUMNS} FROM songs s WHERE s.id = ?1"))?
+        .query_row([id.to_string()], read_song)
+        .optional()?;
+
+    Ok(song.map(|s| with_features(conn, s)))
+}
+
+//every sort ends on the id so songs that compare equal keep the same place between pages
+fn song_order(sort: &SongSort) -> String {
+    let dir = if sort.descending { "DESC" } else { "ASC" };
+
+    match sort.key {
+        SongSortKey::Title => format!("s.sort_title {dir}, s.id"),
+        SongSortKey::Artist => format!("ar.sort_name {dir}, al.sort_name, s.disc_num, s.track_num, s.id"),
+        SongSortKey::Album => format!("al.sort_name {dir}, s.disc_num, s.track_num, s.id"),
+        SongSortKey::Duration => format!("s.duration {dir}, s.id"),
+        //songs without a year go last either way
+        SongSortKey::Year => format!("s.year IS NULL, s.year {dir}, al.sort_name, s.disc_num, s.track_num, s.id"),
+    }
+}
+
+const SONG_PAGE_FROM: &str = r"FROM songs s
+    LEFT JOIN artists ar ON ar.id = s.artist_id
+    LEFT JOIN albums al ON al.id = s.album_id
+    WHERE (?1 IS NULL OR s.artist_id = ?1
+            OR EXISTS (SELECT 1 FROM song_features f WHERE f.song_id = s.id AND f.artist_id = ?1))
+        AND (?2 IS NULL OR s.album_id = ?2)
+        AND (?3 IS NULL OR s.folder_id = ?3)
+        AND (?4 IS NULL OR s.title LIKE ?4 ESCAPE '\' OR ar.name LIKE ?4 ESCAPE '\' OR al.name LIKE ?4 ESCAPE '\')
+        AND (?5 IS NULL OR EXISTS (SELECT 1 FROM song_genres g WHERE g.song_id = s.id AND g.genre = ?5))
+        AND (?6 IS NULL OR s.year = ?6)
+        AND (?7 IS NULL OR s.composer = ?7 COLLATE NOCASE)
+        AND (?8 IS NULL OR s.conductor = ?8 COLLATE NOCASE)";
+
+const SONG_TO_SEND_COLUMNS: &str = "s.id, s.title, s.artist_id, ar.name, s.album_id, al.name, al.cover_hash,
+    s.track_num, s.disc_num, s.path, s.duration, s.year, s.composer, s.conductor, s.bpm, s.comment";
+
+//reads SONG_TO_SEND_COLUMNS, with artists as ar and albums as al joined on
+fn read_song_to_send(row: &rusqlite::Row) -> Result<SongToSend, rusqlite::Error> {
+    let parse = |column: usize| -> Result<Uuid, rusqlite::Error> {
+        let value: String = row.get(column)?;
+        Uuid::parse_str(&value)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(column, "id".to_string(), rusqlite::types::Type::Text))
+    };
+
+    let path: String = row.get(9)?;
+    let artist_name: Option<String> = row.get(3)?;
+    let album_name: Option<String> = row.get(5)?;
+
+    Ok(SongToSend {
+        id: parse(0)?,
+        title: row.get(1)?,
+        artist: (parse(2)?, artist_name.unwrap_or_else(|| "Unknown Artist".into())),
+        album: (parse(4)?, album_name.unwrap_or_else(|| "Unknown Album".into())),
+        features: None,
+        track_num: row.get(7)?,
+        disc_num: row.get(8)?,
+        cover: row.get(6)?,
+        path: PathBuf::from(path),
+        duration: row.get::<_, Option<f64>>(10)?.unwrap_or(0.0),
+        year: row.get(11)?,
+        genres: Vec::new(),
+        composer: row.get(12)?,
+        conductor: row.get(13)?,
+        bpm: row.get(14)?,
+        comment: row.get(15)?,
+    })
+}
+
+//"?1, ?2, .." for an IN list of n values
+fn placeholders(n: usize) -> String {
+    (1..=n).map(|i| format!("?{i}")).collect::<Vec<_>>().join(", ")
+}
+
+//fills in the features and genres, which live in their own tables, with one query each for all the songs
+fn with_send_features(conn: &Connection, mut songs: Vec<SongToSend>) -> Result<Vec<SongToSend>, rusqlite::Error> {
+    if songs.is_empty() {
+        return Ok(songs);
+    }
+
+    let ids: Vec<String> = songs.iter().map(|s| s.id.to_string()).collect();
+    let mut features: HashMap<String, Vec<(Option<Uuid>, String)>> = HashMap::new();
+    let mut genres: HashMap<String, Vec<String>> = HashMap::new();
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT song_id, artist_id, artist_name FROM song_features WHERE song_id IN ({})",
+        placeholders(ids.len())
+    ))?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(&ids))?;
+    while let Some(row) = rows.next()? {
+        let artist_id: Option<String> = row.get(1)?;
+        features
+            .entry(row.get(0)?)
+            .or_default()
+            .push((artist_id.and_then(|id| Uuid::parse_str(&id).ok()), row.get(2)?));
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT song_id, genre FROM song_genres WHERE song_id IN ({}) ORDER BY order_index",
+        placeholders(ids.len())
+    ))?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(&ids))?;
+    while let Some(row) = rows.next()? {
+        genres.entry(row.get(0)?).or_default().push(row.get(1)?);
+    }
+
+    for (song, id) in songs.iter_mut().zip(&ids) {
+        song.features = features.remove(id);
+        song.genres = genres.remove(id).unwrap_or_default();
+    }
+
+    Ok(songs)
+}
+
+//the songs with these ids in the same order, ids without a song are left out
+pub fn get_songs_to_send(conn: &Connection, ids: &[Uuid]) -> Result<Vec<SongToSend>, rusqlite::Error> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {SONG_TO_SEND_COLUMNS} FROM songs s
+         LEFT JOIN artists ar ON ar.id = s.artist_id
+         LEFT JOIN albums al ON al.id = s.album_id
+         WHERE s.id IN ({})",
+        placeholders(ids.len())
+    ))?;
+
+    let mut found: HashMap<Uuid, SongToSend> = stmt
+        .query_map(rusqlite::params_from_iter(ids.iter().map(|id| id.to_string())), read_song_to_send)?
+        .map(|song| song.map(|s| (s.id, s)))
+        .collect::<Result<_, _>>()?;
+
+    let songs = ids.iter().filter_map(|id| found.remove(id)).collect();
+    with_send_features(conn, songs)
+}
+
+//one page of songs in the order and with the filter asked for, along with how many match in total
+pub fn get_song_page(conn: &Connection, query: &SongQuery) -> Result<SongPage, rusqlite::Error> {
+    let filter = &query.filter;
+
+    //% and _ typed into the search box are matched literally
+    let pattern = filter.text().map(|t| {
+        let escaped = t.replace('\\', r"\\").replace('%', r"\%").replace('_', r"\_");
+        format!("%{escaped}%")
+    });
+
+    let artist = filter.artist.map(|id| id.to_string());
+    let album = filter.album.map(|id| id.to_string());
+
+    let genre = filter.genre();
+    let composer = filter.composer();
+    let conductor = filter.conductor();
+
+    let total: i64 = conn
+        .prepare_cached(&format!("SELECT COUNT(*) {SONG_PAGE_FROM}"))?
+        .query_row(
+            rusqlite::params![&artist, &album, filter.folder, &pattern, genre, filter.year, composer, conductor],
+            |row| row.get(0),
+        )?;
+
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {SONG_TO_SEND_COLUMNS}
+         {SONG_PAGE_FROM}
+         ORDER BY {}
+         LIMIT ?9 OFFSET ?10",
+        song_order(&query.sort)
+    ))?;
+
+    let rows = stmt.query_map(
+        rusqlite::params![
+            &artist, &album, filter.folder, &pattern, genre, filter.year, composer, conductor,
+            query.limit as i64, query.offset as i64,
+        ],
+        read_song_to_send,
+    )?;
+
+    let songs = with_send_features(conn, rows.collect::<Result<Vec<_>, _>>()?)?;
+
+    Ok(SongPage {
+        total: total as usize,
+        offset: query.offset,
+        songs,
+    })
+}
+
+//every genre songs are tagged with, spellings that only differ in case count as one
+//and the one that sorts first is shown, so "Rock" wins over "rock"
+pub fn get_genre_counts(conn: &Connection) -> Result<Vec<GenreCount>, rusqlite::Error> {
     let mut stmt = conn.prepare(
+        "SELECT MIN(genre COLLATE BINARY), COUNT(*) FROM song_genres GROUP BY genre ORDER BY genre"
+    )?;
+
+    let genres = stmt.query_map([], |row| {
+        let songs: i64 = row.get(1)?;
+        Ok(GenreCount { name: row.get(0)?, songs: songs as usize })
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(genres)
+}
+
+//the text each kind of item is found by: its name, who made it, the album it is on and where the file is
+fn search_doc_sql(kind: SearchKind) -> &'static str {
+    match kind {
+        SearchKind::Artist => "SELECT name, '', '', '' FROM artists WHERE id = ?1",
+        SearchKind::Album => {
+            "SELECT al.name,
+                COALESCE((SELECT group_concat(artist_name, ' ') FROM album_artists WHERE album_id = al.id), ''),
+                '', ''
+             FROM albums al WHERE al.id = ?1"
+        }
+        SearchKind::Song => {
+            "SELECT s.title,
+                trim(COALESCE(ar.name, '') || ' ' ||
+                    COALESCE((SELECT group_concat(artist_name, ' ') FROM song_features WHERE song_id = s.id), '')),
+                COALESCE(al.name, ''), s.path
+             FROM songs s
+             LEFT JOIN artists ar ON ar.id = s.artist_id
+             LEFT JOIN albums al ON al.id = s.album_id
+             WHERE s.id = ?1"
+        }
+    }
+}
+
+//writes the search entry of an item again from what is in its tables now
+pub fn index_for_search(tx: &Connection, kind: SearchKind, item_id: Uuid) -> Result<(), rusqlite::Error> {
+    let doc: Option<(String, String, String, String)> = tx
+        .prepare_cached(search_doc_sql(kind))?
+        .query_row([item_id.to_string()], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+        .optional()?;
+
+    remove_from_search(tx, kind, item_id)?;
+
+    if let Some((name, artists, album, path)) = doc {
+        tx.prepare_cached("INSERT INTO search_docs (kind, item_id) VALUES (?1, ?2)")?
+            .execute((kind.as_str(), item_id.to_string()))?;
+
+        tx.prepare_cached("INSERT INTO search_index (rowid, name, artists, album, path) VALUES (?1, ?2, ?3, ?4, ?5)")?
+            .execute((tx.last_insert_rowid(), name, artists, album, path))?;
+    }
+
+    Ok(())
+}
+
+//albums and artists are saved with every one of their songs, so they are indexed once from what all
+//of them left in the tables instead of after each song
+pub fn index_artists_and_albums(tx: &Connection, artists: &HashSet<Uuid>, albums: &HashSet<Uuid>) -> Result<(), rusqlite::Error> {
+    for id in artists {
+        index_for_search(tx, SearchKind::Artist, *id)?;
+    }
+    for id in albums {
+        index_for_search(tx, SearchKind::Album, *id)?;
+    }
+
+    Ok(())
+}
+
+pub fn remove_from_search(tx: &Connection, kind: SearchKind, item_id: Uuid) -> Result<(), rusqlite::Error> {
+    let rowid: Option<i64> = tx
+        .prepare_cached("SELECT rowid FROM search_docs WHERE kind = ?1 AND item_id = ?2")?
+        .query_row((kind.as_str(), item_id.to_string()), |row| row.get(0))
+        .optional()?;
+
+    if let Some(rowid) = rowid {
+        tx.prepare_cached("DELETE FROM search_index WHERE rowid = ?1")?.execute([rowid])?;
+        tx.prepare_cached("DELETE FROM search_docs WHERE rowid = ?1")?.execute([rowid])?;
+    }
+
+    Ok(())
+}
+
+//ids of one kind matching the fts5 expression with their score, best first.
+//the name counts the most and the path the least
+fn search_kind(conn: &Connection, expression: &str, kind: SearchKind, limit: usize) -> Result<Vec<(Uuid, f64)>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT d.item_id, bm25(search_index, 10.0, 4.0, 2.0, 1.0) AS score
+         FROM search_index
+         JOIN search_docs d ON d.rowid = search_index.rowid
+         WHERE search_index MATCH ?1 AND d.kind = ?2
+         ORDER BY score
+         LIMIT ?3",
+    )?;
+
+    let hits = stmt.query_map((expression, kind.as_str(), limit as i64), |row| {
+        let id_str: String = row.get(0)?;
+        let id = Uuid::parse_str(&id_str)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(0, "item_id".to_string(), rusqlite::types::Type::Text))?;
+
+        Ok((id, row.get(1)?))
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(hits)
+}
+
+pub fn search_library(conn: &Connection, text: &str, limit: usize) -> Result<SearchMatches, rusqlite::Error> {
+    let expression = match match_expression(text) {
+        Some(e) => e,
+        None => return Ok(SearchMatches::default()),
+    };
+
+    let songs = search_kind(conn, &expression, SearchKind::Song, limit)?;
+    let albums = search_kind(conn, &expression, SearchKind::Album, limit)?;
+    let artists = search_kind(conn, &expression, SearchKind::Artist, limit)?;
+
+    let ids: Vec<Uuid> = songs.iter().map(|(id, _)| *id).collect();
+    let found = get_songs_to_send(conn, &ids)?;
+
+    Ok(SearchMatches {
+        top_hit: pick_top_hit(&songs, &albums, &artists),
+        songs: found,
+        albums: albums.into_iter().map(|(id, _)| id).collect(),
+        artists: artists.into_iter().map(|(id, _)| id).collect(),
+    })
+}
+
+pub fn get_song_features(conn: &Connection, song_id: Uuid) -> Result<Vec<(Option<Uuid>, String)>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
         "SELECT artist_id, artist_name FROM song_features WHERE song_id = ?1"
     )?;
     
@@ -524,6 +1464,8 @@ pub fn get_song_features(conn: &Connection, song_id: Uuid) -> Result<Vec<(Option
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::scan::{FileError, ScanError};
+    use crate::core::fixtures;
 
     #[test]
     fn test_db_connection_opens() {
@@ -552,4 +1494,224 @@ mod tests {
         let mut rows = stmt.query(()).unwrap();
         assert!(rows.next().unwrap().is_some());
     }
+
+    fn test_library() -> (Song, HashMap<Uuid, Artist>, HashMap<Uuid, Album>) {
+        let artist = fixtures::artist("artist");
+        let album = fixtures::album("album", &artist);
+        let song = Song {
+            title: "song".into(),
+            file_size: 100,
+            fingerprint: Some("abc".into()),
+            ..fixtures::song(1, "/music/song.mp3", &artist, &album)
+        };
+
+        (song, HashMap::from([(artist.id, artist)]), HashMap::from([(album.id, album)]))
+    }
+
+    #[test]
+    fn test_insert_song_upserts_on_path() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let (song, artists, albums) = test_library();
+        let tx = conn.transaction().unwrap();
+        insert_song_to_db(&tx, &song, &artists, &albums, &[]).unwrap();
+
+        let mut changed = song.clone();
+        changed.title = "retagged".into();
+        changed.file_size = 200;
+        insert_song_to_db(&tx, &changed, &artists, &albums, &[]).unwrap();
+        tx.commit().unwrap();
+
+        let songs = get_all_songs(&conn).unwrap();
+        assert_eq!(songs.len(), 1);
+        assert_eq!(songs[&song.id].title, "retagged");
+        assert_eq!(songs[&song.id].file_size, 200);
+    }
+
+    #[test]
+    fn test_prune_orphans_after_song_removed() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let (song, artists, albums) = test_library();
+        let tx = conn.transaction().unwrap();
+        insert_song_to_db(&tx, &song, &artists, &albums, &[]).unwrap();
+
+        let (orphan_albums, orphan_artists) = prune_orphans(&tx).unwrap();
+        assert!(orphan_albums.is_empty());
+        assert!(orphan_artists.is_empty());
+
+        delete_song_from_db(&tx, song.id).unwrap();
+        let (orphan_albums, orphan_artists) = prune_orphans(&tx).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(orphan_albums, vec![song.album]);
+        assert_eq!(orphan_artists, vec![song.artist]);
+        assert!(get_all_albums(&conn).unwrap().is_empty());
+        assert!(get_all_artists(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_song_lookup_by_path() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let (song, artists, albums) = test_library();
+        let tx = conn.transaction().unwrap();
+        insert_song_to_db(&tx, &song, &artists, &albums, &[]).unwrap();
+        tx.commit().unwrap();
+
+        let (id, stats) = get_song_stats_by_path(&conn, &song.path).unwrap().unwrap();
+        assert_eq!(id, song.id);
+        assert_eq!(stats, FileStats { size: 100, modified: 1 });
+
+        assert_eq!(get_song_ids_under_path(&conn, Path::new("/music")).unwrap(), vec![song.id]);
+        assert_eq!(get_song_ids_under_path(&conn, &song.path).unwrap(), vec![song.id]);
+        assert!(get_song_ids_under_path(&conn, Path::new("/mus")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_moved_song_keeps_id() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let (song, artists, albums) = test_library();
+        let tx = conn.transaction().unwrap();
+        insert_song_to_db(&tx, &song, &artists, &albums, &[]).unwrap();
+
+        let candidates = get_song_paths_by_fingerprint(&tx, "abc").unwrap();
+        assert_eq!(candidates, vec![(song.id, song.path.clone())]);
+
+        let mut moved = song.clone();
+        moved.path = PathBuf::from("/music/renamed/song.mp3");
+        moved.folder_id = 2;
+        insert_song_to_db(&tx, &moved, &artists, &albums, &[]).unwrap();
+        tx.commit().unwrap();
+
+        let songs = get_all_songs(&conn).unwrap();
+        assert_eq!(songs.len(), 1);
+        assert_eq!(songs[&song.id].path, moved.path);
+        assert_eq!(songs[&song.id].folder_id, 2);
+    }
+
+    #[test]
+    fn test_legacy_album_covers_move_to_cache() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let covers = CoverCache::new(dir.path());
+
+        let (song, artists, albums) = test_library();
+        let tx = conn.transaction().unwrap();
+        insert_song_to_db(&tx, &song, &artists, &albums, &[]).unwrap();
+        tx.commit().unwrap();
+
+        conn.execute(
+            "UPDATE albums SET cover_data = ?1, cover_extension = 'image/png' WHERE id = ?2",
+            (b"old art".to_vec(), song.album.to_string()),
+        ).unwrap();
+
+        assert_eq!(move_album_covers_to_cache(&conn, &covers).unwrap(), 1);
+        assert_eq!(move_album_covers_to_cache(&conn, &covers).unwrap(), 0);
+
+        let album = &get_all_albums(&conn).unwrap()[&song.album];
+        let hash = album.cover.as_deref().unwrap();
+        assert_eq!(album.cover_source, Some(CoverSource::Embedded));
+        assert_eq!(covers.load(hash, None).unwrap(), Image { data: b"old art".to_vec(), extension: "image/png".into() });
+
+        //not a real image, it is only tried once
+        assert_eq!(fill_album_palettes(&conn, &covers).unwrap(), (0, 1));
+        assert_eq!(fill_album_palettes(&conn, &covers).unwrap(), (0, 0));
+        assert_eq!(get_all_albums(&conn).unwrap()[&song.album].palette, None);
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let missing: Option<ScanSettings> = get_setting(&conn, SCAN_SETTINGS_KEY).unwrap();
+        assert!(missing.is_none());
+
+        let mut settings = ScanSettings::default();
+        settings.formats.deny.insert("m4b".into());
+        set_setting(&conn, SCAN_SETTINGS_KEY, &settings).unwrap();
+
+        let loaded: Option<ScanSettings> = get_setting(&conn, SCAN_SETTINGS_KEY).unwrap();
+        assert_eq!(loaded, Some(settings));
+    }
+
+    #[test]
+    fn test_scan_report_round_trip() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        conn.execute("INSERT INTO folders (id, path) VALUES (1, '/music')", []).unwrap();
+
+        assert!(get_scan_report(&conn, 1).unwrap().is_none());
+
+        let mut report = ScanReport::default();
+        report.files_failed = 1;
+        report.errors.push(FileError {
+            path: PathBuf::from("/music/broken.mp3"),
+            error: ScanError::CorruptTag("bad frame".into()),
+        });
+        save_scan_report(&conn, 1, &report).unwrap();
+
+        let loaded = get_scan_report(&conn, 1).unwrap().unwrap();
+        assert_eq!(loaded.files_failed, 1);
+        assert_eq!(loaded.errors, report.errors);
+
+        //reports go away with their folder
+        conn.execute("DELETE FROM folders WHERE id = 1", []).unwrap();
+        assert!(get_scan_report(&conn, 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_absorb_nested_folders() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        conn.execute("INSERT INTO folders (id, path) VALUES (1, '/music/jazz'), (2, '/podcasts'), (3, '/music')", []).unwrap();
+
+        let (mut song, artists, albums) = test_library();
+        song.path = PathBuf::from("/music/jazz/song.mp3");
+
+        let tx = conn.transaction().unwrap();
+        insert_song_to_db(&tx, &song, &artists, &albums, &[]).unwrap();
+
+        let absorbed = absorb_nested_folders(&tx, 3, Path::new("/music")).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(absorbed, vec![1]);
+        assert_eq!(get_song_paths_in_folder(&conn, 3).unwrap(), vec![(song.id, song.path.clone())]);
+        assert!(get_song_paths_in_folder(&conn, 1).unwrap().is_empty());
+
+        let folders = get_all_folders(&conn).unwrap();
+        assert_eq!(folders.len(), 2);
+        assert!(!folders.contains_key(&1));
+    }
+
+    #[test]
+    fn test_search_ignores_diacritics() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let (mut song, mut artists, albums) = test_library();
+        let artist = artists.get_mut(&song.artist).unwrap();
+        artist.name = "Beyoncé".into();
+        song.title = "Café Olé".into();
+        insert_folder_and_get_id(&conn, "/music").unwrap();
+        insert_song_to_db(&conn, &song, &artists, &albums, &[]).unwrap();
+        index_artists_and_albums(&conn, &HashSet::from([song.artist]), &HashSet::from([song.album])).unwrap();
+
+        let found = search_library(&conn, "beyonce", 10).unwrap();
+        assert_eq!(found.artists, vec![song.artist]);
+        assert_eq!(found.top_hit, Some((SearchKind::Artist, song.artist)));
+
+        let found = search_library(&conn, "CAFE ole", 10).unwrap();
+        assert_eq!(found.songs.len(), 1);
+
+        //quotes and query syntax are searched for as text
+        assert!(search_library(&conn, "\"caf OR NEAR(", 10).is_ok());
+    }
 }
@@ -0,0 +1,1086 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use rusqlite::{Connection, Result};
+use uuid::Uuid;
+
+use crate::core::query::{GenreCount, SongPage, SongQuery, SongSortKey};
+use crate::core::search::{pick_top_hit, search_terms, SearchMatches};
+use crate::core::sort_key::{sort_key, SortSettings, SORT_SETTINGS_KEY};
+use crate::core::scan::{FileStats, ScanReport, ScanSettings};
+use crate::core::song::{Album, AlbumTags, Artist, Song};
+use crate::db::Database;
+use crate::state::{
+    absorb_nested_folders, delete_song_from_db, get_album_of_song, get_album_order, get_all_albums, get_genre_counts, get_all_artists, get_all_folders, get_all_songs, get_artist_order,
+    get_scan_report, get_setting, get_song, get_song_ids_under_path, get_song_page, get_song_paths_by_fingerprint, get_song_paths_in_folder,
+    get_song_stats_by_path, get_song_stats_in_folder, index_artists_and_albums, insert_folder_and_get_id, insert_song_to_db, prune_orphans, save_scan_report, save_sort_settings, search_library,
+    set_setting, set_song_folder, update_album_tags, SCAN_SETTINGS_KEY,
+};
+use crate::SongToSend;
+
+//where the library is kept, scans and commands only go through this so they can be tested against MemoryStore
+pub trait LibraryStore: Send + Sync {
+    //only one transaction is open at a time, a second begin waits for the first to commit or drop.
+    //dropping a transaction without committing it throws away everything written through it
+    fn begin(&self) -> Result<Box<dyn StoreTransaction + '_>>;
+
+    fn folders(&self) -> Result<HashMap<i64, PathBuf>>;
+    fn songs(&self) -> Result<HashMap<Uuid, Song>>;
+    fn albums(&self) -> Result<HashMap<Uuid, Album>>;
+    fn artists(&self) -> Result<HashMap<Uuid, Artist>>;
+
+    fn song(&self, id: Uuid) -> Result<Option<Song>>;
+    //what the song list shows, the library is never loaded whole for it
+    fn song_page(&self, query: &SongQuery) -> Result<SongPage>;
+    //at most limit results of each kind
+    fn search(&self, text: &str, limit: usize) -> Result<SearchMatches>;
+    //every genre with its song count, ordered by name
+    fn genres(&self) -> Result<Vec<GenreCount>>;
+
+    //album and artist ids by their sort keys, the maps in MusicLibrary have no order
+    fn album_order(&self) -> Result<Vec<Uuid>>;
+    fn artist_order(&self) -> Result<Vec<Uuid>>;
+
+    fn scan_settings(&self) -> Result<Option<ScanSettings>>;
+    fn set_scan_settings(&self, settings: &ScanSettings) -> Result<()>;
+    fn sort_settings(&self) -> Result<Option<SortSettings>>;
+    //every sort key is worked out again with the new settings before this returns
+    fn set_sort_settings(&self, settings: &SortSettings) -> Result<()>;
+    fn scan_report(&self, folder_id: i64) -> Result<Option<ScanReport>>;
+}
+
+pub trait StoreTransaction {
+    fn albums(&self) -> Result<HashMap<Uuid, Album>>;
+    fn artists(&self) -> Result<HashMap<Uuid, Artist>>;
+
+    //returns the id the folder already has if it was added before
+    fn insert_folder(&mut self, path: &Path) -> Result<i64>;
+    //moves the songs of every folder below path into folder_id, returns the folders that were merged away
+    fn absorb_nested_folders(&mut self, folder_id: i64, path: &Path) -> Result<Vec<i64>>;
+    fn delete_folder(&mut self, folder_id: i64) -> Result<()>;
+
+    fn song_stats_by_path(&self, path: &Path) -> Result<Option<(Uuid, FileStats)>>;
+    //the song at exactly this path, or every song below it if the path was a directory
+    fn song_ids_under_path(&self, path: &Path) -> Result<Vec<Uuid>>;
+    fn song_paths_by_fingerprint(&self, fingerprint: &str) -> Result<Vec<(Uuid, PathBuf)>>;
+    fn song_paths_in_folder(&self, folder_id: i64) -> Result<Vec<(Uuid, PathBuf)>>;
+    //the songs of a folder at or below dir with the size and mtime they were scanned at
+    fn song_stats_in_folder(&self, folder_id: i64, dir: &Path) -> Result<Vec<(Uuid, PathBuf, FileStats)>>;
+
+    //adds or updates a song along with its artist and album, a song that fails leaves nothing of itself behind
+    fn save_song(&mut self, song: &Song, artists: &HashMap<Uuid, Artist>, albums: &HashMap<Uuid, Album>) -> Result<()>;
+    fn set_song_folder(&mut self, song_id: Uuid, folder_id: i64) -> Result<()>;
+    fn delete_song(&mut self, song_id: Uuid) -> Result<()>;
+    //removes albums without songs and artists nothing points to, returns their ids
+    fn prune_orphans(&mut self) -> Result<(Vec<Uuid>, Vec<Uuid>)>;
+    //works the year, genres and the rest out again for the albums whose songs changed since the last call.
+    //commit does the same for whatever is left, this is only needed to hand the new tags to the library
+    fn update_album_tags(&mut self) -> Result<HashMap<Uuid, AlbumTags>>;
+
+    fn save_scan_report(&mut self, folder_id: i64, report: &ScanReport) -> Result<()>;
+
+    fn commit(self: Box<Self>) -> Result<()>;
+}
+
+//the real library, see Database for how connections are shared
+pub struct SqliteStore {
+    db: Database,
+}
+
+impl SqliteStore {
+    pub fn new(db: Database) -> Self {
+        SqliteStore { db }
+    }
+}
+
+impl LibraryStore for SqliteStore {
+    fn begin(&self) -> Result<Box<dyn StoreTransaction + '_>> {
+        //whoever was already waiting goes first, so a scan between batches doesnt keep the writer to itself
+        self.db.hand_over_writer();
+        let conn = self.db.writer();
+        //only ever changed through the writer, so they cant change while the transaction is open
+        let sort: SortSettings = get_setting(&conn, SORT_SETTINGS_KEY)?.unwrap_or_default();
+        conn.execute_batch("BEGIN IMMEDIATE")?;
+
+        Ok(Box::new(SqliteTransaction {
+            conn,
+            articles: sort.articles(),
+            saved_artists: HashSet::new(),
+            saved_albums: HashSet::new(),
+            changed_albums: HashSet::new(),
+            committed: false,
+        }))
+    }
+
+    fn folders(&self) -> Result<HashMap<i64, PathBuf>> {
+        let conn = self.db.reader()?;
+        get_all_folders(&conn)
+    }
+
+    fn songs(&self) -> Result<HashMap<Uuid, Song>> {
+        let conn = self.db.reader()?;
+        get_all_songs(&conn)
+    }
+
+    fn albums(&self) -> Result<HashMap<Uuid, Album>> {
+        let conn = self.db.reader()?;
+        get_all_albums(&conn)
+    }
+
+    fn artists(&self) -> Result<HashMap<Uuid, Artist>> {
+        let conn = self.db.reader()?;
+        get_all_artists(&conn)
+    }
+
+    fn song(&self, id: Uuid) -> Result<Option<Song>> {
+        let conn = self.db.reader()?;
+        get_song(&conn, id)
+    }
+
+    fn song_page(&self, query: &SongQuery) -> Result<SongPage> {
+        let conn = self.db.reader()?;
+        get_song_page(&conn, query)
+    }
+
+    fn search(&self, text: &str, limit: usize) -> Result<SearchMatches> {
+        let conn = self.db.reader()?;
+        search_library(&conn, text, limit)
+    }
+
+    fn genres(&self) -> Result<Vec<GenreCount>> {
+        let conn = self.db.reader()?;
+        get_genre_counts(&conn)
+    }
+
+    fn scan_settings(&self) -> Result<Option<ScanSettings>> {
+        let conn = self.db.reader()?;
+        get_setting(&conn, SCAN_SETTINGS_KEY)
+    }
+
+    fn set_scan_settings(&self, settings: &ScanSettings) -> Result<()> {
+        set_setting(&self.db.writer(), SCAN_SETTINGS_KEY, settings)
+    }
+
+    fn album_order(&self) -> Result<Vec<Uuid>> {
+        let conn = self.db.reader()?;
+        get_album_order(&conn)
+    }
+
+    fn artist_order(&self) -> Result<Vec<Uuid>> {
+        let conn = self.db.reader()?;
+        get_artist_order(&conn)
+    }
+
+    fn sort_settings(&self) -> Result<Option<SortSettings>> {
+        let conn = self.db.reader()?;
+        get_setting(&conn, SORT_SETTINGS_KEY)
+    }
+
+    fn set_sort_settings(&self, settings: &SortSettings) -> Result<()> {
+        save_sort_settings(&self.db.writer(), settings)
+    }
+
+    fn scan_report(&self, folder_id: i64) -> Result<Option<ScanReport>> {
+        let conn = self.db.reader()?;
+        get_scan_report(&conn, folder_id)
+    }
+}
+
+//holds the writer for as long as it is open, rolled back on drop unless committed
+pub struct SqliteTransaction<'a> {
+    conn: MutexGuard<'a, Connection>,
+    //skipped at the start of names when their sort keys are worked out
+    articles: Vec<String>,
+    //saved since the transaction began, their search entries are written once on commit
+    saved_artists: HashSet<Uuid>,
+    saved_albums: HashSet<Uuid>,
+    //albums that gained or lost songs, their tags are worked out from the songs before the commit
+    changed_albums: HashSet<Uuid>,
+    committed: bool,
+}
+
+impl StoreTransaction for SqliteTransaction<'_> {
+    fn albums(&self) -> Result<HashMap<Uuid, Album>> {
+        get_all_albums(&self.conn)
+    }
+
+    fn artists(&self) -> Result<HashMap<Uuid, Artist>> {
+        get_all_artists(&self.conn)
+    }
+
+    fn insert_folder(&mut self, path: &Path) -> Result<i64> {
+        insert_folder_and_get_id(&self.conn, path)
+    }
+
+    fn absorb_nested_folders(&mut self, folder_id: i64, path: &Path) -> Result<Vec<i64>> {
+        absorb_nested_folders(&self.conn, folder_id, path)
+    }
+
+    fn delete_folder(&mut self, folder_id: i64) -> Result<()> {
+        self.conn.execute("DELETE FROM folders WHERE id = ?1", [folder_id])?;
+        Ok(())
+    }
+
+    fn song_stats_by_path(&self, path: &Path) -> Result<Option<(Uuid, FileStats)>> {
+        get_song_stats_by_path(&self.conn, path)
+    }
+
+    fn song_ids_under_path(&self, path: &Path) -> Result<Vec<Uuid>> {
+        get_song_ids_under_path(&self.conn, path)
+    }
+
+    fn song_paths_by_fingerprint(&self, fingerprint: &str) -> Result<Vec<(Uuid, PathBuf)>> {
+        get_song_paths_by_fingerprint(&self.conn, fingerprint)
+    }
+
+    fn song_paths_in_folder(&self, folder_id: i64) -> Result<Vec<(Uuid, PathBuf)>> {
+        get_song_paths_in_folder(&self.conn, folder_id)
+    }
+
+    fn song_stats_in_folder(&self, folder_id: i64, dir: &Path) -> Result<Vec<(Uuid, PathBuf, FileStats)>> {
+        get_song_stats_in_folder(&self.conn, folder_id, dir)
+    }
+
+    //a savepoint so a half written song doesnt leave its artist and album rows behind
+    fn save_song(&mut self, song: &Song, artists: &HashMap<Uuid, Artist>, albums: &HashMap<Uuid, Album>) -> Result<()> {
+        //the albums this song and whatever was at its path were on before change too
+        let replaced = get_song_stats_by_path(&self.conn, &song.path)?.map(|(id, _)| id);
+        let mut previous = Vec::new();
+        for id in std::iter::once(song.id).chain(replaced) {
+            previous.extend(get_album_of_song(&self.conn, id)?);
+        }
+
+        self.conn.execute_batch("SAVEPOINT save_song")?;
+
+        match insert_song_to_db(&self.conn, song, artists, albums, &self.articles) {
+            Ok(()) => {
+                self.conn.execute_batch("RELEASE save_song")?;
+
+                self.changed_albums.insert(song.album);
+                self.changed_albums.extend(previous);
+                self.saved_artists.insert(song.artist);
+                if let Some(album) = albums.get(&song.album) {
+                    self.saved_albums.insert(album.id);
+                    self.saved_artists.extend(album.artists.iter().filter_map(|(id, _)| *id));
+                }
+                Ok(())
+            }
+            Err(e) => {
+                if let Err(e) = self.conn.execute_batch("ROLLBACK TO save_song; RELEASE save_song") {
+                    println!("failed to roll back song insert: {e}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn set_song_folder(&mut self, song_id: Uuid, folder_id: i64) -> Result<()> {
+        set_song_folder(&self.conn, song_id, folder_id)
+    }
+
+    fn delete_song(&mut self, song_id: Uuid) -> Result<()> {
+        self.changed_albums.extend(get_album_of_song(&self.conn, song_id)?);
+        delete_song_from_db(&self.conn, song_id)
+    }
+
+    fn prune_orphans(&mut self) -> Result<(Vec<Uuid>, Vec<Uuid>)> {
+        prune_orphans(&self.conn)
+    }
+
+    fn update_album_tags(&mut self) -> Result<HashMap<Uuid, AlbumTags>> {
+        let tags = update_album_tags(&self.conn, &self.changed_albums)?;
+        self.changed_albums.clear();
+        Ok(tags)
+    }
+
+    fn save_scan_report(&mut self, folder_id: i64, report: &ScanReport) -> Result<()> {
+        save_scan_report(&self.conn, folder_id, report)
+    }
+
+    fn commit(mut self: Box<Self>) -> Result<()> {
+        self.update_album_tags()?;
+        index_artists_and_albums(&self.conn, &self.saved_artists, &self.saved_albums)?;
+        self.conn.execute_batch("COMMIT")?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for SqliteTransaction<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            if let Err(e) = self.conn.execute_batch("ROLLBACK") {
+                println!("failed to roll back transaction: {e}");
+            }
+        }
+    }
+}
+
+//keeps the whole library in a few maps, for tests that dont want a database file
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<MemoryData>,
+}
+
+#[derive(Default, Clone)]
+struct MemoryData {
+    folders: HashMap<i64, PathBuf>,
+    songs: HashMap<Uuid, Song>,
+    //songs of each album are worked out when read, like the sqlite store does
+    albums: HashMap<Uuid, Album>,
+    artists: HashMap<Uuid, Artist>,
+    scan_settings: Option<ScanSettings>,
+    sort_settings: Option<SortSettings>,
+    scan_reports: HashMap<i64, ScanReport>,
+}
+
+//writes go to a copy that replaces the store on commit
+pub struct MemoryTransaction<'a> {
+    data: MutexGuard<'a, MemoryData>,
+    working: MemoryData,
+    changed_albums: HashSet<Uuid>,
+}
+
+//a filter on a name has to match one of the values whole, ignoring case like the NOCASE columns
+fn matches_name<'a>(wanted: Option<&str>, mut values: impl Iterator<Item = &'a String>) -> bool {
+    match wanted {
+        Some(wanted) => values.any(|v| v.eq_ignore_ascii_case(wanted)),
+        None => true,
+    }
+}
+
+impl MemoryData {
+    fn albums(&self) -> HashMap<Uuid, Album> {
+        let mut albums = self.albums.clone();
+
+        for album in albums.values_mut() {
+            album.songs = self.songs.values().filter(|s| s.album == album.id).map(|s| s.id).collect();
+            album.songs.sort();
+        }
+
+        albums
+    }
+
+    fn song_to_send(&self, s: &Song) -> SongToSend {
+        let album = self.albums.get(&s.album);
+
+        SongToSend {
+            id: s.id,
+            title: s.title.clone(),
+            artist: (s.artist, self.artists.get(&s.artist).map(|a| a.name.clone()).unwrap_or_else(|| "Unknown Artist".into())),
+            album: (s.album, album.map(|a| a.title.clone()).unwrap_or_else(|| "Unknown Album".into())),
+            features: s.features.clone(),
+            track_num: s.track_num,
+            disc_num: s.disc_num,
+            cover: album.and_then(|a| a.cover.clone()),
+            path: s.path.clone(),
+            duration: s.duration,
+            year: s.year,
+            genres: s.genres.clone(),
+            composer: s.composer.clone(),
+            conductor: s.conductor.clone(),
+            bpm: s.bpm,
+            comment: s.comment.clone(),
+        }
+    }
+
+    //keys are worked out when sorting instead of being stored, the settings can change at any time
+    fn sort_key(&self) -> impl Fn(&str) -> String {
+        let articles = self.sort_settings.clone().unwrap_or_default().articles();
+        move |name| sort_key(name, &articles)
+    }
+
+    fn ordered<'a>(&self, names: impl Iterator<Item = (Uuid, &'a str)>) -> Vec<Uuid> {
+        let key = self.sort_key();
+        let mut keyed: Vec<(String, Uuid)> = names.map(|(id, name)| (key(name), id)).collect();
+        keyed.sort();
+        keyed.into_iter().map(|(_, id)| id).collect()
+    }
+
+    //same filter and order as get_song_page, done on the whole map
+    fn song_page(&self, query: &SongQuery) -> SongPage {
+        let filter = &query.filter;
+        let key = self.sort_key();
+        let text = filter.text().map(str::to_lowercase);
+        //a sort name tag is used over the shown name, same as the sort_name columns
+        let artist_key = |(id, name): &(Uuid, String)| {
+            key(self.artists.get(id).and_then(|a| a.sort_name.as_deref()).unwrap_or(name))
+        };
+        let album_key = |(id, name): &(Uuid, String)| {
+            key(self.albums.get(id).and_then(|a| a.sort_name.as_deref()).unwrap_or(name))
+        };
+
+        let mut songs: Vec<SongToSend> = self.songs.values()
+            .filter(|s| match filter.artist {
+                Some(id) => s.artist == id || s.features.iter().flatten().any(|(f, _)| *f == Some(id)),
+                None => true,
+            })
+            .filter(|s| filter.album.iter().all(|id| s.album == *id))
+            .filter(|s| filter.folder.iter().all(|id| s.folder_id == *id))
+            .filter(|s| filter.year.iter().all(|year| s.year == Some(*year)))
+            .filter(|s| matches_name(filter.genre(), s.genres.iter()))
+            .filter(|s| matches_name(filter.composer(), s.composer.iter()))
+            .filter(|s| matches_name(filter.conductor(), s.conductor.iter()))
+            .map(|s| self.song_to_send(s))
+            .filter(|s| match &text {
+                Some(t) => [&s.title, &s.artist.1, &s.album.1].iter().any(|v| v.to_lowercase().contains(t)),
+                None => true,
+            })
+            .collect();
+
+        songs.sort_by(|a, b| {
+            let primary = match query.sort.key {
+                SongSortKey::Title => key(&a.title).cmp(&key(&b.title)),
+                SongSortKey::Artist => artist_key(&a.artist).cmp(&artist_key(&b.artist)),
+                SongSortKey::Album => album_key(&a.album).cmp(&album_key(&b.album)),
+                SongSortKey::Duration => a.duration.total_cmp(&b.duration),
+                SongSortKey::Year => a.year.cmp(&b.year),
+            };
+            let primary = if query.sort.descending { primary.reverse() } else { primary };
+            //songs without a year go last either way, like the sqlite store
+            let primary = match query.sort.key {
+                SongSortKey::Year => a.year.is_none().cmp(&b.year.is_none()).then(primary),
+                _ => primary,
+            };
+
+            let in_album = (a.disc_num, a.track_num).cmp(&(b.disc_num, b.track_num));
+            let rest = match query.sort.key {
+                SongSortKey::Artist | SongSortKey::Year => album_key(&a.album).cmp(&album_key(&b.album)).then(in_album),
+                SongSortKey::Album => in_album,
+                _ => std::cmp::Ordering::Equal,
+            };
+
+            primary.then(rest).then(a.id.to_string().cmp(&b.id.to_string()))
+        });
+
+        let total = songs.len();
+        let songs = songs.into_iter().skip(query.offset).take(query.limit).collect();
+
+        SongPage {
+            total,
+            offset: query.offset,
+            songs,
+        }
+    }
+
+    //matches terms against the starts of words like the fts5 index does, without folding diacritics.
+    //scores count the terms found in the name so they order the same way bm25 mostly does
+    fn search(&self, text: &str, limit: usize) -> SearchMatches {
+        let terms: Vec<String> = search_terms(text).iter().map(|t| t.to_lowercase()).collect();
+        if terms.is_empty() {
+            return SearchMatches::default();
+        }
+
+        let words = |field: &str| -> Vec<String> {
+            field.to_lowercase().split(|c: char| !c.is_alphanumeric()).map(String::from).collect()
+        };
+
+        let score = |name: &str, rest: &[&str]| -> Option<f64> {
+            let name = words(name);
+            let rest: Vec<String> = rest.iter().flat_map(|f| words(f)).collect();
+
+            let mut in_name = 0;
+            for term in &terms {
+                if name.iter().any(|w| w.starts_with(term.as_str())) {
+                    in_name += 1;
+                } else if !rest.iter().any(|w| w.starts_with(term.as_str())) {
+                    return None;
+                }
+            }
+
+            Some(-(in_name as f64))
+        };
+
+        let ranked = |mut hits: Vec<(Uuid, f64, String)>| -> Vec<(Uuid, f64)> {
+            hits.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.2.len().cmp(&b.2.len())).then(a.0.cmp(&b.0)));
+            hits.into_iter().take(limit).map(|(id, score, _)| (id, score)).collect()
+        };
+
+        let artist_name = |id: &Uuid| self.artists.get(id).map(|a| a.name.clone()).unwrap_or_default();
+
+        let artists = ranked(self.artists.values()
+            .filter_map(|a| score(&a.name, &[]).map(|s| (a.id, s, a.name.clone())))
+            .collect());
+
+        let albums = ranked(self.albums.values()
+            .filter_map(|a| {
+                let artists: Vec<&str> = a.artists.iter().map(|(_, n)| n.as_str()).collect();
+                score(&a.title, &artists).map(|s| (a.id, s, a.title.clone()))
+            })
+            .collect());
+
+        let songs = ranked(self.songs.values()
+            .filter_map(|s| {
+                let mut fields = vec![artist_name(&s.artist)];
+                fields.extend(s.features.iter().flatten().map(|(_, n)| n.clone()));
+                fields.push(self.albums.get(&s.album).map(|a| a.title.clone()).unwrap_or_default());
+                fields.push(s.path.to_string_lossy().to_string());
+
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                score(&s.title, &fields).map(|score| (s.id, score, s.title.clone()))
+            })
+            .collect());
+
+        let found = songs.iter().filter_map(|(id, _)| self.songs.get(id)).map(|s| self.song_to_send(s)).collect();
+
+        SearchMatches {
+            top_hit: pick_top_hit(&songs, &albums, &artists),
+            songs: found,
+            albums: albums.into_iter().map(|(id, _)| id).collect(),
+            artists: artists.into_iter().map(|(id, _)| id).collect(),
+        }
+    }
+
+    //spellings that only differ in case count as one genre, shown the same way get_genre_counts does
+    fn genres(&self) -> Vec<GenreCount> {
+        let mut counts: Vec<GenreCount> = Vec::new();
+        for genre in self.songs.values().flat_map(|s| s.genres.iter()) {
+            match counts.iter_mut().find(|g| g.name.eq_ignore_ascii_case(genre)) {
+                Some(count) => {
+                    count.songs += 1;
+                    if *genre < count.name {
+                        count.name = genre.clone();
+                    }
+                }
+                None => counts.push(GenreCount { name: genre.clone(), songs: 1 }),
+            }
+        }
+
+        counts.sort_by_key(|g| g.name.to_ascii_lowercase());
+        counts
+    }
+
+    fn song_paths(&self, keep: impl Fn(&Song) -> bool) -> Vec<(Uuid, PathBuf)> {
+        self.songs.values().filter(|s| keep(s)).map(|s| (s.id, s.path.clone())).collect()
+    }
+}
+
+impl LibraryStore for MemoryStore {
+    fn begin(&self) -> Result<Box<dyn StoreTransaction + '_>> {
+        let data = self.data.lock().unwrap();
+        let working = data.clone();
+
+        Ok(Box::new(MemoryTransaction { data, working, changed_albums: HashSet::new() }))
+    }
+
+    fn folders(&self) -> Result<HashMap<i64, PathBuf>> {
+        Ok(self.data.lock().unwrap().folders.clone())
+    }
+
+    fn songs(&self) -> Result<HashMap<Uuid, Song>> {
+        Ok(self.data.lock().unwrap().songs.clone())
+    }
+
+    fn albums(&self) -> Result<HashMap<Uuid, Album>> {
+        Ok(self.data.lock().unwrap().albums())
+    }
+
+    fn artists(&self) -> Result<HashMap<Uuid, Artist>> {
+        Ok(self.data.lock().unwrap().artists.clone())
+    }
+
+    fn song(&self, id: Uuid) -> Result<Option<Song>> {
+        Ok(self.data.lock().unwrap().songs.get(&id).cloned())
+    }
+
+    fn song_page(&self, query: &SongQuery) -> Result<SongPage> {
+        Ok(self.data.lock().unwrap().song_page(query))
+    }
+
+    fn search(&self, text: &str, limit: usize) -> Result<SearchMatches> {
+        Ok(self.data.lock().unwrap().search(text, limit))
+    }
+
+    fn genres(&self) -> Result<Vec<GenreCount>> {
+        Ok(self.data.lock().unwrap().genres())
+    }
+
+    fn scan_settings(&self) -> Result<Option<ScanSettings>> {
+        Ok(self.data.lock().unwrap().scan_settings.clone())
+    }
+
+    fn set_scan_settings(&self, settings: &ScanSettings) -> Result<()> {
+        self.data.lock().unwrap().scan_settings = Some(settings.clone());
+        Ok(())
+    }
+
+    fn album_order(&self) -> Result<Vec<Uuid>> {
+        let data = self.data.lock().unwrap();
+        Ok(data.ordered(data.albums.values().map(|a| (a.id, a.sort_name.as_deref().unwrap_or(&a.title)))))
+    }
+
+    fn artist_order(&self) -> Result<Vec<Uuid>> {
+        let data = self.data.lock().unwrap();
+        Ok(data.ordered(data.artists.values().map(|a| (a.id, a.sort_name.as_deref().unwrap_or(&a.name)))))
+    }
+
+    fn sort_settings(&self) -> Result<Option<SortSettings>> {
+        Ok(self.data.lock().unwrap().sort_settings.clone())
+    }
+
+    fn set_sort_settings(&self, settings: &SortSettings) -> Result<()> {
+        self.data.lock().unwrap().sort_settings = Some(settings.clone());
+        Ok(())
+    }
+
+    fn scan_report(&self, folder_id: i64) -> Result<Option<ScanReport>> {
+        Ok(self.data.lock().unwrap().scan_reports.get(&folder_id).cloned())
+    }
+}
+
+impl StoreTransaction for MemoryTransaction<'_> {
+    fn albums(&self) -> Result<HashMap<Uuid, Album>> {
+        Ok(self.working.albums())
+    }
+
+    fn artists(&self) -> Result<HashMap<Uuid, Artist>> {
+        Ok(self.working.artists.clone())
+    }
+
+    fn insert_folder(&mut self, path: &Path) -> Result<i64> {
+        if let Some((id, _)) = self.working.folders.iter().find(|(_, p)| *p == path) {
+            return Ok(*id);
+        }
+
+        let id = self.working.folders.keys().max().copied().unwrap_or(0) + 1;
+        self.working.folders.insert(id, path.to_path_buf());
+        Ok(id)
+    }
+
+    fn absorb_nested_folders(&mut self, folder_id: i64, path: &Path) -> Result<Vec<i64>> {
+        let nested: Vec<i64> = self
+            .working
+            .folders
+            .iter()
+            .filter(|(id, p)| **id != folder_id && p.starts_with(path))
+            .map(|(id, _)| *id)
+            .collect();
+
+        for song in self.working.songs.values_mut() {
+            if nested.contains(&song.folder_id) {
+                song.folder_id = folder_id;
+            }
+        }
+
+        self.working.folders.retain(|id, _| !nested.contains(id));
+        Ok(nested)
+    }
+
+    fn delete_folder(&mut self, folder_id: i64) -> Result<()> {
+        self.working.folders.remove(&folder_id);
+        Ok(())
+    }
+
+    fn song_stats_by_path(&self, path: &Path) -> Result<Option<(Uuid, FileStats)>> {
+        Ok(self
+            .working
+            .songs
+            .values()
+            .find(|s| s.path == path)
+            .map(|s| (s.id, FileStats { size: s.file_size, modified: s.modified })))
+    }
+
+    fn song_ids_under_path(&self, path: &Path) -> Result<Vec<Uuid>> {
+        Ok(self.working.song_paths(|s| s.path.starts_with(path)).into_iter().map(|(id, _)| id).collect())
+    }
+
+    fn song_paths_by_fingerprint(&self, fingerprint: &str) -> Result<Vec<(Uuid, PathBuf)>> {
+        Ok(self.working.song_paths(|s| s.fingerprint.as_deref() == Some(fingerprint)))
+    }
+
+    fn song_paths_in_folder(&self, folder_id: i64) -> Result<Vec<(Uuid, PathBuf)>> {
+        Ok(self.working.song_paths(|s| s.folder_id == folder_id))
+    }
+
+    fn song_stats_in_folder(&self, folder_id: i64, dir: &Path) -> Result<Vec<(Uuid, PathBuf, FileStats)>> {
+        Ok(self
+            .working
+            .songs
+            .values()
+            .filter(|s| s.folder_id == folder_id && s.path.starts_with(dir))
+            .map(|s| (s.id, s.path.clone(), FileStats { size: s.file_size, modified: s.modified }))
+            .collect())
+    }
+
+    //existing artists and albums only take on tags they didnt have or that changed, same as the sqlite store
+    fn save_song(&mut self, song: &Song, artists: &HashMap<Uuid, Artist>, albums: &HashMap<Uuid, Album>) -> Result<()> {
+        let album_artists = albums.get(&song.album).into_iter().flat_map(|album| album.artists.iter().filter_map(|(id, _)| *id));
+        for artist in std::iter::once(song.artist).chain(album_artists).filter_map(|id| artists.get(&id)) {
+            let saved = self.working.artists.entry(artist.id).or_insert_with(|| artist.clone());
+            if artist.sort_name.is_some() {
+                saved.sort_name = artist.sort_name.clone();
+            }
+        }
+
+        if let Some(album) = albums.get(&song.album) {
+            let saved = self.working.albums.entry(album.id).or_insert_with(|| album.clone());
+            if album.cover.is_some() && album.cover != saved.cover {
+                saved.cover = album.cover.clone();
+                saved.cover_source = album.cover_source.clone();
+                saved.palette = album.palette.clone();
+            }
+            if album.sort_name.is_some() {
+                saved.sort_name = album.sort_name.clone();
+            }
+        }
+
+        let mut song = song.clone();
+        song.cover = None;
+        //one song per path, the same as the unique path column in sqlite
+        let replaced = self.working.songs.values().filter(|saved| saved.id == song.id || saved.path == song.path);
+        self.changed_albums.extend(replaced.map(|saved| saved.album));
+        self.changed_albums.insert(song.album);
+        self.working.songs.retain(|id, saved| *id == song.id || saved.path != song.path);
+        self.working.songs.insert(song.id, song);
+        Ok(())
+    }
+
+    fn set_song_folder(&mut self, song_id: Uuid, folder_id: i64) -> Result<()> {
+        if let Some(song) = self.working.songs.get_mut(&song_id) {
+            song.folder_id = folder_id;
+        }
+        Ok(())
+    }
+
+    fn delete_song(&mut self, song_id: Uuid) -> Result<()> {
+        if let Some(song) = self.working.songs.remove(&song_id) {
+            self.changed_albums.insert(song.album);
+        }
+        Ok(())
+    }
+
+    fn prune_orphans(&mut self) -> Result<(Vec<Uuid>, Vec<Uuid>)> {
+        let data = &mut self.working;
+
+        let albums: Vec<Uuid> = data
+            .albums
+            .keys()
+            .filter(|id| !data.songs.values().any(|s| s.album == **id))
+            .copied()
+            .collect();
+        data.albums.retain(|id, _| !albums.contains(id));
+
+        let referenced = |id: &Uuid| {
+            data.songs.values().any(|s| {
+                s.artist == *id || s.features.iter().flatten().any(|(feature, _)| *feature == Some(*id))
+            }) || data.albums.values().any(|a| a.artists.iter().any(|(artist, _)| *artist == Some(*id)))
+        };
+
+        let artists: Vec<Uuid> = data.artists.keys().filter(|id| !referenced(id)).copied().collect();
+        data.artists.retain(|id, _| !artists.contains(id));
+
+        Ok((albums, artists))
+    }
+
+    fn update_album_tags(&mut self) -> Result<HashMap<Uuid, AlbumTags>> {
+        let mut updated = HashMap::new();
+
+        for id in self.changed_albums.drain() {
+            let songs: Vec<&Song> = self.working.songs.values().filter(|s| s.album == id).collect();
+            if songs.is_empty() {
+                continue;
+            }
+
+            if let Some(album) = self.working.albums.get_mut(&id) {
+                let tags = AlbumTags::from_songs(songs);
+                tags.apply(album);
+                updated.insert(id, tags);
+            }
+        }
+
+        Ok(updated)
+    }
+
+    fn save_scan_report(&mut self, folder_id: i64, report: &ScanReport) -> Result<()> {
+        self.working.scan_reports.insert(folder_id, report.clone());
+        Ok(())
+    }
+
+    fn commit(mut self: Box<Self>) -> Result<()> {
+        self.update_album_tags()?;
+        *self.data = std::mem::take(&mut self.working);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::query::{SongFilter, SongSort};
+    use crate::core::search::SearchKind;
+    use crate::core::fixtures;
+
+    //both stores have to agree, MemoryStore is only useful in tests if it behaves like the real one
+    fn check_store(store: &dyn LibraryStore) {
+        let artist = fixtures::artist("Band");
+        let album = fixtures::album("Loud", &artist);
+        let artists = HashMap::from([(artist.id, artist.clone())]);
+        let albums = HashMap::from([(album.id, album.clone())]);
+
+        let mut tx = store.begin().unwrap();
+        let music = tx.insert_folder(Path::new("/music")).unwrap();
+        let rock = tx.insert_folder(Path::new("/music/rock")).unwrap();
+        assert_eq!(tx.insert_folder(Path::new("/music")).unwrap(), music);
+
+        let a = fixtures::song(rock, "/music/rock/a.mp3", &artist, &album);
+        let b = fixtures::song(music, "/music/b.mp3", &artist, &album);
+        tx.save_song(&a, &artists, &albums).unwrap();
+        tx.save_song(&b, &artists, &albums).unwrap();
+        tx.commit().unwrap();
+
+        //dropped without a commit, nothing it did is kept
+        let mut tx = store.begin().unwrap();
+        tx.delete_song(a.id).unwrap();
+        drop(tx);
+        assert_eq!(store.songs().unwrap().len(), 2);
+
+        //a file saved again under a new id replaces the row its path had
+        let mut tx = store.begin().unwrap();
+        let mut replaced = fixtures::song(music, "/music/b.mp3", &artist, &album);
+        tx.save_song(&replaced, &artists, &albums).unwrap();
+        tx.commit().unwrap();
+        let songs = store.songs().unwrap();
+        assert_eq!(songs.len(), 2);
+        assert!(songs.contains_key(&replaced.id) && !songs.contains_key(&b.id));
+
+        let mut tx = store.begin().unwrap();
+        replaced.id = b.id;
+        tx.save_song(&replaced, &artists, &albums).unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = store.begin().unwrap();
+        assert_eq!(tx.song_ids_under_path(Path::new("/music/rock")).unwrap(), vec![a.id]);
+        assert_eq!(tx.absorb_nested_folders(music, Path::new("/music")).unwrap(), vec![rock]);
+        assert_eq!(tx.song_paths_in_folder(music).unwrap().len(), 2);
+        let stats = tx.song_stats_in_folder(music, Path::new("/music/rock")).unwrap();
+        assert_eq!(stats, vec![(a.id, a.path.clone(), FileStats { size: 1, modified: 1 })]);
+        assert!(tx.song_stats_in_folder(music, Path::new("/music/ro")).unwrap().is_empty());
+
+        tx.delete_song(a.id).unwrap();
+        assert_eq!(tx.prune_orphans().unwrap(), (Vec::new(), Vec::new()));
+        tx.delete_song(b.id).unwrap();
+        assert_eq!(tx.prune_orphans().unwrap(), (vec![album.id], vec![artist.id]));
+        tx.commit().unwrap();
+
+        assert_eq!(store.folders().unwrap(), HashMap::from([(music, PathBuf::from("/music"))]));
+        assert!(store.albums().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stores_behave_the_same() {
+        check_store(&MemoryStore::default());
+        check_store(&SqliteStore::new(Database::open_in_memory().unwrap()));
+    }
+
+    fn check_paging(store: &dyn LibraryStore) {
+        let artist = fixtures::artist("Band");
+        let album = fixtures::album("Loud", &artist);
+        let artists = HashMap::from([(artist.id, artist.clone())]);
+        let albums = HashMap::from([(album.id, album.clone())]);
+
+        let mut tx = store.begin().unwrap();
+        let folder = tx.insert_folder(Path::new("/music")).unwrap();
+        for (i, title) in ["c 50%", "a", "B", "d", "e"].iter().enumerate() {
+            let s = Song {
+                title: title.to_string(),
+                duration: i as f64,
+                ..fixtures::song(folder, &format!("/music/{i}.mp3"), &artist, &album)
+            };
+            tx.save_song(&s, &artists, &albums).unwrap();
+        }
+        tx.commit().unwrap();
+
+        let titles = |query: SongQuery| -> (usize, Vec<String>) {
+            let page = store.song_page(&query).unwrap();
+            (page.total, page.songs.into_iter().map(|s| s.title).collect())
+        };
+
+        //title order ignores case and the total counts every page
+        let page = titles(SongQuery::new(1, 2, SongSort::default(), SongFilter::default()));
+        assert_eq!(page, (5, vec!["B".to_string(), "c 50%".to_string()]));
+
+        let sort = SongSort { key: SongSortKey::Duration, descending: true };
+        let page = titles(SongQuery::new(0, 2, sort, SongFilter::default()));
+        assert_eq!(page, (5, vec!["e".to_string(), "d".to_string()]));
+
+        //% is a plain character in the search text, not a wildcard
+        let filter = SongFilter { text: Some(" 50% ".into()), ..Default::default() };
+        assert_eq!(titles(SongQuery::new(0, 10, SongSort::default(), filter)).0, 1);
+
+        let filter = SongFilter { text: Some("loud".into()), artist: Some(artist.id), ..Default::default() };
+        assert_eq!(titles(SongQuery::new(0, 10, SongSort::default(), filter)).0, 5);
+
+        let filter = SongFilter { album: Some(Uuid::new_v4()), ..Default::default() };
+        assert_eq!(titles(SongQuery::new(0, 10, SongSort::default(), filter)).0, 0);
+    }
+
+    #[test]
+    fn test_song_pages() {
+        check_paging(&MemoryStore::default());
+        check_paging(&SqliteStore::new(Database::open_in_memory().unwrap()));
+    }
+
+    fn check_search(store: &dyn LibraryStore) {
+        let artist = fixtures::artist("Beyonce");
+        let album = fixtures::album("Lemonade", &artist);
+        let artists = HashMap::from([(artist.id, artist.clone())]);
+        let albums = HashMap::from([(album.id, album.clone())]);
+
+        let mut tx = store.begin().unwrap();
+        let folder = tx.insert_folder(Path::new("/music")).unwrap();
+        let formation = Song { title: "Formation".into(), ..fixtures::song(folder, "/music/1.mp3", &artist, &album) };
+        let hold_up = Song { title: "Hold Up".into(), ..fixtures::song(folder, "/music/2.mp3", &artist, &album) };
+        tx.save_song(&formation, &artists, &albums).unwrap();
+        tx.save_song(&hold_up, &artists, &albums).unwrap();
+        tx.commit().unwrap();
+
+        let found = store.search("form", 10).unwrap();
+        assert_eq!(found.songs.iter().map(|s| s.id).collect::<Vec<_>>(), vec![formation.id]);
+        assert_eq!(found.top_hit, Some((SearchKind::Song, formation.id)));
+        assert!(found.albums.is_empty() && found.artists.is_empty());
+
+        //the artist is found by name, the album and songs through who made them
+        let found = store.search("BEYO", 10).unwrap();
+        assert_eq!(found.top_hit, Some((SearchKind::Artist, artist.id)));
+        assert_eq!((found.songs.len(), found.albums, found.artists), (2, vec![album.id], vec![artist.id]));
+
+        //every term has to match
+        assert_eq!(store.search("hold form", 10).unwrap().songs.len(), 0);
+        assert_eq!(store.search("hold lemon", 10).unwrap().songs.len(), 1);
+        assert_eq!(store.search("beyo", 1).unwrap().songs.len(), 1);
+
+        let mut tx = store.begin().unwrap();
+        tx.delete_song(formation.id).unwrap();
+        tx.delete_song(hold_up.id).unwrap();
+        tx.prune_orphans().unwrap();
+        tx.commit().unwrap();
+
+        let found = store.search("beyo", 10).unwrap();
+        assert!(found.top_hit.is_none() && found.songs.is_empty() && found.albums.is_empty() && found.artists.is_empty());
+    }
+
+    #[test]
+    fn test_search() {
+        check_search(&MemoryStore::default());
+        check_search(&SqliteStore::new(Database::open_in_memory().unwrap()));
+    }
+
+    fn check_sorting(store: &dyn LibraryStore) {
+        let mut tx = store.begin().unwrap();
+        let folder = tx.insert_folder(Path::new("/music")).unwrap();
+
+        let mut ids = HashMap::new();
+        for (i, name) in ["The Beatles", "Ásgeir", "Cher"].iter().enumerate() {
+            let artist = fixtures::artist(name);
+            let album = fixtures::album(&format!("Track {}", [10, 9, 1][i]), &artist);
+
+            let s = Song { title: album.title.clone(), ..fixtures::song(folder, &format!("/music/{i}.mp3"), &artist, &album) };
+            tx.save_song(&s, &HashMap::from([(artist.id, artist.clone())]), &HashMap::from([(album.id, album.clone())])).unwrap();
+            ids.insert(*name, (artist.id, album.id));
+        }
+        tx.commit().unwrap();
+
+        let artist_order = |names: &[&str]| names.iter().map(|n| ids[n].0).collect::<Vec<_>>();
+        assert_eq!(store.artist_order().unwrap(), artist_order(&["Ásgeir", "The Beatles", "Cher"]));
+        assert_eq!(store.album_order().unwrap(), vec![ids["Cher"].1, ids["Ásgeir"].1, ids["The Beatles"].1]);
+
+        let titles = |key: SongSortKey| -> Vec<String> {
+            let sort = SongSort { key, descending: false };
+            store.song_page(&SongQuery::new(0, 10, sort, SongFilter::default())).unwrap().songs.into_iter().map(|s| s.title).collect()
+        };
+        assert_eq!(titles(SongSortKey::Title), vec!["Track 1", "Track 9", "Track 10"]);
+        assert_eq!(titles(SongSortKey::Artist), vec!["Track 9", "Track 10", "Track 1"]);
+
+        //without any articles "The" is part of the name again
+        store.set_sort_settings(&SortSettings { languages: Vec::new(), extra_articles: Vec::new() }).unwrap();
+        assert_eq!(store.artist_order().unwrap(), artist_order(&["Ásgeir", "Cher", "The Beatles"]));
+        assert_eq!(titles(SongSortKey::Artist), vec!["Track 9", "Track 1", "Track 10"]);
+    }
+
+    #[test]
+    fn test_sorting() {
+        check_sorting(&MemoryStore::default());
+        check_sorting(&SqliteStore::new(Database::open_in_memory().unwrap()));
+    }
+
+    fn check_tags(store: &dyn LibraryStore) {
+        let artist = fixtures::artist("David Bowie");
+        let album = fixtures::album("Low", &artist);
+        let artists = HashMap::from([(artist.id, artist.clone())]);
+        let albums = HashMap::from([(album.id, album.clone())]);
+
+        let mut tx = store.begin().unwrap();
+        let folder = tx.insert_folder(Path::new("/music")).unwrap();
+
+        let speed = Song {
+            title: "Speed of Life".into(),
+            year: Some(1977),
+            genres: vec!["Rock".into()],
+            composer: Some("David Bowie".into()),
+            bpm: Some(132),
+            track_total: Some(10),
+            label: Some("RCA".into()),
+            ..fixtures::song(folder, "/music/1.mp3", &artist, &album)
+        };
+        tx.save_song(&speed, &artists, &albums).unwrap();
+
+        //a second file of the album without a label doesnt take the first one's away
+        let warszawa = Song {
+            title: "Warszawa".into(),
+            track_num: 2,
+            genres: vec!["rock".into(), "Art Rock".into()],
+            composer: Some("Brian Eno".into()),
+            ..fixtures::song(folder, "/music/2.mp3", &artist, &album)
+        };
+        tx.save_song(&warszawa, &artists, &albums).unwrap();
+        let tags = tx.update_album_tags().unwrap();
+        tx.commit().unwrap();
+
+        let saved = &store.albums().unwrap()[&album.id];
+        assert_eq!((saved.year, saved.track_total, saved.label.as_deref()), (Some(1977), Some(10), Some("RCA")));
+        assert_eq!(saved.genres, vec!["Rock", "Art Rock"]);
+        assert_eq!(tags[&album.id].genres, saved.genres);
+
+        let saved = store.song(speed.id).unwrap().unwrap();
+        assert_eq!((saved.genres, saved.composer, saved.bpm), (speed.genres.clone(), speed.composer.clone(), Some(132)));
+
+        let genres: Vec<(String, usize)> = store.genres().unwrap().into_iter().map(|g| (g.name, g.songs)).collect();
+        assert_eq!(genres, vec![("Art Rock".into(), 1), ("Rock".into(), 2)]);
+
+        let titles = |sort: SongSort, filter: SongFilter| -> Vec<String> {
+            store.song_page(&SongQuery::new(0, 10, sort, filter)).unwrap().songs.into_iter().map(|s| s.title).collect()
+        };
+
+        let filter = SongFilter { genre: Some("ROCK".into()), ..Default::default() };
+        assert_eq!(titles(SongSort::default(), filter).len(), 2);
+        let filter = SongFilter { genre: Some("art rock".into()), composer: Some("brian eno".into()), ..Default::default() };
+        assert_eq!(titles(SongSort::default(), filter), vec!["Warszawa"]);
+        let filter = SongFilter { year: Some(1977), ..Default::default() };
+        assert_eq!(titles(SongSort::default(), filter), vec!["Speed of Life"]);
+
+        //songs without a year come last both ways
+        for descending in [false, true] {
+            let sort = SongSort { key: SongSortKey::Year, descending };
+            assert_eq!(titles(sort, SongFilter::default()), vec!["Speed of Life", "Warszawa"]);
+        }
+
+        //retagged and removed songs take what only they had away from the album
+        let mut tx = store.begin().unwrap();
+        tx.save_song(&Song { genres: vec!["Ambient".into()], ..warszawa.clone() }, &artists, &albums).unwrap();
+        tx.delete_song(speed.id).unwrap();
+        tx.commit().unwrap();
+
+        let saved = &store.albums().unwrap()[&album.id];
+        assert_eq!((saved.year, saved.track_total, saved.label.as_deref()), (None, None, None));
+        assert_eq!(saved.genres, vec!["Ambient"]);
+    }
+
+    #[test]
+    fn test_tags() {
+        check_tags(&MemoryStore::default());
+        check_tags(&SqliteStore::new(Database::open_in_memory().unwrap()));
+    }
+}
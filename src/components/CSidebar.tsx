@@ -48,7 +48,7 @@ const items = [
   },
   {
     title: "search",
-    url: "#",
+    url: "search",
     icon: Search,
   },
   {
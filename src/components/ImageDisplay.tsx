@@ -1,4 +1,5 @@
 import { useEffect, useState } from "react"
+import { convertFileSrc } from "@tauri-apps/api/core"
 import type { Image } from "@/types"
 
 interface ImageDisplayProps {
@@ -43,3 +44,42 @@ export function ImageDisplay({ image, altText = "Image", className = "" }: Image
         />
     );
 }
+
+interface CoverDisplayProps {
+    albumId?: string | null;
+    // hash from the album, pins the url to that exact cover so the webview can cache it for good
+    hash?: string | null;
+    // longest edge the cover is drawn at, the backend picks the closest thumbnail
+    size?: number;
+    altText?: string;
+    className?: string;
+}
+
+// cover://localhost/<album-id> on linux and mac, http://cover.localhost/<album-id> on windows
+export function coverUrl(albumId: string, hash?: string | null, size?: number): string {
+    const params = new URLSearchParams()
+    if (size) {
+        params.set("size", String(size))
+    }
+    if (hash) {
+        params.set("v", hash)
+    }
+
+    return `${convertFileSrc(albumId, "cover")}?${params}`
+}
+
+export function CoverDisplay({ albumId, hash, size, altText = "Cover", className = "" }: CoverDisplayProps) {
+    if (!albumId || !hash) {
+        return <img src="" alt={altText} className={className} />
+    }
+
+    return (
+        <img
+            src={coverUrl(albumId, hash, size)}
+            alt={altText}
+            className={className}
+            loading="lazy"
+            decoding="async"
+        />
+    )
+}
@@ -3,24 +3,39 @@ import { useState, useEffect, useRef } from "react";
 import { Pause, Play, SkipBack, SkipForward } from "lucide-react";
 import { listen } from "@tauri-apps/api/event";
 import { invoke } from "@tauri-apps/api/core";
-import { ImageDisplay } from "@/components/ImageDisplay";
+import { CoverDisplay } from "@/components/ImageDisplay";
 
-import type { Image } from "@/types";
+import type { Palette } from "@/types";
 
 type PlayingSong = {
     title: string;
     artist: [string, string];
     features?: [string | null, string][];
     album: [string, string];
+    cover?: string | null;
+    palette?: Palette | null;
     duration: number;
 };
 
+// exposes the playing cover's colours as css variables so the rest of the ui can pick them up
+const applyPalette = (palette?: Palette | null) => {
+    const root = document.documentElement.style
+    const names = ["dominant", "vibrant", "muted", "text"] as const
+
+    for (const name of names) {
+        if (palette) {
+            root.setProperty(`--cover-${name}`, palette[name])
+        } else {
+            root.removeProperty(`--cover-${name}`)
+        }
+    }
+}
+
 export default function MediaBar() {
     const [sliderValue, setSliderValue] = useState<number>(0);
     const [isPlaying, setIsPlaying] = useState<boolean>(false);
     const [currentSong, setCurrentSong] = useState<PlayingSong | null>(null);
     const [isDragging, setIsDragging] = useState<boolean>(false);
-    const [cover, setCover] = useState<Image>()
     const [volume, setVolume] = useState(100)
 
     const animationFrameRef = useRef<number | null>(null);
@@ -70,7 +85,7 @@ export default function MediaBar() {
             setCurrentSong(e.payload);
             setIsPlaying(true);
             setSliderValue(0);
-            requestCoverArt(e.payload.album[0])
+            applyPalette(e.payload.palette)
             startTimeRef.current = performance.now() / 1000;
 
             
@@ -81,14 +96,6 @@ export default function MediaBar() {
         };
     }, []);
 
-    const requestCoverArt = async (id: string) => {
-        const cover = await invoke<Image | null>("get_cover", { id })
-
-        if (cover) {
-            setCover(cover)
-        }
-    }
-
     const togglePlay = async () => {
         try {
             await invoke("toggle_play");
@@ -128,7 +135,7 @@ export default function MediaBar() {
     return (
         <div className="flex flex-row h-full w-full select-none">
             <div className="w-1/4 flex flex-row border-pink-400 gap-3 border-2">
-                <ImageDisplay image={cover} className="h-full aspect-square"/>
+                <CoverDisplay albumId={currentSong?.album[0]} hash={currentSong?.cover} size={256} className="h-full aspect-square"/>
                 <div className="flex flex-col iteems-center justify-center text-white min-w-0">
                     <p className="text-ellipsis overflow-hidden whitespace-nowrap text-sm font-medium">
                         {currentSong?.title ?? ""}
@@ -0,0 +1,141 @@
+import { invoke } from "@tauri-apps/api/core"
+import { useEffect, useRef, useState } from "react"
+import { CoverDisplay } from "@/components/ImageDisplay"
+import { Input } from "@/components/ui/input"
+
+import type { Album, Artist, SearchResults, Song } from "@/types"
+
+const RESULT_LIMIT = 20
+
+function SongRow({ song }: { song: Song }) {
+    const playSong = async () => {
+        try {
+            await invoke("play_song", { id: song.id })
+        }
+        catch (error) {
+            console.log(String(error))
+        }
+    }
+
+    return (
+        <div onClick={playSong} className="flex flex-row bg-zinc-900 rounded-lg gap-2 cursor-pointer">
+            <CoverDisplay
+            albumId={song.album[0]}
+            hash={song.cover}
+            size={60}
+            className="w-[60px] h-[60px] rounded-l-lg"
+            />
+            <div className="flex flex-col text-white justify-center">
+                <p>{song.title}</p>
+                <p className="text-sm text-zinc-400">{song.artist[1]} · {song.album[1]}</p>
+            </div>
+        </div>
+    )
+}
+
+function AlbumCard({ album }: { album: Album }) {
+    return (
+        <div className="flex flex-col bg-gray-900 rounded-lg shadow-lg overflow-hidden w-40 shrink-0">
+            <CoverDisplay albumId={album.id} hash={album.cover} size={160} className="aspect-square w-full bg-zinc-700" />
+            <div className="p-2 text-white">
+                <h3 className="text-sm font-semibold line-clamp-2">{album.title}</h3>
+                <p className="text-xs text-gray-400 whitespace-nowrap overflow-hidden text-ellipsis">
+                    {album.artists.map(artist => artist[1]).join(', ')}
+                </p>
+            </div>
+        </div>
+    )
+}
+
+function ArtistCard({ artist }: { artist: Artist }) {
+    return (
+        <div className="flex flex-col bg-gray-900 rounded-lg shadow-lg overflow-hidden w-40 shrink-0">
+            <div className="aspect-square w-full bg-zinc-700 flex items-center justify-center">
+                <span className="text-3xl font-bold text-zinc-400">{artist.name.charAt(0)}</span>
+            </div>
+            <div className="p-2 text-white">
+                <h3 className="text-sm font-semibold line-clamp-2">{artist.name}</h3>
+            </div>
+        </div>
+    )
+}
+
+export default function SearchDisplay() {
+    const [query, setQuery] = useState("")
+    const [results, setResults] = useState<SearchResults>()
+    // only the answer to the latest query is shown, slower earlier ones are dropped
+    const latest = useRef(0)
+
+    useEffect(() => {
+        if (query.trim() === "") {
+            setResults(undefined)
+            return
+        }
+
+        const timeout = setTimeout(async () => {
+            const id = ++latest.current
+            try {
+                const found = await invoke<SearchResults>("search", { query, limit: RESULT_LIMIT })
+                if (id === latest.current) setResults(found)
+            }
+            catch (error) {
+                console.log(String(error))
+            }
+        }, 150)
+
+        return () => clearTimeout(timeout)
+    }, [query])
+
+    const top = results?.top_hit
+    const empty = results && !top && results.songs.length === 0
+
+    return (
+        <div className="flex flex-col w-full gap-5">
+            <Input
+                autoFocus
+                placeholder="Search songs, albums and artists"
+                value={query}
+                onChange={(e) => setQuery(e.target.value)}
+                className="max-w-md text-white"
+            />
+
+            {empty && <p className="text-zinc-400">Nothing matches "{query.trim()}"</p>}
+
+            {top && (
+                <section className="flex flex-col gap-2">
+                    <h2 className="text-white text-xl">Top result</h2>
+                    <div className="max-w-md">
+                        {top.kind === "song" && <SongRow song={top.item} />}
+                        {top.kind === "album" && <AlbumCard album={top.item} />}
+                        {top.kind === "artist" && <ArtistCard artist={top.item} />}
+                    </div>
+                </section>
+            )}
+
+            {results && results.songs.length > 0 && (
+                <section className="flex flex-col gap-2">
+                    <h2 className="text-white text-xl">Songs</h2>
+                    {results.songs.map((song) => <SongRow key={song.id} song={song} />)}
+                </section>
+            )}
+
+            {results && results.albums.length > 0 && (
+                <section className="flex flex-col gap-2">
+                    <h2 className="text-white text-xl">Albums</h2>
+                    <div className="flex flex-row gap-3 overflow-x-auto">
+                        {results.albums.map((album) => <AlbumCard key={album.id} album={album} />)}
+                    </div>
+                </section>
+            )}
+
+            {results && results.artists.length > 0 && (
+                <section className="flex flex-col gap-2">
+                    <h2 className="text-white text-xl">Artists</h2>
+                    <div className="flex flex-row gap-3 overflow-x-auto">
+                        {results.artists.map((artist) => <ArtistCard key={artist.id} artist={artist} />)}
+                    </div>
+                </section>
+            )}
+        </div>
+    )
+}
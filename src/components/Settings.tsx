@@ -1,14 +1,39 @@
 import { useEffect, useState } from "react"
 import { Button } from "@/components/ui/button"
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from "@tauri-apps/api/event";
 import { open } from "@tauri-apps/plugin-dialog"
 import { DirectoryTable } from "@/components/settings/DirectoryTable";
+import { ProfileSettings } from "@/components/settings/ProfileSettings";
+import { SortSettings } from "@/components/settings/SortSettings";
 import { Separator } from "@/components/ui/separator";
+import type { FolderOverlap, ScanFinished, ScanProgress } from "@/types";
 
 
 export function SettingsView() {
     const [error, setError] = useState("")
-    
+    const [jobId, setJobId] = useState<string | null>(null)
+    const [progress, setProgress] = useState<ScanProgress | null>(null)
+
+    useEffect(() => {
+        const unlistenProgress = listen<ScanProgress>("scan-progress", (e) => {
+            setProgress(e.payload)
+        });
+
+        const unlistenFinished = listen<ScanFinished>("scan-finished", (e) => {
+            setJobId(null)
+            setProgress(null)
+
+            if (e.payload.error) {
+                setError(e.payload.error)
+            }
+        });
+
+        return () => {
+            unlistenProgress.then((f) => f());
+            unlistenFinished.then((f) => f());
+        };
+    }, []);
 
     const handleClick = async () => {
         const selected = await open({
@@ -19,7 +44,21 @@ export function SettingsView() {
         if(selected) {
             console.log(selected)
             try {
-                await invoke("read_directory", {path: selected})
+                setError("")
+
+                const overlap = await invoke<FolderOverlap | null>("get_folder_overlap", { path: selected })
+                if (overlap) {
+                    const message = overlap.kind === "inside"
+                        ? `${selected} is already part of ${overlap.path}, rescan just this folder?`
+                        : `${selected} contains ${overlap.folders.map(([, path]) => path).join(", ")}, merge them into one folder?`
+
+                    if (!window.confirm(message)) {
+                        return
+                    }
+                }
+
+                const id = await invoke<string>("read_directory", { path: selected, merge: overlap !== null })
+                setJobId(id)
             }
             catch(error) {
                 setError(String(error))
@@ -28,11 +67,37 @@ export function SettingsView() {
         
     }
 
+    //rescans started from the directory table only show up through progress events
+    const runningJob = jobId ?? progress?.job_id
+
+    const handleCancel = async () => {
+        if (runningJob) {
+            await invoke("cancel_scan", { id: runningJob })
+        }
+    }
+
     return (
         <div>
+            <ProfileSettings />
+
+            <Separator className="mb-3"/>
+
+            <SortSettings />
+
+            <Separator className="mb-3"/>
+
             <Button 
             className="border-muted-foreground border-2 hover:bg-muted-foregrounds mb-3" 
             onClick={handleClick}>scan directory</Button>
+
+            {runningJob && (
+                <div className="mb-3 text-sm text-muted-foreground">
+                    <span>
+                        scanning {progress?.current_path ?? "..."} ({progress?.dirs_visited ?? 0} folders, {(progress?.files_added ?? 0) + (progress?.files_updated ?? 0)} files, {progress?.files_failed ?? 0} failed)
+                    </span>
+                    <Button variant="ghost" className="ml-2" onClick={handleCancel}>cancel</Button>
+                </div>
+            )}
             
             {error && (
                 <div
@@ -1,68 +1,207 @@
 import { invoke } from "@tauri-apps/api/core"
-import { useEffect, useState } from "react"
-import { ImageDisplay } from "@/components/ImageDisplay"
+import { listen } from "@tauri-apps/api/event"
+import { useCallback, useEffect, useRef, useState } from "react"
+import { CoverDisplay } from "@/components/ImageDisplay"
+import { Input } from "@/components/ui/input"
 
-import type { Song, Image } from "@/types"
+import type { GenreCount, Song, SongFilter, SongPage, SongSort, SongSortKey } from "@/types"
 
+// each row is the 100px cover plus the gap below it
+const ROW_HEIGHT = 108
+const PAGE_SIZE = 100
+// rows drawn above and below what is on screen so fast scrolling doesnt show blanks
+const OVERSCAN = 10
 
-type CoversMap = Record<string, Image>;
+const SORT_KEYS: { key: SongSortKey, label: string }[] = [
+    { key: "title", label: "Title" },
+    { key: "artist", label: "Artist" },
+    { key: "album", label: "Album" },
+    { key: "duration", label: "Length" },
+    { key: "year", label: "Year" },
+]
 
 export default function SongsDisplay() {
-    const [songs, setSongs] = useState<Song[]>([])
-    const [nowPlaying, setNowPlaying] = useState<Song>()
-    const [covers, setCovers] = useState<CoversMap>({});
+    const [total, setTotal] = useState(0)
+    // loaded pages by page number, the rest of the library stays in the database
+    const [pages, setPages] = useState<Record<number, Song[]>>({})
+    const [sort, setSort] = useState<SongSort>({ key: "title", descending: false })
+    const [text, setText] = useState("")
+    const [filter, setFilter] = useState<SongFilter>({})
+    const [genres, setGenres] = useState<GenreCount[]>([])
+    const [scrollTop, setScrollTop] = useState(0)
+    const [viewHeight, setViewHeight] = useState(0)
 
+    const listRef = useRef<HTMLDivElement>(null)
+    const requested = useRef(new Set<number>())
+    // bumped whenever the sort or filter changes so late pages from the old query are dropped
+    const generation = useRef(0)
+
+    const loadPage = useCallback(async (page: number) => {
+        if (requested.current.has(page)) return
+        requested.current.add(page)
+
+        const gen = generation.current
+        try {
+            const result = await invoke<SongPage>("get_songs", {
+                offset: page * PAGE_SIZE,
+                limit: PAGE_SIZE,
+                sort,
+                filter,
+            })
+            if (gen !== generation.current) return
+
+            setTotal(result.total)
+            setPages((p) => ({ ...p, [page]: result.songs }))
+        }
+        catch (error) {
+            console.log(String(error))
+            requested.current.delete(page)
+        }
+    }, [sort, filter])
+
+    const reset = useCallback(() => {
+        generation.current += 1
+        requested.current = new Set()
+        setPages({})
+    }, [])
+
+    // start over from the first page when the query changes
+    useEffect(() => {
+        reset()
+        listRef.current?.scrollTo({ top: 0 })
+        setScrollTop(0)
+    }, [sort, filter, reset])
+
+    // wait for typing to stop before searching
     useEffect(() => {
-        const load_songs = async () => {
-            console.log("getting songs")
-            const songs = await invoke<Song[]>("get_songs");
+        const timeout = setTimeout(() => {
+            const trimmed = text.trim()
+            setFilter((f) => ({ ...f, text: trimmed === "" ? undefined : trimmed }))
+        }, 250)
+
+        return () => clearTimeout(timeout)
+    }, [text])
+
+    const loadGenres = useCallback(async () => {
+        try {
+            setGenres(await invoke<GenreCount[]>("get_genres"))
+        }
+        catch (error) {
+            console.log(String(error))
+        }
+    }, [])
 
-            setSongs(songs)
+    useEffect(() => { loadGenres() }, [loadGenres])
 
-            const covers = await invoke<CoversMap>("get_covers");
-            setCovers(covers)
+    // a finished scan or a batch of file changes from the watcher can change any page,
+    // so drop them and load what is on screen again
+    useEffect(() => {
+        const refresh = () => {
+            reset()
+            loadGenres()
         }
-        
+        const unlisteners = [listen("scan-finished", refresh), listen("library-changed", refresh)]
+        return () => { unlisteners.forEach((unlisten) => unlisten.then((f) => f())) }
+    }, [reset, loadGenres])
+
+    useEffect(() => {
+        const el = listRef.current
+        if (!el) return
 
-        load_songs()
+        const observer = new ResizeObserver(() => setViewHeight(el.clientHeight))
+        observer.observe(el)
+        setViewHeight(el.clientHeight)
 
+        return () => observer.disconnect()
     }, [])
 
+    const first = Math.max(0, Math.floor(scrollTop / ROW_HEIGHT) - OVERSCAN)
+    const last = Math.min(
+        Math.max(total, PAGE_SIZE) - 1,
+        Math.ceil((scrollTop + viewHeight) / ROW_HEIGHT) + OVERSCAN,
+    )
+
+    useEffect(() => {
+        for (let page = Math.floor(first / PAGE_SIZE); page <= Math.floor(last / PAGE_SIZE); page++) {
+            loadPage(page)
+        }
+    }, [first, last, pages, loadPage])
+
     const playSong = async (id: string) => {
         try {
             await invoke("play_song", { id })
-            
+
         }
         catch (error) {
             console.log(String(error))
         }
     }
-        
+
+    const rows: { index: number, song: Song }[] = []
+    for (let index = first; index <= last && index < total; index++) {
+        const song = pages[Math.floor(index / PAGE_SIZE)]?.[index % PAGE_SIZE]
+        if (song) rows.push({ index, song })
+    }
+
     return (
-        <div className="flex flex-col w-full">
-            <div className="flex flex-col gap-2">
-                {songs.map((song) => (
-                    <div onClick={() => playSong(song.id)} className="flex flex-row bg-zinc-900 rounded-lg gap-2">
-                        {/* <div className="w-[100px] h-[100px] bg-zinc-700 flex items-center justify-center">
-                            <span className="text-3xl font-bold text-zinc-400">
-                                {song.title.charAt(0)}
-                            </span>
-                        </div> */}
-                        <ImageDisplay
-                        image={
-                            covers[song.album[0]] ?? undefined
-                        }
-                        className="w-[100px] h-[100px]"
-                        />
-                        <div className="flex flex-col text-white">
-                            <p>{song.title}</p>
-                            <p>{song.artist[1]}</p>
-                            <p>{song.album[1]}</p>
-                        </div>
-                    </div>
+        <div className="flex flex-col w-full h-full gap-3">
+            <div className="flex flex-row gap-2 items-center">
+                <Input
+                    placeholder="Filter songs"
+                    value={text}
+                    onChange={(e) => setText(e.target.value)}
+                    className="max-w-xs text-white"
+                />
+                <select
+                    value={filter.genre ?? ""}
+                    onChange={(e) => setFilter((f) => ({ ...f, genre: e.target.value === "" ? undefined : e.target.value }))}
+                    className="px-2 py-1 rounded-md text-sm bg-zinc-900 text-white"
+                >
+                    <option value="">All genres</option>
+                    {genres.map((g) => <option key={g.name} value={g.name}>{g.name} ({g.songs})</option>)}
+                </select>
+                {SORT_KEYS.map(({ key, label }) => (
+                    <button
+                        key={key}
+                        onClick={() => setSort((s) => ({ key, descending: s.key === key ? !s.descending : false }))}
+                        className={`px-3 py-1 rounded-md text-sm ${sort.key === key ? "bg-zinc-700 text-white" : "bg-zinc-900 text-zinc-400"}`}
+                    >
+                        {label}{sort.key === key ? (sort.descending ? " ↓" : " ↑") : ""}
+                    </button>
                 ))}
+                <span className="text-sm text-zinc-400">{total} songs</span>
             </div>
-            
+
+            <div
+                ref={listRef}
+                onScroll={(e) => setScrollTop(e.currentTarget.scrollTop)}
+                className="flex-1 overflow-y-auto"
+            >
+                <div className="relative" style={{ height: total * ROW_HEIGHT }}>
+                    {rows.map(({ index, song }) => (
+                        <div
+                            key={song.id}
+                            onClick={() => playSong(song.id)}
+                            className="absolute left-0 right-0 flex flex-row bg-zinc-900 rounded-lg gap-2"
+                            style={{ top: index * ROW_HEIGHT, height: ROW_HEIGHT - 8 }}
+                        >
+                            <CoverDisplay
+                            albumId={song.album[0]}
+                            hash={song.cover}
+                            size={100}
+                            className="w-[100px] h-[100px]"
+                            />
+                            <div className="flex flex-col text-white">
+                                <p>{song.title}</p>
+                                <p>{song.artist[1]}</p>
+                                <p>{song.album[1]}{song.year ? ` · ${song.year}` : ""}</p>
+                                {song.genres.length > 0 && <p className="text-sm text-zinc-400">{song.genres.join(", ")}</p>}
+                            </div>
+                        </div>
+                    ))}
+                </div>
+            </div>
+
         </div>
     )
-}
\ No newline at end of file
+}
@@ -18,12 +18,48 @@ import {
 
 import { useState, useEffect } from "react"
 import { invoke } from "@tauri-apps/api/core"
+import { listen } from "@tauri-apps/api/event"
+import type { FileError, ScanReport } from "@/types"
+
+function describeError(error: FileError["error"]) {
+    switch (error.kind) {
+        case "permission_denied": return "permission denied"
+        case "zero_duration": return "audio has no length"
+        default: return `${error.kind.replace("_", " ")}: ${error.message}`
+    }
+}
+
+function describeReport(report: ScanReport) {
+    const changes = `${report.files_added} added, ${report.files_updated} updated, ${report.files_removed} removed`
+
+    if (report.files_failed) {
+        return `${changes}, ${report.files_failed} files could not be imported`
+    }
+
+    return changes
+}
 
 export function DirectoryTable() {
     const [dirs, setDirs] = useState<{ id: number; path: string }[]>([])
+    const [reports, setReports] = useState<Record<number, ScanReport | null>>({})
+    const [expanded, setExpanded] = useState<number | null>(null)
+
+    const handleRescan = async (id: number) => {
+        try {
+            await invoke("rescan_directory", { id })
+        }
+        catch (error) {
+            console.error(error)
+        }
+    }
 
     const handleDelete = async (id: number) => {
-        await invoke("delete_directory", { id }) 
+        try {
+            await invoke("delete_directory", { id })
+        }
+        catch (error) {
+            console.error(error)
+        }
         loadDirs();
     }
 
@@ -36,10 +72,20 @@ export function DirectoryTable() {
 
         setDirs(sortedDirs)
 
+        const loaded: Record<number, ScanReport | null> = {}
+        for (const dir of sortedDirs) {
+            loaded[dir.id] = await invoke<ScanReport | null>("get_scan_report", { folderId: dir.id })
+        }
+        setReports(loaded)
     }
 
     useEffect(() => {
         loadDirs()
+
+        const unlisten = listen("scan-finished", () => loadDirs())
+        return () => {
+            unlisten.then((f) => f())
+        }
     }, [])
 
     return (
@@ -49,18 +95,32 @@ export function DirectoryTable() {
                 <TableHeader>
                     <TableRow>
                         <TableHead className="font-bold text-white">path</TableHead>
+                        <TableHead className="font-bold text-white">last scan</TableHead>
                     </TableRow>
                 </TableHeader>
                 <TableBody>
                 {dirs.map((dir) => (
                     <ContextMenu key={dir.id}>
                         <ContextMenuTrigger asChild>
-                            <TableRow>
-                                <TableCell className="font-medium text-white">{dir.path}</TableCell>
+                            <TableRow onClick={() => setExpanded(expanded === dir.id ? null : dir.id)}>
+                                <TableCell className="font-medium text-white">
+                                    {dir.path}
+                                    {expanded === dir.id && reports[dir.id]?.errors.map((e) => (
+                                        <div key={e.path} className="text-xs text-muted-foreground">
+                                            {e.path}: {describeError(e.error)}
+                                        </div>
+                                    ))}
+                                </TableCell>
+                                <TableCell className={reports[dir.id]?.files_failed ? "text-red-400" : "text-muted-foreground"}>
+                                    {reports[dir.id] && describeReport(reports[dir.id]!)}
+                                </TableCell>
                             </TableRow>
                         </ContextMenuTrigger>
 
                         <ContextMenuContent className="w-52">
+                            <ContextMenuItem onSelect={() => handleRescan(dir.id)}>
+                                rescan
+                            </ContextMenuItem>
                             <ContextMenuItem className="text-red-500" onSelect={(e) => handleDelete(dir.id)}>
                                 delete
                             </ContextMenuItem>
@@ -0,0 +1,84 @@
+import { useEffect, useState } from "react"
+import { invoke } from "@tauri-apps/api/core"
+import { open } from "@tauri-apps/plugin-dialog"
+import { Button } from "@/components/ui/button"
+import { Input } from "@/components/ui/input"
+import type { ProfilesInfo } from "@/types"
+
+//switching profile or data directory restarts the app, so neither call comes back on success
+export function ProfileSettings() {
+    const [info, setInfo] = useState<ProfilesInfo | null>(null)
+    const [newProfile, setNewProfile] = useState("")
+    const [error, setError] = useState("")
+
+    useEffect(() => {
+        invoke<ProfilesInfo>("get_profiles").then(setInfo)
+    }, [])
+
+    const switchProfile = async (name: string) => {
+        try {
+            setError("")
+            await invoke("switch_profile", { name })
+        }
+        catch (error) {
+            setError(String(error))
+        }
+    }
+
+    const changeDataDir = async (reset: boolean) => {
+        const selected = reset ? null : await open({ directory: true, multiple: false })
+        if (!reset && !selected) {
+            return
+        }
+
+        try {
+            setError("")
+            await invoke("set_data_dir", { path: selected })
+        }
+        catch (error) {
+            setError(String(error))
+        }
+    }
+
+    if (!info) {
+        return null
+    }
+
+    return (
+        <div className="mb-3 text-sm">
+            <div className="mb-2">
+                library profile
+                {info.profiles.map((name) => (
+                    <Button
+                        key={name}
+                        variant={name === info.current ? "secondary" : "ghost"}
+                        className="ml-2"
+                        disabled={name === info.current}
+                        onClick={() => switchProfile(name)}>{name}</Button>
+                ))}
+            </div>
+
+            <div className="flex mb-2 gap-2">
+                <Input
+                    className="w-60"
+                    placeholder="new profile"
+                    value={newProfile}
+                    onChange={(e) => setNewProfile(e.target.value)} />
+                <Button variant="ghost" disabled={!newProfile.trim()} onClick={() => switchProfile(newProfile.trim())}>create and switch</Button>
+            </div>
+
+            <div className="text-muted-foreground">
+                stored in {info.data_dir}
+                <Button variant="ghost" className="ml-2" onClick={() => changeDataDir(false)}>change</Button>
+                <Button variant="ghost" onClick={() => changeDataDir(true)}>reset</Button>
+            </div>
+
+            {error && (
+                <div
+                    className="mt-4 p-3 rounded-md text-sm bg-red-900/30 text-red-400">
+                    {error}
+                </div>
+            )}
+        </div>
+    )
+}
@@ -0,0 +1,89 @@
+import { useEffect, useState } from "react"
+import { invoke } from "@tauri-apps/api/core"
+import { Button } from "@/components/ui/button"
+import { Input } from "@/components/ui/input"
+import type { SortSettings as SortSettingsType } from "@/types"
+
+//has to match LANGUAGE_ARTICLES in core/sort_key.rs
+const LANGUAGES: [string, string][] = [
+    ["en", "english"],
+    ["de", "german"],
+    ["fr", "french"],
+    ["es", "spanish"],
+    ["it", "italian"],
+    ["nl", "dutch"],
+    ["pt", "portuguese"],
+]
+
+//which leading articles are skipped when sorting, saving resorts the whole library
+export function SortSettings() {
+    const [settings, setSettings] = useState<SortSettingsType | null>(null)
+    const [extra, setExtra] = useState("")
+    const [error, setError] = useState("")
+
+    useEffect(() => {
+        invoke<SortSettingsType>("get_sort_settings").then((s) => {
+            setSettings(s)
+            setExtra(s.extra_articles.join(", "))
+        })
+    }, [])
+
+    const save = async (next: SortSettingsType) => {
+        try {
+            setError("")
+            await invoke("set_sort_settings", { settings: next })
+            setSettings(next)
+        }
+        catch (error) {
+            setError(String(error))
+        }
+    }
+
+    if (!settings) {
+        return null
+    }
+
+    const toggle = (code: string) => {
+        const languages = settings.languages.includes(code)
+            ? settings.languages.filter((l) => l !== code)
+            : [...settings.languages, code]
+
+        save({ ...settings, languages })
+    }
+
+    const saveExtra = () => {
+        const extra_articles = extra.split(",").map((a) => a.trim()).filter((a) => a !== "")
+        save({ ...settings, extra_articles })
+    }
+
+    return (
+        <div className="mb-3 text-sm">
+            <div className="mb-2">
+                ignore articles in
+                {LANGUAGES.map(([code, name]) => (
+                    <Button
+                        key={code}
+                        variant={settings.languages.includes(code) ? "secondary" : "ghost"}
+                        className="ml-2"
+                        onClick={() => toggle(code)}>{name}</Button>
+                ))}
+            </div>
+
+            <div className="flex gap-2">
+                <Input
+                    className="w-60"
+                    placeholder="more articles, comma separated"
+                    value={extra}
+                    onChange={(e) => setExtra(e.target.value)} />
+                <Button variant="ghost" onClick={saveExtra}>save</Button>
+            </div>
+
+            {error && (
+                <div
+                    className="mt-4 p-3 rounded-md text-sm bg-red-900/30 text-red-400">
+                    {error}
+                </div>
+            )}
+        </div>
+    )
+}
@@ -6,20 +6,159 @@ export interface Song {
     features?: [string | null, string][]
     track_num: number
     disc_num: number
-    cover?: Image
+    // hash of the album cover, drawn with CoverDisplay
+    cover?: string
     path: string
     duration: number
+    year?: number
+    genres: string[]
+    composer?: string
+    conductor?: string
+    bpm?: number
+    comment?: string
+}
+
+// leading articles skipped when sorting names
+export interface SortSettings {
+    // language codes whose articles are skipped
+    languages: string[]
+    extra_articles: string[]
+}
+
+export type SongSortKey = "title" | "artist" | "album" | "duration" | "year"
+
+export interface SongSort {
+    key: SongSortKey
+    descending: boolean
+}
+
+// every field that is set has to match
+export interface SongFilter {
+    artist?: string
+    album?: string
+    folder?: number
+    text?: string
+    // whole names, case doesnt matter
+    genre?: string
+    year?: number
+    composer?: string
+    conductor?: string
+}
+
+export interface GenreCount {
+    name: string
+    songs: number
+}
+
+export interface SongPage {
+    // songs matching the filter across every page
+    total: number
+    offset: number
+    songs: Song[]
+}
+
+export interface Artist {
+    id: string
+    name: string
+    // from the file tags, only used for ordering, name is what gets shown
+    sort_name?: string
+}
+
+export type TopHit =
+    | { kind: "song", item: Song }
+    | { kind: "album", item: Album }
+    | { kind: "artist", item: Artist }
+
+// each group is best match first
+export interface SearchResults {
+    top_hit?: TopHit
+    songs: Song[]
+    albums: Album[]
+    artists: Artist[]
 }
 
 export interface Album {
     id: string
     title: string
+    sort_name?: string
+    year?: number
+    // for reissues and remasters
+    original_year?: number
+    genres: string[]
+    track_total?: number
+    disc_total?: number
+    label?: string
     artists: [string | null, string][],
-    cover?: Image
+    // hash of the cover, drawn from cover://localhost/<album-id>?size=<px>&v=<hash> built by coverUrl
+    cover?: string
+    cover_source?: CoverSource
+    palette?: Palette
     songs: string[]
 }
 
+// colours picked from the cover at scan time, all #rrggbb
+export interface Palette {
+    dominant: string
+    vibrant: string
+    muted: string
+    // black or white, whichever reads better on dominant
+    text: string
+}
+
+export type CoverSource =
+    | { kind: "embedded" }
+    | { kind: "sidecar", path: string }
+
 export interface Image {
     data: number[]
     extension: string
 }
+
+export interface ScanReport {
+    dirs_visited: number
+    files_added: number
+    files_updated: number
+    files_moved: number
+    files_unchanged: number
+    files_removed: number
+    files_skipped: number
+    files_failed: number
+    cancelled: boolean
+    errors: FileError[]
+}
+
+export type ScanError =
+    | { kind: "unreadable", message: string }
+    | { kind: "permission_denied" }
+    | { kind: "unsupported_format", message: string }
+    | { kind: "corrupt_tag", message: string }
+    | { kind: "corrupt_audio", message: string }
+    | { kind: "zero_duration" }
+    | { kind: "db_insert", message: string }
+
+export interface FileError {
+    path: string
+    error: ScanError
+}
+
+export interface ScanProgress extends Omit<ScanReport, "errors"> {
+    job_id: string
+    current_path: string
+}
+
+export interface ScanFinished {
+    job_id: string
+    folder: string
+    report?: ScanReport
+    error?: string
+}
+
+export type FolderOverlap =
+    | { kind: "inside", id: number, path: string }
+    | { kind: "contains", folders: [number, string][] }
+
+export interface ProfilesInfo {
+    current: string
+    data_dir: string
+    profiles: string[]
+}